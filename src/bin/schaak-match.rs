@@ -0,0 +1,37 @@
+use schaak::match_runner::{run_match, MatchConfig, MATCH_USAGE};
+use schaak::player::set_verbose;
+
+use std::fs::File;
+use std::io::Write;
+
+// plays a series of games between two players without a window, writing them to a PGN file
+fn main() -> Result<(), String> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.iter().any(|a| a == "--help") {
+        println!("usage: schaak-match [options]\n\n{MATCH_USAGE}");
+        return Ok(());
+    }
+    let match_config =
+        MatchConfig::from_args(&args).map_err(|e| format!("{e} (see schaak-match --help)"))?;
+
+    let path = &match_config.pgn_path;
+    let mut pgn = File::create(path).map_err(|e| format!("could not create {path}: {e}"))?;
+    // the per-tick logging of every player would drown out everything else
+    set_verbose(false);
+
+    let summary = run_match(&match_config, |round, game| {
+        println!(
+            "game {round}: {} - {} {}",
+            game.tag("White").unwrap_or("?"),
+            game.tag("Black").unwrap_or("?"),
+            game.tag("Result").unwrap_or("*")
+        );
+        // written as they finish, so that an interrupted match still leaves its games behind
+        writeln!(pgn, "{game}").expect("could not write the game record");
+    })
+    .map_err(|e| e.to_string())?;
+
+    println!();
+    println!("{summary}");
+    Ok(())
+}
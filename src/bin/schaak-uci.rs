@@ -0,0 +1,11 @@
+// the engine as a UCI program, for use with chess GUIs and tournament managers
+use schaak::{
+    player::{set_verbose, SearchPlayer},
+    uci,
+};
+
+fn main() {
+    // GUIs show whatever ends up on stderr, and the per-tick logging is of no use to them
+    set_verbose(false);
+    uci::run::<SearchPlayer, _, _>("schaak", std::io::stdin().lock(), std::io::stdout());
+}
@@ -0,0 +1,134 @@
+use crate::chess_move::{KING_MOVES_RAW, KNIGHT_MOVES_RAW, QUEEN_OFFSETS};
+
+// one bit per square; bit x + 8 * y is set for square (x, y), so a1 is the lowest bit and h8 the highest
+pub type Bitboard = u64;
+
+pub fn square_index(coord: (u8, u8)) -> usize {
+    (coord.0 + 8 * coord.1) as usize
+}
+
+pub fn index_coord(index: usize) -> (u8, u8) {
+    ((index % 8) as u8, (index / 8) as u8)
+}
+
+pub fn bit(coord: (u8, u8)) -> Bitboard {
+    1 << square_index(coord)
+}
+
+// every square on the given file
+pub fn file_mask(x: u8) -> Bitboard {
+    0x0101_0101_0101_0101 << x
+}
+
+// every square on the given rank
+pub fn rank_mask(y: u8) -> Bitboard {
+    0xFF << (8 * y)
+}
+
+// iterates over the coordinates of all set bits, lowest first
+pub struct BitboardSquares(Bitboard);
+
+impl Iterator for BitboardSquares {
+    type Item = (u8, u8);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.0 == 0 {
+            return None;
+        }
+        let index = self.0.trailing_zeros() as usize;
+        self.0 &= self.0 - 1;
+        Some(index_coord(index))
+    }
+}
+
+pub fn squares_of(bitboard: Bitboard) -> BitboardSquares {
+    BitboardSquares(bitboard)
+}
+
+// for pieces that jump a fixed offset: every square reachable from each starting square
+const fn leaper_table(offsets: &[(i8, i8)]) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut index = 0;
+    while index < 64 {
+        let (x, y) = ((index % 8) as i8, (index / 8) as i8);
+        let mut i = 0;
+        while i < offsets.len() {
+            let (tx, ty) = (x + offsets[i].0, y + offsets[i].1);
+            if tx >= 0 && tx < 8 && ty >= 0 && ty < 8 {
+                table[index] |= 1 << (tx + 8 * ty);
+            }
+            i += 1;
+        }
+        index += 1;
+    }
+    table
+}
+
+// every square from each starting square up to the edge of the board in a single direction
+const fn ray_table(direction: (i8, i8)) -> [Bitboard; 64] {
+    let mut table = [0; 64];
+    let mut index = 0;
+    while index < 64 {
+        let (mut x, mut y) = (
+            (index % 8) as i8 + direction.0,
+            (index / 8) as i8 + direction.1,
+        );
+        while x >= 0 && x < 8 && y >= 0 && y < 8 {
+            table[index] |= 1 << (x + 8 * y);
+            x += direction.0;
+            y += direction.1;
+        }
+        index += 1;
+    }
+    table
+}
+
+pub const KNIGHT_ATTACKS: [Bitboard; 64] = leaper_table(&KNIGHT_MOVES_RAW);
+pub const KING_ATTACKS: [Bitboard; 64] = leaper_table(&KING_MOVES_RAW);
+// indexed by ChessColour::index; pawns only attack diagonally forwards
+pub const PAWN_ATTACKS: [[Bitboard; 64]; 2] = [
+    leaper_table(&[(-1, 1), (1, 1)]),
+    leaper_table(&[(-1, -1), (1, -1)]),
+];
+
+// one table per entry of QUEEN_OFFSETS: the first four are diagonal, the last four straight
+const RAYS: [[Bitboard; 64]; 8] = {
+    let mut rays = [[0; 64]; 8];
+    let mut direction = 0;
+    while direction < 8 {
+        rays[direction] = ray_table(QUEEN_OFFSETS[direction]);
+        direction += 1;
+    }
+    rays
+};
+
+// the ray is cut off after the first piece it hits, which is itself still attacked.
+// for directions towards higher bits that piece is the lowest blocker, otherwise the highest
+fn ray_attacks(index: usize, occupancy: Bitboard, direction: usize) -> Bitboard {
+    let ray = RAYS[direction][index];
+    let blockers = ray & occupancy;
+    if blockers == 0 {
+        return ray;
+    }
+
+    let (dx, dy) = QUEEN_OFFSETS[direction];
+    let first_blocker = if dx + 8 * dy > 0 {
+        blockers.trailing_zeros()
+    } else {
+        63 - blockers.leading_zeros()
+    };
+
+    ray ^ RAYS[direction][first_blocker as usize]
+}
+
+pub fn bishop_attacks(index: usize, occupancy: Bitboard) -> Bitboard {
+    (0..4).fold(0, |acc, d| acc | ray_attacks(index, occupancy, d))
+}
+
+pub fn rook_attacks(index: usize, occupancy: Bitboard) -> Bitboard {
+    (4..8).fold(0, |acc, d| acc | ray_attacks(index, occupancy, d))
+}
+
+pub fn queen_attacks(index: usize, occupancy: Bitboard) -> Bitboard {
+    bishop_attacks(index, occupancy) | rook_attacks(index, occupancy)
+}
@@ -0,0 +1,116 @@
+use crate::{
+    piece::{PieceKind::*, *},
+    state::State,
+};
+
+pub const POSITIONS: u32 = 960;
+// the classical starting position
+pub const CLASSICAL_INDEX: u32 = 518;
+
+// the back rank of the start position with the given number, from the a to the h file,
+// using Scharnagl's numbering
+pub fn back_rank(index: u32) -> [PieceKind; 8] {
+    assert!(index < POSITIONS, "there are only 960 start positions");
+    let mut rank = [None; 8];
+    let mut n = index;
+
+    // the bishops go on squares of either colour, the light one first
+    rank[(2 * (n % 4) + 1) as usize] = Some(Bishop);
+    n /= 4;
+    rank[(2 * (n % 4)) as usize] = Some(Bishop);
+    n /= 4;
+
+    // everything else is counted among the squares that are still empty
+    let mut place = |kind: PieceKind, nth_empty: u32| {
+        let x = (0..8)
+            .filter(|x| rank[*x].is_none())
+            .nth(nth_empty as usize)
+            .unwrap();
+        rank[x] = Some(kind);
+    };
+    place(Queen, n % 6);
+    n /= 6;
+    // the ten ways to put two knights on five squares
+    let (first, second) = [
+        (0, 1),
+        (0, 2),
+        (0, 3),
+        (0, 4),
+        (1, 2),
+        (1, 3),
+        (1, 4),
+        (2, 3),
+        (2, 4),
+        (3, 4),
+    ][n as usize];
+    // the second knight is counted after the first has been placed
+    place(Knight, first);
+    place(Knight, second - 1);
+    // the king always ends up between the rooks
+    place(Rook, 0);
+    place(King, 0);
+    place(Rook, 0);
+
+    rank.map(Option::unwrap)
+}
+
+pub fn start_fen(index: u32) -> String {
+    let white = back_rank(index)
+        .iter()
+        .map(|kind| {
+            Piece {
+                kind: *kind,
+                colour: ChessColour::White,
+            }
+            .to_char()
+        })
+        .collect::<String>();
+    format!(
+        "{}/pppppppp/8/8/8/8/PPPPPPPP/{white} w KQkq - 0 1",
+        white.to_ascii_lowercase()
+    )
+}
+
+impl State {
+    // the start position with the given number, between 0 and 959
+    pub fn chess960(index: u32) -> Self {
+        let mut state = State::from_fen(&start_fen(index)).unwrap();
+        state.chess960 = true;
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_positions() {
+        assert_eq!(start_fen(CLASSICAL_INDEX), crate::fen::STARTING_FEN);
+        assert_eq!(
+            start_fen(0),
+            "bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w KQkq - 0 1"
+        );
+        assert_eq!(
+            start_fen(959),
+            "rkrnnqbb/pppppppp/8/8/8/8/PPPPPPPP/RKRNNQBB w KQkq - 0 1"
+        );
+
+        let mut ranks = (0..POSITIONS).map(back_rank).collect::<Vec<_>>();
+        ranks.sort_by_key(|rank| rank.map(|kind| kind.index()));
+        ranks.dedup();
+        assert_eq!(ranks.len(), 960);
+
+        let state = State::chess960(0);
+        assert!(state.chess960);
+        assert_eq!(state.castling_files, [[7, 5]; 2]);
+        assert_eq!(
+            state.to_shredder_fen(),
+            start_fen(0).replace("KQkq", "HFhf")
+        );
+        assert_eq!(
+            State::chess960(CLASSICAL_INDEX).to_fen(),
+            crate::fen::STARTING_FEN
+        );
+    }
+}
@@ -1,9 +1,8 @@
-use std::{
-    fmt,
-    sync::{Arc, Mutex},
-};
+use std::fmt;
 
-use crate::State;
+use crate::bitboard::Bitboard;
+use crate::piece::{ChessColour, Piece, PieceKind};
+use crate::state::CastlingRights;
 
 pub const KNIGHT_MOVES_RAW: [(i8, i8); 8] = [
     (1, 2),
@@ -26,8 +25,7 @@ pub const KING_MOVES_RAW: [(i8, i8); 8] = [
     (1, 1),
 ];
 
-pub const ROOK_OFFSETS: [(i8, i8); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
-pub const BISHOP_OFFSETS: [(i8, i8); 4] = [(-1, -1), (1, -1), (-1, 1), (1, 1)];
+// the first four directions are diagonal, the last four straight; bitboard::RAYS relies on this order
 pub const QUEEN_OFFSETS: [(i8, i8); 8] = [
     (-1, -1),
     (1, -1),
@@ -39,45 +37,61 @@ pub const QUEEN_OFFSETS: [(i8, i8); 8] = [
     (0, 1),
 ];
 
-#[derive(Clone)]
-pub struct ChessMove {
-    pub dst: (u8, u8),
-    /// returned boolean stands for if pieces were moved during the function execution
-    pub function: Arc<Mutex<dyn FnMut(&mut State) -> bool>>,
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum MoveKind {
+    Quiet,
+    Capture,
+    // pawn moving two squares forward, which opens it up to en passant
+    DoublePush,
+    EnPassant,
+    ShortCastle,
+    LongCastle,
+    Promotion {
+        promote_to: PieceKind,
+        capture: bool,
+    },
+    // a piece from the pocket put onto an empty square, in crazyhouse
+    Drop(PieceKind),
 }
 
-impl PartialEq for ChessMove {
-    fn eq(&self, other: &Self) -> bool {
-        self.dst.eq(&other.dst)
-    }
-}
-
-impl PartialEq<(u8, u8)> for ChessMove {
-    fn eq(&self, other: &(u8, u8)) -> bool {
-        self.dst.eq(other)
-    }
+// src and dst are board coordinates; for castling moves they are the king's squares,
+// and for drops both are the square the piece is dropped on
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct ChessMove {
+    pub src: (u8, u8),
+    pub dst: (u8, u8),
+    pub kind: MoveKind,
 }
 
 impl ChessMove {
-    pub fn dummy(dst: (u8, u8)) -> Self {
-        ChessMove {
-            dst,
-            function: Arc::new(Mutex::new(|_s: &mut State| false)),
-        }
+    pub fn new(src: (u8, u8), dst: (u8, u8), kind: MoveKind) -> Self {
+        ChessMove { src, dst, kind }
     }
-}
 
-// critically: chess moves need to be sent between threads
-unsafe impl Send for ChessMove {}
-
-#[derive(Clone, Copy)]
-pub struct PerformedMove {
-    src: (u8, u8),
-    dst: (u8, u8),
+    pub fn is_capture(&self) -> bool {
+        matches!(
+            self.kind,
+            MoveKind::Capture | MoveKind::EnPassant | MoveKind::Promotion { capture: true, .. }
+        )
+    }
 }
 
-impl fmt::Display for PerformedMove {
+// long algebraic notation as used by UCI, e.g. e2e4 or e7e8q. drops are written N@f3
+impl fmt::Display for ChessMove {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let MoveKind::Drop(kind) = self.kind {
+            let dropped = Piece {
+                kind,
+                colour: ChessColour::White,
+            };
+            return write!(
+                f,
+                "{}@{}{}",
+                dropped.to_char(),
+                (self.dst.0 + 97) as char,
+                (self.dst.1 + 49) as char
+            );
+        }
         write!(
             f,
             "{}{}{}{}",
@@ -85,20 +99,44 @@ impl fmt::Display for PerformedMove {
             (self.src.1 + 49) as char,
             (self.dst.0 + 97) as char,
             (self.dst.1 + 49) as char
-        )
+        )?;
+        if let MoveKind::Promotion { promote_to, .. } = self.kind {
+            let promotor = Piece {
+                kind: promote_to,
+                colour: ChessColour::Black,
+            };
+            write!(f, "{}", promotor.to_char())?;
+        }
+
+        Ok(())
     }
 }
 
-impl PerformedMove {
-    pub fn new(src: (u8, u8), dst: (u8, u8)) -> Self {
-        PerformedMove { src, dst }
+// a move as it was played, along with everything make_move overwrote that
+// unmake_move needs to restore
+#[derive(Clone, Copy, Debug)]
+pub struct PerformedMove {
+    pub chess_move: ChessMove,
+    pub captured: Option<Piece>,
+    pub castling_rights: CastlingRights,
+    pub en_passant: Option<(u8, u8)>,
+    pub halfmove_clock: u32,
+    pub checks_given: [u8; 2],
+    pub pockets: [[u8; 5]; 2],
+    pub promoted: Bitboard,
+    // what an atomic capture blew up, in the order of variant::blast_squares
+    pub exploded: [Option<Piece>; 9],
+    // of the position before the move, which also makes the history a list of earlier positions
+    pub hash: u64,
+}
+
+impl fmt::Display for PerformedMove {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.chess_move)
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub struct MoveInfo {
-    pub coord: (u8, u8),
     pub move_data: ChessMove,
 }
-
-unsafe impl Send for MoveInfo {}
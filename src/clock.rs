@@ -0,0 +1,363 @@
+use crate::{piece::ChessColour, player::SearchLimits};
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+// what a player gets back after each of their moves
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bonus {
+    None,
+    // added in full, however long the move took
+    Fischer(Duration),
+    // gives back the time the move took, up to this much
+    Bronstein(Duration),
+}
+
+// time for a number of moves, or for the rest of the game if moves is None
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Period {
+    pub moves: Option<u32>,
+    pub time: Duration,
+    pub bonus: Bonus,
+}
+
+// the same for both sides. once the moves of the last period have been made, its time is
+// added again for as many moves more, on top of whatever is left
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TimeControl {
+    // never empty
+    pub periods: Vec<Period>,
+}
+
+impl TimeControl {
+    // periods separated by colons, like PGN's TimeControl tag. each one is
+    // [<moves>/]<seconds>[+<increment>|d<delay>], where the times may have decimals:
+    // 300 is sudden death, 180+2 has an increment, 180d2 a delay,
+    // and 40/5400+30:1800+30 is 90 minutes for 40 moves and then 30 more, with an increment
+    pub fn parse(text: &str) -> Option<Self> {
+        let periods = text
+            .split(':')
+            .map(Period::parse)
+            .collect::<Option<Vec<_>>>()?;
+        Some(TimeControl { periods })
+    }
+}
+
+impl Period {
+    fn parse(text: &str) -> Option<Self> {
+        let (moves, rest) = match text.split_once('/') {
+            Some((moves, rest)) => (Some(moves.parse().ok().filter(|m| *m > 0)?), rest),
+            None => (None, text),
+        };
+        let (time, bonus) = if let Some((time, increment)) = rest.split_once('+') {
+            (time, Bonus::Fischer(parse_seconds(increment)?))
+        } else if let Some((time, delay)) = rest.split_once('d') {
+            (time, Bonus::Bronstein(parse_seconds(delay)?))
+        } else {
+            (rest, Bonus::None)
+        };
+        Some(Period {
+            moves,
+            time: parse_seconds(time)?,
+            bonus,
+        })
+    }
+}
+
+fn parse_seconds(text: &str) -> Option<Duration> {
+    Duration::try_from_secs_f64(text.parse().ok()?).ok()
+}
+
+// written the way TimeControl::parse reads it
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, period) in self.periods.iter().enumerate() {
+            if i > 0 {
+                write!(f, ":")?;
+            }
+            if let Some(moves) = period.moves {
+                write!(f, "{moves}/")?;
+            }
+            write!(f, "{}", period.time.as_secs_f64())?;
+            match period.bonus {
+                Bonus::None => {}
+                Bonus::Fischer(increment) => write!(f, "+{}", increment.as_secs_f64())?,
+                Bonus::Bronstein(delay) => write!(f, "d{}", delay.as_secs_f64())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// h:mm:ss, m:ss, or with tenths of a second once under ten seconds
+pub fn format_clock(time: Duration) -> String {
+    let secs = time.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else if secs >= 10 {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    } else {
+        format!("0:{:02}.{}", secs, time.subsec_millis() / 100)
+    }
+}
+
+// both sides' clocks. the caller passes in the time, so that everything happens at the same
+// moment for a whole frame, and so that tests don't have to wait
+#[derive(Clone, Debug)]
+pub struct Clock {
+    control: TimeControl,
+    // as of the last time the running clock was started; indexed by ChessColour::index
+    remaining: [Duration; 2],
+    // the period each side is in, and how many moves they've made in it
+    period: [usize; 2],
+    moves_in_period: [u32; 2],
+    // the side whose clock is running, and since when
+    running: Option<(ChessColour, Instant)>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        let time = control.periods[0].time;
+        Clock {
+            control,
+            remaining: [time; 2],
+            period: [0; 2],
+            moves_in_period: [0; 2],
+            running: None,
+        }
+    }
+
+    pub fn control(&self) -> &TimeControl {
+        &self.control
+    }
+
+    pub fn start(&mut self, col: ChessColour, now: Instant) {
+        self.stop(now);
+        self.running = Some((col, now));
+    }
+
+    // stops the running clock without ending the move, e.g. when the game is over
+    pub fn stop(&mut self, now: Instant) {
+        if let Some((col, _)) = self.running {
+            self.remaining[col.index()] = self.time_left(col, now);
+            self.running = None;
+        }
+    }
+
+    pub fn time_left(&self, col: ChessColour, now: Instant) -> Duration {
+        match self.running {
+            Some((running, since)) if running == col => {
+                self.remaining[col.index()].saturating_sub(now.saturating_duration_since(since))
+            }
+            _ => self.remaining[col.index()],
+        }
+    }
+
+    // the side whose time has run out, if any
+    pub fn flagged(&self, now: Instant) -> Option<ChessColour> {
+        self.running
+            .map(|(col, _)| col)
+            .filter(|col| self.time_left(*col, now).is_zero())
+    }
+
+    // ends the move of the side whose clock is running and starts the other one's.
+    // returns false if the move came too late, in which case both clocks are stopped
+    pub fn press(&mut self, now: Instant) -> bool {
+        let Some((col, since)) = self.running else {
+            return true;
+        };
+        let i = col.index();
+        let elapsed = now.saturating_duration_since(since);
+        if elapsed >= self.remaining[i] {
+            self.remaining[i] = Duration::ZERO;
+            self.running = None;
+            return false;
+        }
+
+        let period = self.control.periods[self.period[i]];
+        self.remaining[i] += match period.bonus {
+            Bonus::None => Duration::ZERO,
+            Bonus::Fischer(increment) => increment,
+            Bonus::Bronstein(delay) => elapsed.min(delay),
+        };
+        self.remaining[i] -= elapsed;
+
+        self.moves_in_period[i] += 1;
+        if Some(self.moves_in_period[i]) == period.moves {
+            self.period[i] = (self.period[i] + 1).min(self.control.periods.len() - 1);
+            self.moves_in_period[i] = 0;
+            self.remaining[i] += self.control.periods[self.period[i]].time;
+        }
+
+        self.running = Some((col.flip(), now));
+        true
+    }
+
+    // the given limits with the clocks filled in, instead of a fixed time per move
+    pub fn search_limits(&self, base: &SearchLimits, now: Instant) -> SearchLimits {
+        let mut limits = SearchLimits {
+            movetime: None,
+            ..base.clone()
+        };
+        for col in [ChessColour::White, ChessColour::Black] {
+            let i = col.index();
+            let period = self.control.periods[self.period[i]];
+            limits.time_left[i] = Some(self.time_left(col, now));
+            // a delay is only given back in full for moves that take at least that long,
+            // which is about what the search will spend anyway
+            limits.increment[i] = match period.bonus {
+                Bonus::None => None,
+                Bonus::Fischer(bonus) | Bonus::Bronstein(bonus) => Some(bonus),
+            };
+        }
+
+        let to_move = self.running.map_or(ChessColour::White, |(col, _)| col);
+        let i = to_move.index();
+        limits.moves_to_go = self.control.periods[self.period[i]]
+            .moves
+            .map(|moves| moves - self.moves_in_period[i]);
+        limits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(s: u64) -> Duration {
+        Duration::from_secs(s)
+    }
+
+    #[test]
+    fn time_controls() {
+        for text in ["300", "180+2", "180d2", "40/5400+30:1800+30", "60+0.5"] {
+            assert_eq!(TimeControl::parse(text).unwrap().to_string(), text);
+        }
+        assert_eq!(
+            TimeControl::parse("40/5400:1800").unwrap().periods[0],
+            Period {
+                moves: Some(40),
+                time: secs(5400),
+                bonus: Bonus::None
+            }
+        );
+        for text in ["", "-1", "5+x", "0/60", "60:"] {
+            assert_eq!(TimeControl::parse(text), None, "{text}");
+        }
+    }
+
+    #[test]
+    fn clock_formatting() {
+        assert_eq!(format_clock(secs(5400)), "1:30:00");
+        assert_eq!(format_clock(secs(299)), "4:59");
+        assert_eq!(format_clock(Duration::from_millis(9_870)), "0:09.8");
+    }
+
+    #[test]
+    fn fischer_and_bronstein() {
+        let start = Instant::now();
+
+        let mut clock = Clock::new(TimeControl::parse("60+2").unwrap());
+        clock.start(ChessColour::White, start);
+        assert_eq!(
+            clock.time_left(ChessColour::White, start + secs(5)),
+            secs(55)
+        );
+        assert!(clock.press(start + secs(5)));
+        assert_eq!(
+            clock.time_left(ChessColour::White, start + secs(9)),
+            secs(57)
+        );
+        assert_eq!(
+            clock.time_left(ChessColour::Black, start + secs(9)),
+            secs(56)
+        );
+
+        let mut clock = Clock::new(TimeControl::parse("60d3").unwrap());
+        clock.start(ChessColour::White, start);
+        clock.press(start + secs(2));
+        clock.press(start + secs(12));
+        assert_eq!(
+            clock.time_left(ChessColour::White, start + secs(12)),
+            secs(60)
+        );
+        assert_eq!(
+            clock.time_left(ChessColour::Black, start + secs(12)),
+            secs(53)
+        );
+    }
+
+    #[test]
+    fn periods() {
+        let start = Instant::now();
+        let mut clock = Clock::new(TimeControl::parse("2/10:5").unwrap());
+        clock.start(ChessColour::White, start);
+
+        let base = SearchLimits::default();
+        assert_eq!(clock.search_limits(&base, start).moves_to_go, Some(2));
+        // white's first move takes 4 seconds and the second 1, black's are instant
+        clock.press(start + secs(4));
+        clock.press(start + secs(4));
+        assert_eq!(
+            clock.search_limits(&base, start + secs(4)).moves_to_go,
+            Some(1)
+        );
+        clock.press(start + secs(5));
+        assert_eq!(
+            clock.time_left(ChessColour::White, start + secs(5)),
+            secs(10)
+        );
+        assert_eq!(
+            clock.search_limits(&base, start + secs(5)).time_left,
+            [Some(secs(10)), Some(secs(10))]
+        );
+    }
+
+    #[test]
+    fn past_the_last_period() {
+        let start = Instant::now();
+        let mut clock = Clock::new(TimeControl::parse("2/10:1/5").unwrap());
+        clock.start(ChessColour::White, start);
+        let base = SearchLimits::default();
+
+        // every move of white's takes a second, black's are instant
+        clock.press(start + secs(1));
+        clock.press(start + secs(1));
+        for (moves, left) in [(2, 13), (3, 17), (4, 21)] {
+            clock.press(start + secs(moves));
+            clock.press(start + secs(moves));
+            assert_eq!(
+                clock.time_left(ChessColour::White, start + secs(moves)),
+                secs(left)
+            );
+            assert_eq!(
+                clock.search_limits(&base, start + secs(moves)).moves_to_go,
+                Some(1)
+            );
+        }
+    }
+
+    #[test]
+    fn running_out() {
+        let start = Instant::now();
+        let mut clock = Clock::new(TimeControl::parse("10").unwrap());
+        clock.start(ChessColour::White, start);
+        assert_eq!(clock.flagged(start + secs(9)), None);
+        assert_eq!(clock.flagged(start + secs(10)), Some(ChessColour::White));
+        assert!(!clock.press(start + secs(11)));
+        assert_eq!(
+            clock.time_left(ChessColour::White, start + secs(20)),
+            Duration::ZERO
+        );
+
+        // a stopped clock keeps its time
+        let mut clock = Clock::new(TimeControl::parse("10").unwrap());
+        clock.start(ChessColour::Black, start);
+        clock.stop(start + secs(3));
+        assert_eq!(
+            clock.time_left(ChessColour::Black, start + secs(8)),
+            secs(7)
+        );
+        assert_eq!(clock.flagged(start + secs(20)), None);
+    }
+}
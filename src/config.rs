@@ -0,0 +1,654 @@
+use crate::{
+    chess960,
+    chess_move::MoveInfo,
+    clock::{Clock, TimeControl},
+    fen::FenError,
+    piece::ChessColour,
+    player::{HumanHandle, HumanPlayer, Player, RandomPlayer, SearchLimits, SearchPlayer},
+    state::State,
+    uci_engine::{UciEngineError, UciEnginePlayer},
+    variant::Variant,
+};
+
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+// who plays one side of the board
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PlayerConfig {
+    Human,
+    Random,
+    // None searches for a fixed time per move instead
+    Search { depth: Option<u32> },
+    // an engine speaking UCI, started with the given command line
+    Uci { command: String },
+}
+
+// written the way PlayerConfig::parse reads it
+impl fmt::Display for PlayerConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerConfig::Human => write!(f, "human"),
+            PlayerConfig::Random => write!(f, "random"),
+            PlayerConfig::Search { depth: None } => write!(f, "search"),
+            PlayerConfig::Search { depth: Some(depth) } => write!(f, "search:{depth}"),
+            PlayerConfig::Uci { command } => write!(f, "uci:{command}"),
+        }
+    }
+}
+
+impl PlayerConfig {
+    // human, random, search, search:<depth> or uci:<command>
+    pub fn parse(text: &str) -> Option<Self> {
+        let (kind, arg) = match text.split_once(':') {
+            Some((kind, arg)) => (kind, Some(arg)),
+            None => (text, None),
+        };
+        match (kind, arg) {
+            ("human", None) => Some(PlayerConfig::Human),
+            ("random", None) => Some(PlayerConfig::Random),
+            ("search", None) => Some(PlayerConfig::Search { depth: None }),
+            ("search", Some(depth)) => Some(PlayerConfig::Search {
+                depth: Some(depth.parse().ok().filter(|d| *d > 0)?),
+            }),
+            ("uci", Some(command)) if !command.trim().is_empty() => Some(PlayerConfig::Uci {
+                command: command.trim().to_string(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// which Chess960 start position to play from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chess960Start {
+    // between 0 and 959
+    Position(u32),
+    // picked when the game is set up, from the seed if there is one
+    Random,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    UnknownKey(String),
+    MissingValue(String),
+    InvalidValue(String, String),
+    InvalidFen(FenError),
+    // two options that can't be used together
+    Conflict(String, String),
+    Unreadable(String, String),
+    // the configured engine could not be started
+    Engine(UciEngineError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::UnknownKey(key) => write!(f, "unknown option {key}"),
+            ConfigError::MissingValue(key) => write!(f, "{key} needs a value"),
+            ConfigError::InvalidValue(key, value) => write!(f, "{value} is not a valid {key}"),
+            ConfigError::InvalidFen(e) => write!(f, "invalid fen: {e}"),
+            ConfigError::Conflict(a, b) => write!(f, "{a} can't be used with {b}"),
+            ConfigError::Unreadable(path, e) => write!(f, "could not read {path}: {e}"),
+            ConfigError::Engine(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+pub const USAGE: &str = "\
+options, which can also be put in a file as `<option> = <value>` lines:
+  --white <player>    human, random, search, search:<depth> or uci:<command> (default human)
+  --black <player>    the same (default search)
+  --variant <name>    standard, three-check, king of the hill, antichess, crazyhouse,
+                      atomic, horde or racing kings (default standard)
+  --fen <fen>         the starting position
+  --chess960 <n>      plays Chess960 from start position n, between 0 and 959, or a random one
+                      with --chess960 random. with --fen, only switches on Chess960 castling
+  --tc <time>         periods of [<moves>/]<seconds>[+<increment>|d<delay>], separated by
+                      colons, e.g. 300+2, 180d2 or 40/5400+30:1800+30
+  --seed <number>     makes random players and --chess960 random repeatable
+  --config <file>     reads options from the file first, the command line overrides them";
+
+// a player ticking away on a thread of its own, until this is dropped
+pub struct PlayerThread {
+    pub rx_from_player: Receiver<MoveInfo>,
+    // for human players: main's end, through which the clicks are passed on
+    pub human: Option<HumanHandle>,
+    stop: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl PlayerThread {
+    // whether the player has stopped ticking before it was asked to, e.g. because its engine
+    // failed or its thread panicked
+    pub fn has_stopped(&self) -> bool {
+        self.handle.is_finished()
+    }
+}
+
+impl Drop for PlayerThread {
+    // a player in the middle of thinking only notices once it's done
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// a player that has stopped while the game is still on has given up, whether or not it is their
+// move. the side to move is looked at first
+pub fn resign_stopped_players(state: &mut State, players: &[PlayerThread; 2]) {
+    if state.termination.is_some() {
+        return;
+    }
+    if let Some(col) = [state.turn, state.turn.flip()]
+        .into_iter()
+        .find(|col| players[col.index()].has_stopped())
+    {
+        state.resign(col);
+    }
+}
+
+// how a game is set up
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    // indexed by ChessColour::index
+    pub players: [PlayerConfig; 2],
+    pub variant: Variant,
+    pub fen: Option<String>,
+    pub chess960: Option<Chess960Start>,
+    pub time_control: Option<TimeControl>,
+    pub seed: Option<u64>,
+    // how often the players are ticked. None goes at a pace that can be followed in the GUI
+    pub tick_interval: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            players: [PlayerConfig::Human, PlayerConfig::Search { depth: None }],
+            variant: Variant::Standard,
+            fen: None,
+            chess960: None,
+            time_control: None,
+            seed: None,
+            tick_interval: None,
+        }
+    }
+}
+
+impl Config {
+    // --<key> <value> pairs, as listed in USAGE
+    pub fn from_args(args: &[String]) -> Result<Self, ConfigError> {
+        let mut config = Config::default();
+        config.apply_args(args)?;
+        Ok(config)
+    }
+
+    // like from_args, but on top of what is already set
+    pub fn apply_args(&mut self, args: &[String]) -> Result<(), ConfigError> {
+        let mut pairs = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let key = arg
+                .strip_prefix("--")
+                .ok_or_else(|| ConfigError::UnknownKey(arg.clone()))?;
+            let value = args
+                .next()
+                .ok_or_else(|| ConfigError::MissingValue(key.to_string()))?;
+            pairs.push((key, value.as_str()));
+        }
+
+        // the file goes first, wherever it is given, so that everything else overrides it
+        for (_, path) in pairs.iter().filter(|(key, _)| *key == "config") {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| ConfigError::Unreadable(path.to_string(), e.to_string()))?;
+            self.apply_file(&text)?;
+        }
+        pairs.retain(|(key, _)| *key != "config");
+        self.apply_pairs(&pairs)
+    }
+
+    // the variant goes first, as it decides what makes a valid FEN
+    fn apply_pairs(&mut self, pairs: &[(&str, &str)]) -> Result<(), ConfigError> {
+        let (variant, rest): (Vec<_>, Vec<_>) =
+            pairs.iter().partition(|(key, _)| *key == "variant");
+        for (key, value) in variant.into_iter().chain(rest) {
+            self.set(key, value)?;
+        }
+        Ok(())
+    }
+
+    // `<key> = <value>` lines, with # starting a comment
+    pub fn from_file(text: &str) -> Result<Self, ConfigError> {
+        let mut config = Config::default();
+        config.apply_file(text)?;
+        Ok(config)
+    }
+
+    fn apply_file(&mut self, text: &str) -> Result<(), ConfigError> {
+        let mut pairs = Vec::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| ConfigError::MissingValue(line.to_string()))?;
+            pairs.push((key.trim(), value.trim()));
+        }
+        self.apply_pairs(&pairs)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = || ConfigError::InvalidValue(key.to_string(), value.to_string());
+        match key {
+            "white" | "black" => {
+                let index = if key == "white" { 0 } else { 1 };
+                self.players[index] = PlayerConfig::parse(value).ok_or_else(invalid)?;
+            }
+            "variant" => {
+                self.variant = Variant::parse(value).ok_or_else(invalid)?;
+                self.check_chess960()?;
+                if let Some(fen) = &self.fen {
+                    State::from_fen_with_variant(fen, self.variant)
+                        .map_err(ConfigError::InvalidFen)?;
+                }
+            }
+            "fen" => {
+                State::from_fen_with_variant(value, self.variant)
+                    .map_err(ConfigError::InvalidFen)?;
+                self.fen = Some(value.to_string());
+            }
+            "chess960" => {
+                self.chess960 = Some(match value {
+                    "random" => Chess960Start::Random,
+                    _ => Chess960Start::Position(
+                        value
+                            .parse()
+                            .ok()
+                            .filter(|n| *n < chess960::POSITIONS)
+                            .ok_or_else(invalid)?,
+                    ),
+                });
+                self.check_chess960()?;
+            }
+            "tc" => self.time_control = Some(TimeControl::parse(value).ok_or_else(invalid)?),
+            "seed" => self.seed = Some(value.parse().map_err(|_| invalid())?),
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    // variants with a start position of their own, or without castling, can't be shuffled
+    fn check_chess960(&self) -> Result<(), ConfigError> {
+        match self.chess960 {
+            Some(_) if !self.variant.allows_chess960() => Err(ConfigError::Conflict(
+                String::from("chess960"),
+                self.variant.uci_name().to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // the number of the Chess960 start position. a random one is the same every time for the
+    // same seed, and different every time without one
+    pub fn chess960_position(&self) -> Option<u32> {
+        match self.chess960? {
+            Chess960Start::Position(index) => Some(index),
+            Chess960Start::Random => Some(match self.seed {
+                Some(seed) => SmallRng::seed_from_u64(seed).gen_range(0..chess960::POSITIONS),
+                None => rand::thread_rng().gen_range(0..chess960::POSITIONS),
+            }),
+        }
+    }
+
+    pub fn starting_position(&self) -> Result<State, ConfigError> {
+        let fen = match (&self.fen, self.chess960_position()) {
+            (Some(fen), _) => fen.clone(),
+            (None, Some(index)) => chess960::start_fen(index),
+            (None, None) => self.variant.start_fen().to_string(),
+        };
+        let mut state =
+            State::from_fen_with_variant(&fen, self.variant).map_err(ConfigError::InvalidFen)?;
+        state.chess960 |= self.chess960.is_some();
+        Ok(state)
+    }
+
+    // the limits for a search player on the given side, if it should use anything but the defaults.
+    // with a time control the clock fills in the rest
+    pub fn search_limits(&self, colour: ChessColour) -> Option<SearchLimits> {
+        let PlayerConfig::Search { depth: Some(depth) } = self.players[colour.index()] else {
+            return None;
+        };
+        Some(SearchLimits {
+            depth: Some(depth),
+            ..SearchLimits::default()
+        })
+    }
+
+    // both sides get their own seed, so that two random players don't mirror each other
+    pub fn seed_for(&self, colour: ChessColour) -> Option<u64> {
+        self.seed.map(|s| s.wrapping_add(colour.index() as u64))
+    }
+
+    // starts the player configured for the given side, playing from the given position
+    pub fn spawn_player(
+        &self,
+        colour: ChessColour,
+        clock: Option<&Arc<Mutex<Clock>>>,
+        rx_from_main: Receiver<MoveInfo>,
+        state: &State,
+    ) -> Result<PlayerThread, ConfigError> {
+        let ((stop, handle), rx_from_player, human) = match &self.players[colour.index()] {
+            PlayerConfig::Human => {
+                let (player, rx) = HumanPlayer::new(rx_from_main, state, colour);
+                let handle = player.handle();
+                (
+                    self.run(player, colour, clock, Duration::from_millis(10)),
+                    rx,
+                    Some(handle),
+                )
+            }
+            PlayerConfig::Random => {
+                let (player, rx) = RandomPlayer::new(rx_from_main, state, colour);
+                (
+                    self.run(player, colour, clock, Duration::from_millis(500)),
+                    rx,
+                    None,
+                )
+            }
+            PlayerConfig::Search { .. } => {
+                // the search itself takes a while, so there's no need to slow it down any further
+                let (player, rx) = SearchPlayer::new(rx_from_main, state, colour);
+                (
+                    self.run(player, colour, clock, Duration::from_millis(100)),
+                    rx,
+                    None,
+                )
+            }
+            PlayerConfig::Uci { command } => {
+                let (mut player, rx) = UciEnginePlayer::new(rx_from_main, state, colour);
+                player.start_engine(command).map_err(ConfigError::Engine)?;
+                (
+                    self.run(player, colour, clock, Duration::from_millis(100)),
+                    rx,
+                    None,
+                )
+            }
+        };
+
+        Ok(PlayerThread {
+            rx_from_player,
+            human,
+            stop,
+            handle,
+        })
+    }
+
+    // sets the player up as configured and ticks it on its own thread until the returned flag is
+    // set, or until the player fails
+    fn run<P: Player + 'static>(
+        &self,
+        mut player: P,
+        colour: ChessColour,
+        clock: Option<&Arc<Mutex<Clock>>>,
+        interval: Duration,
+    ) -> (Arc<AtomicBool>, JoinHandle<()>) {
+        if let Some(clock) = clock {
+            player.set_clock(clock.clone());
+        }
+        if let Some(seed) = self.seed_for(colour) {
+            player.set_seed(seed);
+        }
+        if let Some(limits) = self.search_limits(colour) {
+            player.set_limits(limits);
+        }
+
+        let interval = self.tick_interval.unwrap_or(interval);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let handle = thread::spawn(move || {
+            while !thread_stop.load(Ordering::Relaxed) {
+                player.tick();
+                // the player is dropped along with its end of the channel, so main sees it go
+                if let Some(failure) = player.failure() {
+                    log!("{colour:?} player gave up: {failure}");
+                    break;
+                }
+                thread::sleep(interval);
+            }
+        });
+        (stop, handle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn player_configs() {
+        assert_eq!(PlayerConfig::parse("human"), Some(PlayerConfig::Human));
+        assert_eq!(
+            PlayerConfig::parse("search:6"),
+            Some(PlayerConfig::Search { depth: Some(6) })
+        );
+        assert_eq!(
+            PlayerConfig::parse("uci:stockfish"),
+            Some(PlayerConfig::Uci {
+                command: String::from("stockfish")
+            })
+        );
+        assert_eq!(PlayerConfig::parse("search:0"), None);
+        assert_eq!(PlayerConfig::parse("uci"), None);
+        assert_eq!(PlayerConfig::parse("human:1"), None);
+    }
+
+    #[test]
+    fn command_line() {
+        let config = Config::from_args(&args("--white random --black search:4 --seed 7")).unwrap();
+        assert_eq!(
+            config.players,
+            [
+                PlayerConfig::Random,
+                PlayerConfig::Search { depth: Some(4) }
+            ]
+        );
+        assert_eq!(config.seed_for(ChessColour::Black), Some(8));
+        assert_eq!(
+            config.search_limits(ChessColour::Black).unwrap().depth,
+            Some(4)
+        );
+        assert!(config.search_limits(ChessColour::White).is_none());
+
+        assert_eq!(Config::from_args(&[]).unwrap(), Config::default());
+        assert_eq!(
+            Config::from_args(&args("--colour white")),
+            Err(ConfigError::UnknownKey(String::from("colour")))
+        );
+        assert_eq!(
+            Config::from_args(&args("--seed")),
+            Err(ConfigError::MissingValue(String::from("seed")))
+        );
+
+        // the fen has to be quoted, as it contains spaces
+        let fen = "4k3/8/8/8/8/8/8/4K2R w K - 0 1";
+        let config = Config::from_args(&[String::from("--fen"), String::from(fen)]).unwrap();
+        assert_eq!(config.starting_position().unwrap().to_fen(), fen);
+        assert!(!config.starting_position().unwrap().chess960);
+
+        // a random start position follows the seed, whichever comes first
+        let seeded = Config::from_args(&args("--chess960 random --seed 3")).unwrap();
+        let fen = seeded.starting_position().unwrap().to_fen();
+        assert_eq!(seeded.starting_position().unwrap().to_fen(), fen);
+        let config = Config::from_args(&args("--seed 3 --chess960 random")).unwrap();
+        assert_eq!(config.starting_position().unwrap().to_fen(), fen);
+        assert_eq!(
+            fen,
+            crate::chess960::start_fen(seeded.chess960_position().unwrap())
+        );
+
+        let config = Config::from_args(&args("--chess960 0")).unwrap();
+        assert_eq!(
+            config.starting_position().unwrap().to_fen(),
+            crate::chess960::start_fen(0)
+        );
+        assert!(config.starting_position().unwrap().chess960);
+        assert!(Config::from_args(&args("--chess960 random"))
+            .unwrap()
+            .chess960
+            .is_some());
+        assert_eq!(
+            Config::from_args(&args("--chess960 960")),
+            Err(ConfigError::InvalidValue(
+                String::from("chess960"),
+                String::from("960")
+            ))
+        );
+
+        // a three-check FEN only makes sense once the variant is known, whichever comes first
+        let three_check = [
+            "--fen",
+            "4k3/8/8/8/8/8/8/4K3 w - - 1+2 0 1",
+            "--variant",
+            "3check",
+        ];
+        let config = Config::from_args(&three_check.map(String::from)).unwrap();
+        assert_eq!(config.variant, Variant::ThreeCheck);
+        assert_eq!(config.starting_position().unwrap().checks_given, [2, 1]);
+        let config = Config::from_args(&args("--variant antichess")).unwrap();
+        assert_eq!(
+            config.starting_position().unwrap().to_fen(),
+            Variant::Antichess.start_fen()
+        );
+        assert_eq!(
+            Config::from_args(&args("--variant shogi")),
+            Err(ConfigError::InvalidValue(
+                String::from("variant"),
+                String::from("shogi")
+            ))
+        );
+
+        // Chess960 only goes with variants that start from the usual pieces
+        let config = Config::from_args(&args("--variant crazyhouse --chess960 5")).unwrap();
+        assert!(config.starting_position().unwrap().chess960);
+        for text in [
+            "--variant horde --chess960 5",
+            "--chess960 5 --variant racingkings",
+        ] {
+            assert!(matches!(
+                Config::from_args(&args(text)),
+                Err(ConfigError::Conflict(..))
+            ));
+        }
+        let mut config = Config::from_args(&args("--chess960 5")).unwrap();
+        assert_eq!(
+            config.set("variant", "antichess"),
+            Err(ConfigError::Conflict(
+                String::from("chess960"),
+                String::from("antichess")
+            ))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stopped_players_resign() {
+        use crate::{termination::Termination, uci_engine::tests::stand_in_engine};
+        use std::sync::mpsc;
+        use std::time::Instant;
+
+        crate::player::set_verbose(false);
+        let engine = stand_in_engine("stops", &["bestmove e2e5"]);
+        let config = Config {
+            players: [
+                PlayerConfig::Uci {
+                    command: engine.command.clone(),
+                },
+                PlayerConfig::Random,
+            ],
+            ..Config::default()
+        };
+        let spawn = |config: &Config, state: &State| {
+            [ChessColour::White, ChessColour::Black].map(|colour| {
+                let (_, rx_from_main) = mpsc::channel();
+                config
+                    .spawn_player(colour, None, rx_from_main, state)
+                    .unwrap()
+            })
+        };
+
+        // players that are still going are left alone
+        let mut state = State::new();
+        let random = Config {
+            players: [PlayerConfig::Random, PlayerConfig::Random],
+            ..Config::default()
+        };
+        let players = spawn(&random, &state);
+        resign_stopped_players(&mut state, &players);
+        assert_eq!(state.termination, None);
+
+        // white's engine gives up on its first move, and by the time main looks, it is black's
+        // move: white resigns all the same
+        let players = spawn(&config, &state);
+        let start = Instant::now();
+        while !players[0].has_stopped() {
+            assert!(start.elapsed() < Duration::from_secs(10));
+            thread::sleep(Duration::from_millis(10));
+        }
+        let mut state =
+            State::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        resign_stopped_players(&mut state, &players);
+        assert_eq!(
+            state.termination,
+            Some(Termination::Resignation {
+                winner: ChessColour::Black
+            })
+        );
+    }
+
+    #[test]
+    fn config_file() {
+        let text = "\
+            # an engine match\n\
+            white = search\n\
+            black = uci:/usr/bin/stockfish  # the opponent\n\
+            fen = 4k3/8/8/8/8/8/4P3/4K3 w - - 0 1\n\
+            tc = 10+0.1\n";
+        let config = Config::from_file(text).unwrap();
+        assert_eq!(
+            config.players[1],
+            PlayerConfig::Uci {
+                command: String::from("/usr/bin/stockfish")
+            }
+        );
+        assert_eq!(
+            config.starting_position().unwrap().to_fen(),
+            "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"
+        );
+
+        assert_eq!(config.time_control.unwrap().to_string(), "10+0.1");
+
+        assert_eq!(
+            Config::from_file("white human"),
+            Err(ConfigError::MissingValue(String::from("white human")))
+        );
+        assert!(matches!(
+            Config::from_file("fen = 8/8/8 w - - 0 1"),
+            Err(ConfigError::InvalidFen(_))
+        ));
+    }
+}
@@ -0,0 +1,476 @@
+use crate::{
+    bitboard::*,
+    piece::{PieceKind::*, *},
+    state::State,
+    variant::{Variant, HILL},
+};
+
+// scores a position in centipawns from the point of view of the side to move.
+// searches only ever see positions where that side has at least one legal move
+pub trait Evaluator: Send {
+    fn evaluate(&self, state: &State) -> i32;
+}
+
+// just counts material; cheap, and useful to compare the other evaluators against
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MaterialEvaluator;
+
+impl Evaluator for MaterialEvaluator {
+    fn evaluate(&self, state: &State) -> i32 {
+        let score = [Pawn, Rook, Knight, Bishop, Queen]
+            .into_iter()
+            .map(|kind| {
+                let count = |col: ChessColour| piece_count(state, col, kind);
+                MIDDLEGAME_VALUES[kind.index()]
+                    * (count(ChessColour::White) - count(ChessColour::Black))
+            })
+            .sum::<i32>();
+
+        // in antichess, every piece given away is a step closer to winning
+        match state.variant {
+            Variant::Antichess => -from_side_to_move(state, score),
+            _ => from_side_to_move(state, score),
+        }
+    }
+}
+
+// every term is given separately for the middlegame and the endgame, and the two are blended
+// by how much material is left on the board
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StandardEvaluator;
+
+impl Evaluator for StandardEvaluator {
+    fn evaluate(&self, state: &State) -> i32 {
+        // there's no king to keep safe, and nothing else here applies either
+        if state.variant == Variant::Antichess {
+            return MaterialEvaluator.evaluate(state);
+        }
+
+        let (white_mg, white_eg) = side_score(state, ChessColour::White);
+        let (black_mg, black_eg) = side_score(state, ChessColour::Black);
+        let (mg, eg) = (white_mg - black_mg, white_eg - black_eg);
+
+        let phase = game_phase(state);
+        from_side_to_move(state, (mg * phase + eg * (MAX_PHASE - phase)) / MAX_PHASE)
+    }
+}
+
+// indexed by PieceKind::index. kings aren't counted, as they're always there
+const MIDDLEGAME_VALUES: [i32; 6] = [100, 500, 320, 330, 900, 0];
+const ENDGAME_VALUES: [i32; 6] = [120, 530, 300, 320, 950, 0];
+
+// a knight or bishop counts 1 towards the phase, a rook 2 and a queen 4, which adds up to
+// MAX_PHASE in the starting position; promotions can push it past that
+const PHASE_WEIGHTS: [i32; 6] = [0, 2, 1, 1, 4, 0];
+const MAX_PHASE: i32 = 24;
+
+// per square the piece can move to, for knights, bishops, rooks and queens
+const MOBILITY_WEIGHTS: [(i32, i32); 6] = [(0, 0), (2, 4), (4, 4), (5, 5), (1, 2), (0, 0)];
+
+const BISHOP_PAIR: (i32, i32) = (30, 50);
+const DOUBLED_PAWN: (i32, i32) = (-10, -20);
+const ISOLATED_PAWN: (i32, i32) = (-15, -20);
+// by how many ranks the pawn has advanced
+const PASSED_PAWN: [(i32, i32); 8] = [
+    (0, 0),
+    (5, 10),
+    (10, 20),
+    (20, 40),
+    (35, 70),
+    (60, 120),
+    (100, 200),
+    (0, 0),
+];
+
+// per check given in three-check, by how many there have been so far
+const CHECKS_GIVEN: [i32; 3] = [0, 150, 400];
+// per square the king is away from the centre in king of the hill
+const HILL_DISTANCE: i32 = -25;
+// per rank the king has climbed in racing kings
+const RACE_RANK: i32 = 60;
+
+// own pawns right in front of a castled king, and one rank further up
+const PAWN_SHIELD: [i32; 2] = [12, 6];
+// per attack on the enemy king or the squares around it. only in the middlegame,
+// as that's when there are enough pieces around for it to matter
+const KING_ZONE_ATTACK: i32 = 8;
+
+// the piece-square tables are laid out the way the board is drawn, from white's side:
+// a8 is the first entry and h1 the last. black uses them mirrored
+#[rustfmt::skip]
+const PAWN_TABLE_MG: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    10,  10,  20,  30,  30,  20,  10,  10,
+     5,   5,  10,  25,  25,  10,   5,   5,
+     0,   0,   0,  20,  20,   0,   0,   0,
+     5,  -5, -10,   0,   0, -10,  -5,   5,
+     5,  10,  10, -20, -20,  10,  10,   5,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const PAWN_TABLE_EG: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+    80,  80,  80,  80,  80,  80,  80,  80,
+    50,  50,  50,  50,  50,  50,  50,  50,
+    30,  30,  30,  30,  30,  30,  30,  30,
+    20,  20,  20,  20,  20,  20,  20,  20,
+    10,  10,  10,  10,  10,  10,  10,  10,
+     0,   0,   0,   0,   0,   0,   0,   0,
+     0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_TABLE: [i32; 64] = [
+   -50, -40, -30, -30, -30, -30, -40, -50,
+   -40, -20,   0,   0,   0,   0, -20, -40,
+   -30,   0,  10,  15,  15,  10,   0, -30,
+   -30,   5,  15,  20,  20,  15,   5, -30,
+   -30,   0,  15,  20,  20,  15,   0, -30,
+   -30,   5,  10,  15,  15,  10,   5, -30,
+   -40, -20,   0,   5,   5,   0, -20, -40,
+   -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP_TABLE: [i32; 64] = [
+   -20, -10, -10, -10, -10, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,  10,  10,   5,   0, -10,
+   -10,   5,   5,  10,  10,   5,   5, -10,
+   -10,   0,  10,  10,  10,  10,   0, -10,
+   -10,  10,  10,  10,  10,  10,  10, -10,
+   -10,   5,   0,   0,   0,   0,   5, -10,
+   -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK_TABLE: [i32; 64] = [
+     0,   0,   0,   0,   0,   0,   0,   0,
+     5,  10,  10,  10,  10,  10,  10,   5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+    -5,   0,   0,   0,   0,   0,   0,  -5,
+     0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const QUEEN_TABLE: [i32; 64] = [
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+   -10,   0,   0,   0,   0,   0,   0, -10,
+   -10,   0,   5,   5,   5,   5,   0, -10,
+    -5,   0,   5,   5,   5,   5,   0,  -5,
+     0,   0,   5,   5,   5,   5,   0,  -5,
+   -10,   5,   5,   5,   5,   5,   0, -10,
+   -10,   0,   5,   0,   0,   0,   0, -10,
+   -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+// hide behind the pawns while there are pieces around to attack the king...
+#[rustfmt::skip]
+const KING_TABLE_MG: [i32; 64] = [
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -30, -40, -40, -50, -50, -40, -40, -30,
+   -20, -30, -30, -40, -40, -30, -30, -20,
+   -10, -20, -20, -20, -20, -20, -20, -10,
+    20,  20,   0,   0,   0,   0,  20,  20,
+    20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+// ...and head for the centre once they're gone
+#[rustfmt::skip]
+const KING_TABLE_EG: [i32; 64] = [
+   -50, -40, -30, -20, -20, -30, -40, -50,
+   -30, -20, -10,   0,   0, -10, -20, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  30,  40,  40,  30, -10, -30,
+   -30, -10,  20,  30,  30,  20, -10, -30,
+   -30, -30,   0,   0,   0,   0, -30, -30,
+   -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+// indexed by PieceKind::index
+const TABLES_MG: [&[i32; 64]; 6] = [
+    &PAWN_TABLE_MG,
+    &ROOK_TABLE,
+    &KNIGHT_TABLE,
+    &BISHOP_TABLE,
+    &QUEEN_TABLE,
+    &KING_TABLE_MG,
+];
+const TABLES_EG: [&[i32; 64]; 6] = [
+    &PAWN_TABLE_EG,
+    &ROOK_TABLE,
+    &KNIGHT_TABLE,
+    &BISHOP_TABLE,
+    &QUEEN_TABLE,
+    &KING_TABLE_EG,
+];
+
+fn from_side_to_move(state: &State, white_score: i32) -> i32 {
+    match state.turn {
+        ChessColour::White => white_score,
+        ChessColour::Black => -white_score,
+    }
+}
+
+// pieces in a crazyhouse pocket count as well, as they can come back at any time
+fn piece_count(state: &State, col: ChessColour, kind: PieceKind) -> i32 {
+    let held = match kind {
+        King => 0,
+        _ => state.pockets[col.index()][kind.index()] as i32,
+    };
+    state.pieces[col.index()][kind.index()].count_ones() as i32 + held
+}
+
+// MAX_PHASE with all pieces on the board, down to 0 when only kings and pawns are left
+fn game_phase(state: &State) -> i32 {
+    let phase = [Rook, Knight, Bishop, Queen]
+        .into_iter()
+        .map(|kind| {
+            PHASE_WEIGHTS[kind.index()]
+                * (piece_count(state, ChessColour::White, kind)
+                    + piece_count(state, ChessColour::Black, kind))
+        })
+        .sum::<i32>();
+    phase.min(MAX_PHASE)
+}
+
+// the entry in the piece-square tables for a piece of the given colour
+fn table_index(coord: (u8, u8), col: ChessColour) -> usize {
+    let rank_from_top = match col {
+        ChessColour::White => 7 - coord.1,
+        ChessColour::Black => coord.1,
+    };
+    (coord.0 + 8 * rank_from_top) as usize
+}
+
+// how many ranks a piece of the given colour has come from its own back rank
+fn relative_rank(coord: (u8, u8), col: ChessColour) -> usize {
+    match col {
+        ChessColour::White => coord.1 as usize,
+        ChessColour::Black => 7 - coord.1 as usize,
+    }
+}
+
+// every square on the files next to the given one
+fn adjacent_files(x: u8) -> Bitboard {
+    let left = if x > 0 { file_mask(x - 1) } else { 0 };
+    let right = if x < 7 { file_mask(x + 1) } else { 0 };
+    left | right
+}
+
+// the ranks in front of a pawn of the given colour
+fn ranks_ahead(coord: (u8, u8), col: ChessColour) -> Bitboard {
+    match col {
+        ChessColour::White => !0 << (8 * coord.1) << 8,
+        ChessColour::Black => (1 << (8 * coord.1)) - 1,
+    }
+}
+
+// every term for one side, as middlegame and endgame scores
+fn side_score(state: &State, col: ChessColour) -> (i32, i32) {
+    let own = &state.pieces[col.index()];
+    let enemy = &state.pieces[col.flip().index()];
+    let occupied = state.occupied();
+    let mut mg = 0;
+    let mut eg = 0;
+
+    // material and placement
+    for kind in [Pawn, Rook, Knight, Bishop, Queen, King] {
+        for coord in squares_of(own[kind.index()]) {
+            let index = table_index(coord, col);
+            mg += MIDDLEGAME_VALUES[kind.index()] + TABLES_MG[kind.index()][index];
+            eg += ENDGAME_VALUES[kind.index()] + TABLES_EG[kind.index()][index];
+        }
+    }
+    // pieces in the pocket can be dropped wherever they're needed
+    for kind in [Pawn, Rook, Knight, Bishop, Queen] {
+        let held = state.pockets[col.index()][kind.index()] as i32;
+        mg += MIDDLEGAME_VALUES[kind.index()] * held;
+        eg += ENDGAME_VALUES[kind.index()] * held;
+    }
+
+    // mobility, not counting squares the enemy pawns are watching
+    let enemy_pawn_attacks = squares_of(enemy[Pawn.index()])
+        .map(|coord| PAWN_ATTACKS[col.flip().index()][square_index(coord)])
+        .fold(0, |acc, attacks| acc | attacks);
+    let safe = !state.occupancy[col.index()] & !enemy_pawn_attacks;
+    // attacks on the enemy king and the squares around it, if there is one; the horde has none
+    let enemy_king_zone = state.find_king(col.flip()).map_or(0, |king| {
+        let index = square_index(king);
+        KING_ATTACKS[index] | (1 << index)
+    });
+    let mut king_zone_attacks = 0;
+
+    for kind in [Rook, Knight, Bishop, Queen] {
+        for coord in squares_of(own[kind.index()]) {
+            let index = square_index(coord);
+            let attacks = match kind {
+                Knight => KNIGHT_ATTACKS[index],
+                Bishop => bishop_attacks(index, occupied),
+                Rook => rook_attacks(index, occupied),
+                _ => queen_attacks(index, occupied),
+            };
+            let mobility = (attacks & safe).count_ones() as i32;
+            let (weight_mg, weight_eg) = MOBILITY_WEIGHTS[kind.index()];
+            mg += weight_mg * mobility;
+            eg += weight_eg * mobility;
+            king_zone_attacks += (attacks & enemy_king_zone).count_ones() as i32;
+        }
+    }
+    mg += KING_ZONE_ATTACK * king_zone_attacks;
+
+    if own[Bishop.index()].count_ones() >= 2 {
+        mg += BISHOP_PAIR.0;
+        eg += BISHOP_PAIR.1;
+    }
+
+    // pawn structure
+    let own_pawns = own[Pawn.index()];
+    let enemy_pawns = enemy[Pawn.index()];
+    for x in 0..8 {
+        let on_file = (own_pawns & file_mask(x)).count_ones() as i32;
+        if on_file > 1 {
+            mg += DOUBLED_PAWN.0 * (on_file - 1);
+            eg += DOUBLED_PAWN.1 * (on_file - 1);
+        }
+        if on_file > 0 && own_pawns & adjacent_files(x) == 0 {
+            mg += ISOLATED_PAWN.0 * on_file;
+            eg += ISOLATED_PAWN.1 * on_file;
+        }
+    }
+    for coord in squares_of(own_pawns) {
+        let in_front = (file_mask(coord.0) | adjacent_files(coord.0)) & ranks_ahead(coord, col);
+        if enemy_pawns & in_front == 0 {
+            let (bonus_mg, bonus_eg) = PASSED_PAWN[relative_rank(coord, col)];
+            mg += bonus_mg;
+            eg += bonus_eg;
+        }
+    }
+
+    // king safety: the pawns sheltering the king, if there is one
+    let king = state.find_king(col);
+    for (distance, bonus) in PAWN_SHIELD.iter().enumerate() {
+        let Some(king) = king else {
+            break;
+        };
+        let rank = match col {
+            ChessColour::White => king.1 as i32 + 1 + distance as i32,
+            ChessColour::Black => king.1 as i32 - 1 - distance as i32,
+        };
+        if !(0..8).contains(&rank) {
+            continue;
+        }
+        let shield = (file_mask(king.0) | adjacent_files(king.0)) & (0xFF << (8 * rank));
+        mg += bonus * (own_pawns & shield).count_ones() as i32;
+    }
+
+    // getting closer to the variant's own goal
+    let variant_bonus = match state.variant {
+        Variant::ThreeCheck => CHECKS_GIVEN[(state.checks_given[col.index()] as usize).min(2)],
+        Variant::KingOfTheHill => {
+            let king = state.get_king_coord(col);
+            let distance = squares_of(HILL)
+                .map(|hill| hill.0.abs_diff(king.0).max(hill.1.abs_diff(king.1)))
+                .min()
+                .unwrap();
+            HILL_DISTANCE * distance as i32
+        }
+        Variant::RacingKings => RACE_RANK * state.get_king_coord(col).1 as i32,
+        _ => 0,
+    };
+    mg += variant_bonus;
+    eg += variant_bonus;
+
+    (mg, eg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(fen: &str) -> i32 {
+        StandardEvaluator.evaluate(&State::from_fen(fen).unwrap())
+    }
+
+    // the same position with the colours swapped, which has to get the same score
+    fn mirror(fen: &str) -> String {
+        let fields = fen.split(' ').collect::<Vec<_>>();
+        let swap_case = |s: &str| {
+            s.chars()
+                .map(|c| {
+                    if c.is_uppercase() {
+                        c.to_ascii_lowercase()
+                    } else {
+                        c.to_ascii_uppercase()
+                    }
+                })
+                .collect::<String>()
+        };
+        let placement = fields[0].split('/').rev().collect::<Vec<_>>().join("/");
+        let turn = if fields[1] == "w" { "b" } else { "w" };
+        let en_passant = match fields[3] {
+            "-" => String::from("-"),
+            target => target
+                .chars()
+                .map(|c| match c {
+                    '3' => '6',
+                    '6' => '3',
+                    c => c,
+                })
+                .collect(),
+        };
+        format!(
+            "{} {turn} {} {en_passant} {} {}",
+            swap_case(&placement),
+            swap_case(fields[2]),
+            fields[4],
+            fields[5]
+        )
+    }
+
+    #[test]
+    fn symmetric() {
+        assert_eq!(evaluate(crate::fen::STARTING_FEN), 0);
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            assert_eq!(evaluate(fen), evaluate(&mirror(fen)), "{fen}");
+        }
+    }
+
+    #[test]
+    fn pawn_structure() {
+        // the same material, but one side's pawns are passed and the other's doubled and isolated
+        let healthy = evaluate("4k3/8/8/8/8/8/3PP3/4K3 w - - 0 1");
+        let doubled = evaluate("4k3/8/8/8/8/4P3/4P3/4K3 w - - 0 1");
+        assert!(healthy > doubled);
+
+        let passed = evaluate("4k3/8/8/3P4/8/8/8/4K3 w - - 0 1");
+        let blocked = evaluate("4k3/2p5/8/3P4/8/8/8/4K3 w - - 0 1");
+        assert!(passed > blocked + ENDGAME_VALUES[Pawn.index()]);
+    }
+
+    #[test]
+    fn bishop_pair() {
+        let pair = evaluate("4k3/8/8/8/8/8/8/2B1KB2 w - - 0 1");
+        let bishop_and_knight = evaluate("4k3/8/8/8/8/8/8/2B1KN2 w - - 0 1");
+        assert!(pair > bishop_and_knight);
+    }
+
+    #[test]
+    fn material_only() {
+        let state = State::from_fen("4k3/8/8/8/8/8/8/R3K3 b - - 0 1").unwrap();
+        assert_eq!(MaterialEvaluator.evaluate(&state), -500);
+    }
+}
@@ -0,0 +1,476 @@
+use crate::{
+    bitboard::bit,
+    piece::{PieceKind::*, *},
+    square::parse_coord,
+    state::{back_rank, State},
+    variant::Variant,
+};
+
+use std::fmt;
+
+pub const STARTING_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FenError {
+    WrongFieldCount(usize),
+    WrongRankCount(usize),
+    BadRank(String),
+    InvalidPiece(char),
+    InvalidActiveColour(String),
+    InvalidCastling(String),
+    InvalidEnPassant(String),
+    InvalidHalfmoveClock(String),
+    InvalidFullmoveNumber(String),
+    InvalidChecks(String),
+    InvalidPocket(String),
+    KingCount(ChessColour, usize),
+    // a pawn on a rank it can't stand on, e.g. the one it promotes on
+    MisplacedPawn(String),
+    // the side that just moved left its own king in check
+    OpponentInCheck(ChessColour),
+}
+
+impl fmt::Display for FenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FenError::WrongFieldCount(n) => write!(f, "expected 4 or 6 fields, found {n}"),
+            FenError::WrongRankCount(n) => write!(f, "expected 8 ranks, found {n}"),
+            FenError::BadRank(r) => write!(f, "rank \"{r}\" does not describe 8 squares"),
+            FenError::InvalidPiece(c) => write!(f, "invalid piece character '{c}'"),
+            FenError::InvalidActiveColour(s) => write!(f, "invalid side to move \"{s}\""),
+            FenError::InvalidCastling(s) => write!(f, "invalid castling rights \"{s}\""),
+            FenError::InvalidEnPassant(s) => write!(f, "invalid en passant target \"{s}\""),
+            FenError::InvalidHalfmoveClock(s) => write!(f, "invalid halfmove clock \"{s}\""),
+            FenError::InvalidFullmoveNumber(s) => write!(f, "invalid fullmove number \"{s}\""),
+            FenError::InvalidChecks(s) => write!(f, "invalid remaining checks \"{s}\""),
+            FenError::InvalidPocket(s) => write!(f, "invalid pocket \"{s}\""),
+            FenError::KingCount(col, n) => write!(f, "{col:?} can't have {n} kings"),
+            FenError::MisplacedPawn(s) => write!(f, "there can't be a pawn on {s}"),
+            FenError::OpponentInCheck(col) => {
+                write!(f, "{col:?} is in check, but it isn't their move")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FenError {}
+
+impl State {
+    pub fn from_fen(fen: &str) -> Result<Self, FenError> {
+        State::from_fen_with_variant(fen, Variant::Standard)
+    }
+
+    // three-check adds how many checks each side has left after the en passant target, e.g. 3+3,
+    // which may be left out at the start of the game. crazyhouse adds the pockets after the
+    // pieces, e.g. [Qpp], and marks promoted pieces with a ~ after their letter
+    pub fn from_fen_with_variant(fen: &str, variant: Variant) -> Result<Self, FenError> {
+        let mut fields = fen.split_whitespace().collect::<Vec<_>>();
+        let checks = match fields.len() {
+            5 | 7 if variant == Variant::ThreeCheck => Some(fields.remove(4)),
+            _ => None,
+        };
+        if fields.len() != 4 && fields.len() != 6 {
+            return Err(FenError::WrongFieldCount(fields.len()));
+        }
+
+        let mut state = State::empty();
+        state.variant = variant;
+
+        let (placement, pocket) = match fields[0].split_once('[') {
+            Some((placement, pocket)) if variant.has_pockets() => {
+                let pocket = pocket
+                    .strip_suffix(']')
+                    .ok_or_else(|| FenError::InvalidPocket(pocket.to_string()))?;
+                (placement, pocket)
+            }
+            _ => (fields[0], ""),
+        };
+
+        // piece placement, from the eighth rank down to the first
+        let ranks = placement.split('/').collect::<Vec<_>>();
+        if ranks.len() != 8 {
+            return Err(FenError::WrongRankCount(ranks.len()));
+        }
+        for (i, rank) in ranks.iter().enumerate() {
+            let y = 7 - i as u8;
+            let mut x = 0u8;
+            // a ~ only ever comes straight after the piece it marks
+            let mut after_piece = false;
+            for c in rank.chars() {
+                let follows_piece = std::mem::replace(&mut after_piece, false);
+                if let Some(skip) = c.to_digit(10) {
+                    x += skip as u8;
+                    if x > 8 {
+                        return Err(FenError::BadRank(rank.to_string()));
+                    }
+                } else if c == '~' && variant.has_pockets() && follows_piece {
+                    state.promoted |= bit((x - 1, y));
+                } else {
+                    let piece = Piece::from_char(c).ok_or(FenError::InvalidPiece(c))?;
+                    if x >= 8 {
+                        return Err(FenError::BadRank(rank.to_string()));
+                    }
+                    // only the horde has pawns on the first rank
+                    let horde_pawn =
+                        variant == Variant::Horde && piece.colour == ChessColour::White;
+                    if piece.kind == Pawn && (y == 7 || y == 0 && !horde_pawn) {
+                        return Err(FenError::MisplacedPawn(state[(x, y)].coord()));
+                    }
+                    state.set_piece((x, y), Some(piece));
+                    x += 1;
+                    after_piece = true;
+                }
+            }
+            if x != 8 {
+                return Err(FenError::BadRank(rank.to_string()));
+            }
+        }
+
+        for c in pocket.chars() {
+            let invalid = || FenError::InvalidPocket(pocket.to_string());
+            let piece = Piece::from_char(c)
+                .filter(|p| p.kind != King)
+                .ok_or_else(invalid)?;
+            let held = &mut state.pockets[piece.colour.index()][piece.kind.index()];
+            *held = held.saturating_add(1);
+        }
+
+        // without royal kings, there may be any number of them
+        for col in [ChessColour::White, ChessColour::Black] {
+            if !variant.royal_king() {
+                break;
+            }
+            let kings = state
+                .squares
+                .iter()
+                .filter_map(|s| s.content)
+                .filter(|p| p.kind == King && p.colour == col)
+                .count();
+            if kings != variant.has_king(col) as usize {
+                return Err(FenError::KingCount(col, kings));
+            }
+        }
+
+        state.turn = match fields[1] {
+            "w" => ChessColour::White,
+            "b" => ChessColour::Black,
+            other => return Err(FenError::InvalidActiveColour(other.to_string())),
+        };
+        if state.is_in_check(state.turn.flip()) {
+            return Err(FenError::OpponentInCheck(state.turn.flip()));
+        }
+
+        // KQkq, where in Chess960 K and Q stand for the outermost rook on that side of the king
+        // (X-FEN), or the files of the castling rooks, e.g. HAha (Shredder-FEN). a castling right
+        // is only accepted if the king is on its back rank and there is a rook to castle with.
+        // in variants without castling, the field is ignored
+        if fields[2] != "-" && variant.royal_king() {
+            let invalid = || FenError::InvalidCastling(fields[2].to_string());
+            for c in fields[2].chars() {
+                let colour = if c.is_ascii_uppercase() {
+                    ChessColour::White
+                } else {
+                    ChessColour::Black
+                };
+                let rank = back_rank(colour);
+                let (king_file, king_rank) = state.find_king(colour).ok_or_else(invalid)?;
+                if king_rank != rank {
+                    return Err(invalid());
+                }
+                let rook = Piece { kind: Rook, colour };
+                let mut rook_files = (0..8u8).filter(|x| state[(*x, rank)].content == Some(rook));
+
+                let rook_file = match c.to_ascii_lowercase() {
+                    'k' => rook_files.rev().find(|x| *x > king_file),
+                    'q' => rook_files.find(|x| *x < king_file),
+                    file @ 'a'..='h' => {
+                        // naming the file only makes sense for Chess960
+                        state.chess960 = true;
+                        Some(file as u8 - b'a').filter(|x| state[(*x, rank)].content == Some(rook))
+                    }
+                    _ => None,
+                }
+                .filter(|x| *x != king_file)
+                .ok_or_else(invalid)?;
+
+                let long_castle = rook_file < king_file;
+                state.castling_rights.set(colour, long_castle, true);
+                state.castling_files[colour.index()][long_castle as usize] = rook_file;
+                let usual_file = if long_castle { 0 } else { 7 };
+                if king_file != 4 || rook_file != usual_file {
+                    state.chess960 = true;
+                }
+            }
+        }
+
+        // the en passant target is the square behind the pawn that just made a double step,
+        // which for the horde's pawns on the first rank is on the second
+        if fields[3] != "-" {
+            let invalid = || FenError::InvalidEnPassant(fields[3].to_string());
+            let target = parse_coord(fields[3]).ok_or_else(invalid)?;
+            let pusher = state.turn.flip();
+            let target_ranks: &[u8] = match (state.turn, variant) {
+                (ChessColour::White, _) => &[5],
+                (ChessColour::Black, Variant::Horde) => &[1, 2],
+                (ChessColour::Black, _) => &[2],
+            };
+            if !target_ranks.contains(&target.1) || state[target].content.is_some() {
+                return Err(invalid());
+            }
+            let pawn_rank = match state.turn {
+                ChessColour::White => target.1 - 1,
+                ChessColour::Black => target.1 + 1,
+            };
+            let pushed_pawn = Piece {
+                kind: Pawn,
+                colour: pusher,
+            };
+            if state[(target.0, pawn_rank)].content != Some(pushed_pawn) {
+                return Err(invalid());
+            }
+            state.en_passant = Some(target);
+        }
+
+        if fields.len() == 6 {
+            state.halfmove_clock = fields[4]
+                .parse()
+                .map_err(|_| FenError::InvalidHalfmoveClock(fields[4].to_string()))?;
+            state.fullmove_number = match fields[5].parse() {
+                Ok(n) if n >= 1 => n,
+                _ => return Err(FenError::InvalidFullmoveNumber(fields[5].to_string())),
+            };
+        }
+
+        if let Some(checks) = checks {
+            let invalid = || FenError::InvalidChecks(checks.to_string());
+            let (white, black) = checks.split_once('+').ok_or_else(invalid)?;
+            for (col, remaining) in [white, black].into_iter().enumerate() {
+                let remaining = remaining.parse::<u8>().ok().filter(|r| *r <= 3);
+                state.checks_given[col] = 3 - remaining.ok_or_else(invalid)?;
+            }
+        }
+
+        state.hash = state.compute_hash();
+
+        Ok(state)
+    }
+
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for y in (0..8u8).rev() {
+            let mut empty = 0;
+            for x in 0..8u8 {
+                match self[(x, y)].content {
+                    Some(p) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placement.push(p.to_char());
+                        if self.promoted & bit((x, y)) != 0 {
+                            placement.push('~');
+                        }
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if y > 0 {
+                placement.push('/');
+            }
+        }
+        if self.variant.has_pockets() {
+            placement.push('[');
+            for col in [ChessColour::White, ChessColour::Black] {
+                for kind in [Queen, Rook, Bishop, Knight, Pawn] {
+                    let c = Piece { kind, colour: col }.to_char();
+                    for _ in 0..self.pockets[col.index()][kind.index()] {
+                        placement.push(c);
+                    }
+                }
+            }
+            placement.push(']');
+        }
+
+        let turn = if self.turn == ChessColour::White {
+            'w'
+        } else {
+            'b'
+        };
+
+        let castling = self.castling_field(false);
+
+        let en_passant = match self.en_passant {
+            Some(target) => self[target].coord(),
+            None => String::from("-"),
+        };
+
+        let checks = match self.variant {
+            Variant::ThreeCheck => {
+                format!(" {}+{}", 3 - self.checks_given[0], 3 - self.checks_given[1])
+            }
+            _ => String::new(),
+        };
+
+        format!(
+            "{placement} {turn} {castling} {en_passant}{checks} {} {}",
+            self.halfmove_clock, self.fullmove_number
+        )
+    }
+
+    // the same, but with the castling rooks' files in the castling field the way Shredder-FEN
+    // writes them, e.g. HAha
+    pub fn to_shredder_fen(&self) -> String {
+        let fen = self.to_fen();
+        let mut fields = fen.split(' ').collect::<Vec<_>>();
+        let castling = self.castling_field(true);
+        fields[2] = &castling;
+        fields.join(" ")
+    }
+
+    // KQkq, unless a Chess960 rook isn't the outermost one on its side, in which case its file
+    // is written instead (X-FEN). shredder writes the files every time
+    fn castling_field(&self, shredder: bool) -> String {
+        let mut castling = String::new();
+        for colour in [ChessColour::White, ChessColour::Black] {
+            for (long_castle, letter) in [(false, 'K'), (true, 'Q')] {
+                if !self.castling_rights.get(colour, long_castle) {
+                    continue;
+                }
+                let (rook_file, rank) = self.castling_rook(colour, long_castle);
+                let mut outer_files = if long_castle {
+                    0..rook_file
+                } else {
+                    rook_file + 1..8
+                };
+                let rook = Piece { kind: Rook, colour };
+                let outermost = outer_files.all(|x| self[(x, rank)].content != Some(rook));
+
+                let c = if shredder || !outermost {
+                    (b'A' + rook_file) as char
+                } else {
+                    letter
+                };
+                castling.push(match colour {
+                    ChessColour::White => c,
+                    ChessColour::Black => c.to_ascii_lowercase(),
+                });
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        castling
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        for fen in [
+            STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/8/8/8/8/8/8/k6K b - - 57 120",
+        ] {
+            assert_eq!(State::from_fen(fen).unwrap().to_fen(), fen);
+        }
+        // the counters may be left out
+        assert_eq!(
+            State::from_fen("4k3/8/8/8/8/8/8/4K3 w - -")
+                .unwrap()
+                .to_fen(),
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let cases = [
+            ("4k3/8/8/8/8/8/8/4K3 w - - 0", FenError::WrongFieldCount(5)),
+            ("4k3/8/8/8/8/8/4K3 w - - 0 1", FenError::WrongRankCount(7)),
+            (
+                "4k3/8/8/8/8/8/8/4K4 w - - 0 1",
+                FenError::BadRank(String::from("4K4")),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/99999999999999999999999999999999 w - - 0 1",
+                FenError::BadRank(String::from("99999999999999999999999999999999")),
+            ),
+            ("4k3/8/8/8/8/8/8/4X3 w - - 0 1", FenError::InvalidPiece('X')),
+            (
+                "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+                FenError::InvalidActiveColour(String::from("x")),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w K - 0 1",
+                FenError::InvalidCastling(String::from("K")),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - e3 0 1",
+                FenError::InvalidEnPassant(String::from("e3")),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - x 1",
+                FenError::InvalidHalfmoveClock(String::from("x")),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K3 w - - 0 0",
+                FenError::InvalidFullmoveNumber(String::from("0")),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/4K2K w - - 0 1",
+                FenError::KingCount(ChessColour::White, 2),
+            ),
+            (
+                "4k3/8/8/8/8/8/8/8 w - - 0 1",
+                FenError::KingCount(ChessColour::White, 0),
+            ),
+            (
+                "4k3/4R3/8/8/8/8/8/4K3 w - - 0 1",
+                FenError::OpponentInCheck(ChessColour::Black),
+            ),
+        ];
+        for (fen, error) in cases {
+            assert_eq!(State::from_fen(fen).err(), Some(error), "{fen}");
+        }
+        assert_eq!(
+            State::from_fen_with_variant("4k3/8/8/8/8/8/8/4K3 w - - 4+3 0 1", Variant::ThreeCheck)
+                .err(),
+            Some(FenError::InvalidChecks(String::from("4+3")))
+        );
+        assert_eq!(
+            State::from_fen_with_variant("4k3/8/8/8/8/8/8/4K3[Q w - - 0 1", Variant::Crazyhouse)
+                .err(),
+            Some(FenError::InvalidPocket(String::from("Q")))
+        );
+    }
+
+    #[test]
+    fn misplaced_pawns() {
+        for (fen, square) in [
+            ("P3k3/8/8/8/8/8/8/4K3 w - - 0 1", "a8"),
+            ("4k3/8/8/8/8/8/8/p3K3 b - - 0 1", "a1"),
+            ("4k3/8/8/8/8/8/8/P3K3 w - - 0 1", "a1"),
+            ("p3k3/8/8/8/8/8/8/4K3 w - - 0 1", "a8"),
+        ] {
+            assert_eq!(
+                State::from_fen(fen).err(),
+                Some(FenError::MisplacedPawn(String::from(square))),
+                "{fen}"
+            );
+        }
+        // apart from the horde's
+        assert!(
+            State::from_fen_with_variant("4k3/8/8/8/8/8/8/P7 w - - 0 1", Variant::Horde).is_ok()
+        );
+        assert!(
+            State::from_fen_with_variant("p3k3/8/8/8/8/8/8/P7 w - - 0 1", Variant::Horde).is_err()
+        );
+    }
+}
@@ -0,0 +1,34 @@
+// the rules engine and players, shared by the GUI in main.rs and the binaries in src/bin
+
+// eprintln, unless logging was turned off with player::set_verbose
+macro_rules! log {
+    ($($arg:tt)*) => {
+        if $crate::player::verbose() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub mod bitboard;
+pub mod chess960;
+pub mod chess_move;
+pub mod clock;
+pub mod config;
+pub mod eval;
+pub mod fen;
+pub mod match_runner;
+pub mod perft;
+pub mod pgn;
+pub mod piece;
+pub mod player;
+pub mod san;
+pub mod search;
+pub mod square;
+pub mod state;
+pub mod stats;
+pub mod termination;
+pub mod tt;
+pub mod uci;
+pub mod uci_engine;
+pub mod variant;
+pub mod zobrist;
@@ -3,32 +3,35 @@ const BOARD_EDGE: i32 = 8 * SQUARE_W as i32;
 const MARGIN: i32 = 16; // obv only makes sense as unsigned, but this makes addition nicer
 const SCREEN_W: u32 = BOARD_EDGE as u32 + 400;
 const SCREEN_H: u32 = BOARD_EDGE as u32;
+// crazyhouse pockets go in the side panel above the moves, black's on top like on the board
+const POCKET_W: u32 = 30;
+const POCKET_TOP: i32 = 5 * MARGIN;
+const POCKET_ROWS: [ChessColour; 2] = [ChessColour::Black, ChessColour::White];
+const POCKET_KINDS: [PieceKind; 5] = [Queen, Rook, Bishop, Knight, Pawn];
 
 use sdl2::event::Event;
 use sdl2::image::{self, InitFlag, LoadTexture};
 use sdl2::keyboard::Keycode;
+use sdl2::mouse::MouseButton;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::{Canvas, TextureCreator};
 use sdl2::ttf::{self, Font};
 use sdl2::video::{Window, WindowContext};
 use std::sync::mpsc;
-use std::thread;
-use std::time::Duration;
-
-mod piece;
-use piece::{PieceKind::*, *};
-
-mod chess_move;
-use chess_move::*;
-
-mod state;
-use state::*;
-
-mod square;
-
-mod player;
-use player::*;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use schaak::{
+    chess_move::ChessMove,
+    clock::{format_clock, Clock},
+    config::{resign_stopped_players, Config, USAGE},
+    pgn::{parse_pgn, PgnGame},
+    piece::{PieceKind::*, *},
+    player::*,
+    square::Square,
+    state::*,
+};
 
 fn draw_text(
     text: &str,
@@ -49,8 +52,140 @@ fn draw_text(
     )
 }
 
+fn square_colour(square: &Square, mouse_over: bool) -> Color {
+    if square.is_light() {
+        if mouse_over {
+            Color::RGB(141, 177, 196)
+        } else {
+            Color::RGB(161, 222, 255)
+        }
+    } else {
+        if mouse_over {
+            Color::RGB(4, 38, 56)
+        } else {
+            Color::RGB(0, 79, 122)
+        }
+    }
+}
+
+// the board square under the given point on the screen, if there is one
+fn board_coord(x: i32, y: i32) -> Option<(u8, u8)> {
+    if (0..BOARD_EDGE).contains(&x) && (0..BOARD_EDGE).contains(&y) {
+        let square_w = SQUARE_W as i32;
+        Some(((x / square_w) as u8, 7 - (y / square_w) as u8))
+    } else {
+        None
+    }
+}
+
+// where a piece in a pocket is drawn; how many there are goes to its right
+fn pocket_rect(row: usize, slot: usize) -> Rect {
+    let pocket_w = POCKET_W as i32;
+    Rect::new(
+        BOARD_EDGE + MARGIN + 2 * pocket_w * slot as i32,
+        POCKET_TOP + (pocket_w + 4) * row as i32,
+        POCKET_W,
+        POCKET_W,
+    )
+}
+
+// the pocket piece under the given point on the screen, if there is one
+fn pocket_slot(x: i32, y: i32) -> Option<(ChessColour, PieceKind)> {
+    POCKET_ROWS
+        .into_iter()
+        .enumerate()
+        .find_map(|(row, colour)| {
+            (0..POCKET_KINDS.len())
+                .find(|slot| pocket_rect(row, *slot).contains_point((x, y)))
+                .map(|slot| (colour, POCKET_KINDS[slot]))
+        })
+}
+
+// appends the move to the side panel's move list, which holds one line per full move.
+// has to be called before the move is made, as SAN depends on the position it is played in
+fn record_san(move_list: &mut Vec<String>, state: &mut State, chess_move: ChessMove) {
+    let san = state.move_to_san(chess_move);
+    match (state.turn, move_list.last_mut()) {
+        (ChessColour::White, _) => move_list.push(format!("{}. {san}", state.fullmove_number)),
+        (ChessColour::Black, Some(line)) => {
+            line.push(' ');
+            line.push_str(&san);
+        }
+        (ChessColour::Black, None) => move_list.push(format!("{}... {san}", state.fullmove_number)),
+    }
+}
+
+// `schaak perft <depth> [fen]` prints the node count per root move and in total
+fn run_perft(args: &[String]) -> Result<(), String> {
+    let depth = args
+        .first()
+        .and_then(|d| d.parse().ok())
+        .ok_or("usage: schaak perft <depth> [fen]")?;
+    let mut state = if args.len() > 1 {
+        State::from_fen(&args[1..].join(" ")).map_err(|e| e.to_string())?
+    } else {
+        State::new()
+    };
+
+    let divided = state.divide(depth);
+    for (root_move, nodes) in &divided {
+        println!("{root_move}: {nodes}");
+    }
+    println!();
+    println!("nodes: {}", divided.iter().map(|(_, n)| n).sum::<u64>());
+
+    Ok(())
+}
+
+// `schaak pgn <file> [options]` continues the first game in the file from its last position
+fn load_pgn(args: &[String]) -> Result<PgnGame, String> {
+    let path = args.first().ok_or("usage: schaak pgn <file> [options]")?;
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let games = parse_pgn(&text).map_err(|e| e.to_string())?;
+    games
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("{path} does not contain any games"))
+}
+
 fn main() -> Result<(), String> {
-    let mut state = State::new();
+    let args = std::env::args().collect::<Vec<_>>();
+    if args.get(1).map(String::as_str) == Some("perft") {
+        return run_perft(&args[2..]);
+    }
+
+    if args.iter().any(|a| a == "--help") {
+        println!(
+            "usage: schaak [pgn <file>] [options]\n       schaak perft <depth> [fen]\n\n{USAGE}"
+        );
+        return Ok(());
+    }
+
+    // the game's own starting position wins over --fen
+    let (loaded_game, options) = match args.get(1).map(String::as_str) {
+        Some("pgn") => (Some(load_pgn(&args[2..])?), args.get(3..).unwrap_or(&[])),
+        _ => (None, &args[1..]),
+    };
+    let config = Config::from_args(options).map_err(|e| format!("{e} (see schaak --help)"))?;
+
+    let mut state = config
+        .starting_position()
+        .map_err(|e| format!("{e} (see schaak --help)"))?;
+    let mut move_list: Vec<String> = Vec::new();
+    let mut title = String::from("schaak");
+    if let Some(game) = &loaded_game {
+        let mut positions = game.positions();
+        for (position, pgn_move) in positions.iter_mut().zip(&game.moves) {
+            record_san(&mut move_list, position, pgn_move.chess_move);
+        }
+        state = positions.last().unwrap().clone();
+        title = format!(
+            "schaak: {} - {}",
+            game.tag("White").unwrap_or("?"),
+            game.tag("Black").unwrap_or("?")
+        );
+    }
+    state.update_termination();
 
     let sdl_context = sdl2::init().unwrap();
     let _image_context = image::init(InitFlag::PNG).unwrap(); // has to be let-binding to ensure drop at the end of the program
@@ -61,7 +196,7 @@ fn main() -> Result<(), String> {
         .unwrap();
 
     let window = video_subsystem
-        .window("schaak", SCREEN_W, SCREEN_H)
+        .window(&title, SCREEN_W, SCREEN_H)
         .opengl()
         .build()
         .unwrap();
@@ -106,33 +241,107 @@ fn main() -> Result<(), String> {
         .load_texture("assets/textures/bk.png")
         .unwrap();
 
+    let texture_of = |piece: Piece| match piece {
+        Piece {
+            kind: PieceKind::Pawn,
+            colour: ChessColour::White,
+            ..
+        } => &tex_wp,
+        Piece {
+            kind: PieceKind::Rook,
+            colour: ChessColour::White,
+            ..
+        } => &tex_wr,
+        Piece {
+            kind: PieceKind::Knight,
+            colour: ChessColour::White,
+            ..
+        } => &tex_wn,
+        Piece {
+            kind: PieceKind::Bishop,
+            colour: ChessColour::White,
+            ..
+        } => &tex_wb,
+        Piece {
+            kind: PieceKind::Queen,
+            colour: ChessColour::White,
+            ..
+        } => &tex_wq,
+        Piece {
+            kind: PieceKind::King,
+            colour: ChessColour::White,
+            ..
+        } => &tex_wk,
+
+        Piece {
+            kind: PieceKind::Pawn,
+            colour: ChessColour::Black,
+            ..
+        } => &tex_bp,
+        Piece {
+            kind: PieceKind::Rook,
+            colour: ChessColour::Black,
+            ..
+        } => &tex_br,
+        Piece {
+            kind: PieceKind::Knight,
+            colour: ChessColour::Black,
+            ..
+        } => &tex_bn,
+        Piece {
+            kind: PieceKind::Bishop,
+            colour: ChessColour::Black,
+            ..
+        } => &tex_bb,
+        Piece {
+            kind: PieceKind::Queen,
+            colour: ChessColour::Black,
+            ..
+        } => &tex_bq,
+        Piece {
+            kind: PieceKind::King,
+            colour: ChessColour::Black,
+            ..
+        } => &tex_bk,
+    };
+
     canvas.set_draw_color(Color::RGB(0, 0, 0));
     canvas.clear();
     canvas.present();
     let mut event_pump = sdl_context.event_pump().unwrap();
 
-    // players and their comms
+    // with a time control, the clock is started once both players are up
+    let clock = config
+        .time_control
+        .clone()
+        .map(|tc| Arc::new(Mutex::new(Clock::new(tc))));
 
+    // players and their comms
     let (tx_to_white_player, rx_to_white_player) = mpsc::channel();
     let (tx_to_black_player, rx_to_black_player) = mpsc::channel();
 
-    let (mut white_player, rx_from_white_player) =
-        RandomPlayer::new(rx_to_white_player, &state, ChessColour::White);
-    let (mut black_player, rx_from_black_player) =
-        RandomPlayer::new(rx_to_black_player, &state, ChessColour::Black);
-
-    thread::spawn(move || loop {
-        white_player.tick();
-        thread::sleep(Duration::from_millis(1000));
-    });
-
-    thread::spawn(move || {
-        thread::sleep(Duration::from_millis(500));
-        loop {
-            black_player.tick();
-            thread::sleep(Duration::from_millis(1000));
-        }
-    });
+    let white_player = config
+        .spawn_player(
+            ChessColour::White,
+            clock.as_ref(),
+            rx_to_white_player,
+            &state,
+        )
+        .map_err(|e| e.to_string())?;
+    let black_player = config
+        .spawn_player(
+            ChessColour::Black,
+            clock.as_ref(),
+            rx_to_black_player,
+            &state,
+        )
+        .map_err(|e| e.to_string())?;
+    // indexed by ChessColour::index
+    let players = [white_player, black_player];
+
+    if let (Some(clock), None) = (&clock, state.termination) {
+        clock.lock().unwrap().start(state.turn, Instant::now());
+    }
 
     'running: loop {
         // clear the screen
@@ -142,13 +351,12 @@ fn main() -> Result<(), String> {
         // drawing the board tiles
         let mx = event_pump.mouse_state().x() as u32;
         let my = event_pump.mouse_state().y() as u32;
-        let md = event_pump
-            .mouse_state()
-            .is_mouse_button_pressed(sdl2::mouse::MouseButton::Left);
 
-        if state.mouse_pressed_previous && !md {
-            state.mouse_pressed_previous = false;
-        }
+        // the piece a human player to move has picked up, and where it can go
+        let selection = players[state.turn.index()]
+            .human
+            .as_ref()
+            .and_then(HumanHandle::selection);
 
         let mut mouse_over_coord: Option<String> = None;
 
@@ -166,37 +374,6 @@ fn main() -> Result<(), String> {
                     mouse_over_coord = Some(square.coord())
                 };
 
-                // we only want interaction when the game is running;
-                // this mouse check is the only place where input happens.
-
-                // if state.game_running {
-                //     if mouse_hit && !state.mouse_pressed_previous && md {
-                //         if state.selected_square.is_none() {
-                //             if square.content.is_some()
-                //                 && square.content.unwrap().colour == state.turn
-                //             {
-                //                 state.selected_square = Some((x, y));
-                //             }
-                //         } else {
-                //             if state.attempt_move((x, y)) {
-                //                 state.turn = state.turn.flip();
-                //                 // every piece that is of the colour whose turn it currently is can now be "de-en passanted"; it is no longer the current turn
-                //                 let aux_squares = state.squares.clone();
-                //                 for (idx, s) in aux_squares.into_iter().enumerate() {
-                //                     if let Some(p) = s.content {
-                //                         if p.colour == state.turn {
-                //                             state.squares[idx].content = Some(Piece {
-                //                                 en_passanteable: false,
-                //                                 ..p
-                //                             });
-                //                         }
-                //                     }
-                //                 }
-                //             }
-                //         }
-                //     }
-                // }
-
                 let screen_rect = Rect::new(
                     top_left_onscreen.0 as i32,
                     top_left_onscreen.1 as i32,
@@ -204,92 +381,37 @@ fn main() -> Result<(), String> {
                     SQUARE_W,
                 );
 
-                canvas.set_draw_color(square.colour(mouse_hit));
-                if state[(x, y)].content.is_some() && state[(x, y)].content.unwrap().en_passanteable
-                {
+                canvas.set_draw_color(square_colour(&square, mouse_hit));
+                if state.en_passant == Some((x, y)) {
                     canvas.set_draw_color(Color::RGB(140, 100, 250));
                 }
-                if state.selected_square.is_some() && state.selected_square.unwrap() == (x, y) {
+                if selection
+                    .as_ref()
+                    .is_some_and(|(picked, _)| *picked == Picked::Square((x, y)))
+                {
                     canvas.set_draw_color(Color::RGB(240, 200, 210));
                 }
                 canvas.fill_rect(screen_rect)?;
 
                 if let Some(piece) = square.content {
-                    let texture = match piece {
-                        Piece {
-                            kind: PieceKind::Pawn,
-                            colour: ChessColour::White,
-                            ..
-                        } => &tex_wp,
-                        Piece {
-                            kind: PieceKind::Rook,
-                            colour: ChessColour::White,
-                            ..
-                        } => &tex_wr,
-                        Piece {
-                            kind: PieceKind::Knight,
-                            colour: ChessColour::White,
-                            ..
-                        } => &tex_wn,
-                        Piece {
-                            kind: PieceKind::Bishop,
-                            colour: ChessColour::White,
-                            ..
-                        } => &tex_wb,
-                        Piece {
-                            kind: PieceKind::Queen,
-                            colour: ChessColour::White,
-                            ..
-                        } => &tex_wq,
-                        Piece {
-                            kind: PieceKind::King,
-                            colour: ChessColour::White,
-                            ..
-                        } => &tex_wk,
-
-                        Piece {
-                            kind: PieceKind::Pawn,
-                            colour: ChessColour::Black,
-                            ..
-                        } => &tex_bp,
-                        Piece {
-                            kind: PieceKind::Rook,
-                            colour: ChessColour::Black,
-                            ..
-                        } => &tex_br,
-                        Piece {
-                            kind: PieceKind::Knight,
-                            colour: ChessColour::Black,
-                            ..
-                        } => &tex_bn,
-                        Piece {
-                            kind: PieceKind::Bishop,
-                            colour: ChessColour::Black,
-                            ..
-                        } => &tex_bb,
-                        Piece {
-                            kind: PieceKind::Queen,
-                            colour: ChessColour::Black,
-                            ..
-                        } => &tex_bq,
-                        Piece {
-                            kind: PieceKind::King,
-                            colour: ChessColour::Black,
-                            ..
-                        } => &tex_bk,
-                    };
-
-                    canvas.copy(texture, None, screen_rect)?;
+                    canvas.copy(texture_of(piece), None, screen_rect)?;
                 }
             }
         }
 
         if let Some(text) = mouse_over_coord {
-            let drawn_text = if state.selected_square.is_none() {
-                text
-            } else {
-                let square = state[state.selected_square.unwrap()];
-                format!("{} -> {}", square.coord(), text)
+            let drawn_text = match &selection {
+                None => text,
+                Some((Picked::Square(selected), _)) => {
+                    format!("{} -> {}", state[*selected].coord(), text)
+                }
+                Some((Picked::Pocket(kind), _)) => {
+                    let piece = Piece {
+                        kind: *kind,
+                        colour: ChessColour::White,
+                    };
+                    format!("{}@ -> {}", piece.to_char(), text)
+                }
             };
             draw_text(
                 &drawn_text,
@@ -301,16 +423,9 @@ fn main() -> Result<(), String> {
             )?;
         }
 
-        if state.game_running {
+        if let Some(termination) = state.termination {
             draw_text(
-                &format!(
-                    "{} to play",
-                    if state.turn == ChessColour::White {
-                        "white"
-                    } else {
-                        "black"
-                    }
-                ),
+                &format!("{} ({})", termination, termination.result()),
                 &mut canvas,
                 &texture_creator,
                 &font,
@@ -319,7 +434,14 @@ fn main() -> Result<(), String> {
             )?;
         } else {
             draw_text(
-                "checkmate!",
+                &format!(
+                    "{} to play",
+                    if state.turn == ChessColour::White {
+                        "white"
+                    } else {
+                        "black"
+                    }
+                ),
                 &mut canvas,
                 &texture_creator,
                 &font,
@@ -328,54 +450,14 @@ fn main() -> Result<(), String> {
             )?;
         }
 
-        if let Some(coord) = state.selected_square {
+        if let Some((_, valid_moves)) = &selection {
             canvas.set_draw_color(Color::RGBA(50, 200, 20, 50));
 
-            let valid_moves = state.get_moves(coord, true);
-            for y in 0..8_u32 {
-                for x in 0..8_u32 {
-                    if valid_moves.contains(&ChessMove::dummy((x as u8, y as u8))) {
-                        let cx = (x * SQUARE_W + SQUARE_W / 2) as i32;
-                        let cy = ((7 - y) * SQUARE_W + SQUARE_W / 2) as i32;
-                        canvas.fill_rect(Rect::from_center(
-                            (cx, cy),
-                            SQUARE_W / 3,
-                            SQUARE_W / 3,
-                        ))?;
-                    }
-                }
-            }
-        }
-
-        // checkmate test
-        for c in &[ChessColour::White, ChessColour::Black] {
-            if state.is_in_check(*c) {
-                // test every piece's every move and check if the king is still in check
-                let no_unchecking_moves = state
-                    .squares
-                    .into_iter()
-                    .filter(|s| {
-                        if let Some(p) = s.content {
-                            p.colour == *c
-                        } else {
-                            false
-                        }
-                    })
-                    .map(|s| (s.coords, state.get_moves(s.coords, true)))
-                    .map(|(square, moves)| {
-                        // we will test if making the move still leaves the king in check
-                        moves.into_iter().all(|m| {
-                            let mut test_board = state.clone();
-                            test_board.make_move(square, m);
-
-                            test_board.is_in_check(*c)
-                        })
-                    })
-                    .all(|b| b);
-
-                if no_unchecking_moves {
-                    state.game_running = false;
-                }
+            for valid_move in valid_moves {
+                let (x, y) = (valid_move.dst.0 as u32, valid_move.dst.1 as u32);
+                let cx = (x * SQUARE_W + SQUARE_W / 2) as i32;
+                let cy = ((7 - y) * SQUARE_W + SQUARE_W / 2) as i32;
+                canvas.fill_rect(Rect::from_center((cx, cy), SQUARE_W / 3, SQUARE_W / 3))?;
             }
         }
 
@@ -410,6 +492,77 @@ fn main() -> Result<(), String> {
             3 * MARGIN,
         )?;
 
+        // the side to move loses once their time is up, and the clocks stop with the game
+        if let Some(clock) = &clock {
+            let mut clock = clock.lock().unwrap();
+            let now = Instant::now();
+            if let (Some(col), None) = (clock.flagged(now), state.termination) {
+                state.flag(col);
+            }
+            if state.termination.is_some() {
+                clock.stop(now);
+            }
+
+            draw_text(
+                &format!(
+                    "white {}   black {}",
+                    format_clock(clock.time_left(ChessColour::White, now)),
+                    format_clock(clock.time_left(ChessColour::Black, now))
+                ),
+                &mut canvas,
+                &texture_creator,
+                &font,
+                BOARD_EDGE + MARGIN,
+                SCREEN_H as i32 - 4 * MARGIN,
+            )?;
+        }
+
+        // the piece a human player has picked from their pocket is highlighted
+        let mut move_list_top = 5 * MARGIN;
+        let mut move_list_len = 14;
+        if state.variant.has_pockets() {
+            for (row, colour) in POCKET_ROWS.into_iter().enumerate() {
+                for (slot, kind) in POCKET_KINDS.into_iter().enumerate() {
+                    let rect = pocket_rect(row, slot);
+                    let picked = colour == state.turn
+                        && selection
+                            .as_ref()
+                            .is_some_and(|(picked, _)| *picked == Picked::Pocket(kind));
+                    if picked {
+                        canvas.set_draw_color(Color::RGB(240, 200, 210));
+                        canvas.fill_rect(rect)?;
+                    }
+
+                    let held = state.pockets[colour.index()][kind.index()];
+                    if held > 0 {
+                        canvas.copy(texture_of(Piece { kind, colour }), None, rect)?;
+                        draw_text(
+                            &held.to_string(),
+                            &mut canvas,
+                            &texture_creator,
+                            &font,
+                            rect.right() + 4,
+                            rect.y() + 6,
+                        )?;
+                    }
+                }
+            }
+            move_list_top += 2 * (POCKET_W as i32 + 4);
+            move_list_len -= 3;
+        }
+
+        // only the most recent moves fit in the side panel
+        for (i, line) in move_list.iter().rev().take(move_list_len).rev().enumerate() {
+            draw_text(
+                line,
+                &mut canvas,
+                &texture_creator,
+                &font,
+                BOARD_EDGE + MARGIN,
+                move_list_top + 20 * i as i32,
+            )?;
+        }
+
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. }
@@ -441,40 +594,82 @@ fn main() -> Result<(), String> {
                 } => {
                     state.next_promotor = Knight;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F),
+                    ..
+                } => {
+                    println!("{}", state.to_fen());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    ..
+                } => {
+                    let mut game = PgnGame::from_state(&state);
+                    if let Some(tc) = &config.time_control {
+                        game.set_tag("TimeControl", &tc.to_string());
+                    }
+                    println!("{game}");
+                }
+                // clicks only go to a human player, and only when it's their turn
+                Event::MouseButtonDown {
+                    mouse_btn: MouseButton::Left,
+                    x,
+                    y,
+                    ..
+                } if state.termination.is_none() => {
+                    if let Some(human) = &players[state.turn.index()].human {
+                        if let Some(coord) = board_coord(x, y) {
+                            human.click(Click {
+                                coord,
+                                promote_to: state.next_promotor,
+                            });
+                        } else if let Some((colour, kind)) = pocket_slot(x, y) {
+                            // only their own pocket, and only in crazyhouse
+                            if colour == state.turn && state.variant.has_pockets() {
+                                human.pick_from_pocket(kind);
+                            }
+                        }
+                    }
+                }
+                // resigns on behalf of whoever is to move
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } if state.termination.is_none() => {
+                    state.resign(state.turn);
+                }
 
                 _ => {}
             }
         }
 
-        // check if the player whose turn it is has a new move ready
-
-        match state.turn {
-            ChessColour::White => {
-                if let Ok(new_white_move) = rx_from_white_player.try_recv() {
-                    // the white player has a new move ready
-                    state.make_move(new_white_move.coord, new_white_move.move_data.clone());
-
-                    // it now needs to be echoed to the other players
-                    tx_to_white_player.send(new_white_move.clone());
-                    tx_to_black_player.send(new_white_move.clone());
-                }
-            }
-            ChessColour::Black => {
-                if let Ok(new_black_move) = rx_from_black_player.try_recv() {
-                    // the white player has a new move ready
-                    state.make_move(new_black_move.coord, new_black_move.move_data.clone());
-
-                    // it now needs to be echoed to the other players
-                    tx_to_white_player.send(new_black_move.clone());
-                    tx_to_black_player.send(new_black_move.clone());
-                }
+        // check if the player whose turn it is has a new move ready.
+        // moves that arrive after the game has ended are ignored
+        let rx_from_player = &players[state.turn.index()].rx_from_player;
+        if let (None, Ok(new_move)) = (state.termination, rx_from_player.try_recv()) {
+            // a move only counts if it was made before the flag fell
+            let in_time = clock
+                .as_ref()
+                .is_none_or(|clock| clock.lock().unwrap().press(Instant::now()));
+
+            if in_time {
+                record_san(&mut move_list, &mut state, new_move.move_data);
+                state.make_move(new_move.move_data);
+                state.update_termination();
+
+                // it now needs to be echoed to the other players. one that has hung up is
+                // resigned for below
+                let _ = tx_to_white_player.send(new_move);
+                let _ = tx_to_black_player.send(new_move);
+            } else {
+                state.flag(state.turn);
             }
         }
+        // e.g. an engine that failed, which may happen on the other side's move too
+        resign_stopped_players(&mut state, &players);
 
         canvas.present();
         std::thread::sleep(Duration::new(0, 1_000_000_000u32 / 60));
-
-        state.mouse_pressed_previous = md;
     }
 
     Ok(())
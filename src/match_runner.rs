@@ -0,0 +1,593 @@
+use crate::{
+    clock::Clock,
+    config::{Chess960Start, Config, ConfigError, PlayerConfig},
+    pgn::PgnGame,
+    piece::ChessColour,
+    state::State,
+    stats::{Pentanomial, Sprt, SprtResult, Trinomial},
+    termination::{GameResult, Termination},
+    variant::Variant,
+};
+
+use std::fmt;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub const MATCH_USAGE: &str = "\
+options:
+  --first <player>    random, search, search:<depth> or uci:<command> (default search)
+  --second <player>   the same (default random). the two swap colours after every game
+  --games <n>         how many games to play (default 10)
+  --openings <file>   FENs or EPD lines to start from, one per line. every opening is played
+                      twice, once with either player as white
+  --pgn <file>        where the games are written (default match.pgn)
+  --sprt <bounds>     <elo0>,<elo1>[,<alpha>,<beta>]: stops early once it is clear whether the
+                      first player is elo0 or elo1 stronger, e.g. 0,5
+  --variant, --fen, --chess960, --tc, --seed and --config as for schaak itself";
+
+// a series of games between two players
+#[derive(Clone, Debug, PartialEq)]
+pub struct MatchConfig {
+    // the first player is white in the first game
+    pub config: Config,
+    pub games: u32,
+    pub openings: Vec<String>,
+    pub pgn_path: String,
+    pub sprt: Option<Sprt>,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        MatchConfig {
+            config: Config {
+                players: [PlayerConfig::Search { depth: None }, PlayerConfig::Random],
+                // nobody is watching, so players can go as fast as they like
+                tick_interval: Some(Duration::from_millis(1)),
+                ..Config::default()
+            },
+            games: 10,
+            openings: Vec::new(),
+            pgn_path: String::from("match.pgn"),
+            sprt: None,
+        }
+    }
+}
+
+impl MatchConfig {
+    // --<key> <value> pairs, as listed in MATCH_USAGE
+    pub fn from_args(args: &[String]) -> Result<Self, ConfigError> {
+        let mut match_config = MatchConfig::default();
+        let mut config_args = Vec::new();
+        let mut openings = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let key = arg.trim_start_matches("--");
+            let value = || {
+                args.clone()
+                    .next()
+                    .cloned()
+                    .ok_or_else(|| ConfigError::MissingValue(key.to_string()))
+            };
+            let invalid =
+                || ConfigError::InvalidValue(key.to_string(), value().unwrap_or_default());
+            match arg.as_str() {
+                "--first" => config_args.extend([String::from("--white"), value()?]),
+                "--second" => config_args.extend([String::from("--black"), value()?]),
+                "--games" => match_config.games = value()?.parse().map_err(|_| invalid())?,
+                "--openings" => {
+                    let path = value()?;
+                    let text = std::fs::read_to_string(&path)
+                        .map_err(|e| ConfigError::Unreadable(path, e.to_string()))?;
+                    openings = Some(text);
+                }
+                "--pgn" => match_config.pgn_path = value()?,
+                "--sprt" => match_config.sprt = Some(Sprt::parse(&value()?).ok_or_else(invalid)?),
+                // everything else is left to Config
+                _ => {
+                    config_args.push(arg.clone());
+                    if let Ok(value) = value() {
+                        config_args.push(value);
+                    }
+                }
+            }
+            args.next();
+        }
+        match_config.config.apply_args(&config_args)?;
+        // a random Chess960 start position is picked once, for every game of the match, before
+        // the seed is changed from game to game
+        match_config.config.chess960 = match_config
+            .config
+            .chess960_position()
+            .map(Chess960Start::Position);
+        // only read now that the variant is known
+        if let Some(text) = openings {
+            match_config.openings = parse_openings(&text, match_config.config.variant)?;
+        }
+
+        for (key, player) in ["first", "second"].iter().zip(&match_config.config.players) {
+            if *player == PlayerConfig::Human {
+                return Err(ConfigError::InvalidValue(
+                    key.to_string(),
+                    player.to_string(),
+                ));
+            }
+        }
+        Ok(match_config)
+    }
+
+    // the set-up of the given game, counting from 0: the players swap colours every game,
+    // and both games of a pair start from the same opening
+    pub fn game(&self, game: u32) -> Result<(Config, State), ConfigError> {
+        let mut config = self.config.clone();
+        if game % 2 == 1 {
+            config.players.swap(0, 1);
+        }
+        // random players shouldn't play the same game every time
+        config.seed = config.seed.map(|s| s.wrapping_add(2 * game as u64));
+
+        let state = match self.openings.len() {
+            0 => config.starting_position()?,
+            n => State::from_fen_with_variant(
+                &self.openings[(game / 2) as usize % n],
+                config.variant,
+            )
+            .map_err(ConfigError::InvalidFen)?,
+        };
+        Ok((config, state))
+    }
+}
+
+// FENs, or EPD lines whose first four fields are used, one per line. # starts a comment line
+pub fn parse_openings(text: &str, variant: Variant) -> Result<Vec<String>, ConfigError> {
+    let mut openings = Vec::new();
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        let counters = fields
+            .get(4..6)
+            .filter(|counters| counters.iter().all(|c| c.parse::<u32>().is_ok()));
+        let fen = match counters {
+            Some(_) => fields[..6].join(" "),
+            None => format!("{} 0 1", fields[..fields.len().min(4)].join(" ")),
+        };
+        State::from_fen_with_variant(&fen, variant).map_err(ConfigError::InvalidFen)?;
+        openings.push(fen);
+    }
+    Ok(openings)
+}
+
+// plays one game between the configured players without a window, and returns how it ended
+pub fn play_game(config: &Config, mut state: State) -> Result<State, ConfigError> {
+    let clock = config
+        .time_control
+        .clone()
+        .map(|tc| Arc::new(Mutex::new(Clock::new(tc))));
+    let (tx_to_white_player, rx_to_white_player) = mpsc::channel();
+    let (tx_to_black_player, rx_to_black_player) = mpsc::channel();
+    let players = [
+        config.spawn_player(
+            ChessColour::White,
+            clock.as_ref(),
+            rx_to_white_player,
+            &state,
+        )?,
+        config.spawn_player(
+            ChessColour::Black,
+            clock.as_ref(),
+            rx_to_black_player,
+            &state,
+        )?,
+    ];
+
+    state.update_termination();
+    if let Some(clock) = &clock {
+        clock.lock().unwrap().start(state.turn, Instant::now());
+    }
+
+    while state.termination.is_none() {
+        // waits a little at a time, so that the clock is checked in between
+        let rx_from_player = &players[state.turn.index()].rx_from_player;
+        let new_move = match rx_from_player.recv_timeout(Duration::from_millis(10)) {
+            Ok(new_move) => new_move,
+            Err(RecvTimeoutError::Timeout) => {
+                let flagged = clock
+                    .as_ref()
+                    .and_then(|c| c.lock().unwrap().flagged(Instant::now()));
+                if let Some(col) = flagged {
+                    state.flag(col);
+                }
+                continue;
+            }
+            // the player failed or its thread panicked, which is as good as giving up
+            Err(RecvTimeoutError::Disconnected) => {
+                state.resign(state.turn);
+                break;
+            }
+        };
+
+        let in_time = clock
+            .as_ref()
+            .is_none_or(|clock| clock.lock().unwrap().press(Instant::now()));
+        if !in_time {
+            state.flag(state.turn);
+            break;
+        }
+
+        state.make_move(new_move.move_data);
+        state.update_termination();
+        // a player that has hung up is done anyway
+        let _ = tx_to_white_player.send(new_move);
+        let _ = tx_to_black_player.send(new_move);
+    }
+
+    Ok(state)
+}
+
+// the game record as written by the match runner
+pub fn game_record(config: &Config, round: u32, state: &State) -> PgnGame {
+    let mut game = PgnGame::from_state(state);
+    game.set_tag("Event", "schaak match");
+    game.set_tag("Round", &round.to_string());
+    game.set_tag("White", &config.players[0].to_string());
+    game.set_tag("Black", &config.players[1].to_string());
+    if let Some(tc) = &config.time_control {
+        game.set_tag("TimeControl", &tc.to_string());
+    }
+    if let Some(termination) = state.termination {
+        let kind = match termination {
+            Termination::Timeout { .. } => "time forfeit",
+            _ => "normal",
+        };
+        game.set_tag("Termination", kind);
+    }
+    game
+}
+
+// the results so far, as seen by the first player
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct MatchSummary {
+    // 1, 0.5 or 0 per game, in the order they were played
+    pub scores: Vec<f64>,
+    // how often each kind of termination happened, in order of first occurrence
+    pub terminations: Vec<(&'static str, u32)>,
+    pub plies: u64,
+    // the test the match was run with, if any
+    pub sprt: Option<Sprt>,
+}
+
+impl MatchSummary {
+    // the game has to be finished
+    pub fn add(&mut self, first_colour: ChessColour, state: &State) {
+        let termination = state.termination.expect("the game isn't over yet");
+        let score = match termination.result() {
+            GameResult::Draw => 0.5,
+            result if result == GameResult::win_for(first_colour) => 1.0,
+            _ => 0.0,
+        };
+        self.scores.push(score);
+
+        match self
+            .terminations
+            .iter_mut()
+            .find(|(reason, _)| *reason == termination.reason())
+        {
+            Some((_, count)) => *count += 1,
+            None => self.terminations.push((termination.reason(), 1)),
+        }
+        self.plies += state.history.len() as u64;
+    }
+
+    pub fn games(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn wins(&self) -> usize {
+        self.scores.iter().filter(|s| **s == 1.0).count()
+    }
+
+    pub fn draws(&self) -> usize {
+        self.scores.iter().filter(|s| **s == 0.5).count()
+    }
+
+    pub fn losses(&self) -> usize {
+        self.scores.iter().filter(|s| **s == 0.0).count()
+    }
+
+    pub fn trinomial(&self) -> Trinomial {
+        Trinomial {
+            wins: self.wins() as u32,
+            draws: self.draws() as u32,
+            losses: self.losses() as u32,
+        }
+    }
+
+    // games are paired up in the order they were played, as each pair shares an opening.
+    // an unfinished pair is left out
+    pub fn pentanomial(&self) -> Pentanomial {
+        let mut pentanomial = Pentanomial::default();
+        for pair in self.scores.chunks_exact(2) {
+            pentanomial.add(pair[0], pair[1]);
+        }
+        pentanomial
+    }
+
+    pub fn sprt_result(&self) -> Option<SprtResult> {
+        Some(self.sprt?.result(&self.pentanomial()))
+    }
+
+    // in full moves
+    pub fn average_length(&self) -> f64 {
+        if self.scores.is_empty() {
+            0.0
+        } else {
+            self.plies as f64 / 2.0 / self.games() as f64
+        }
+    }
+}
+
+impl fmt::Display for MatchSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "games: {}, first player +{} ={} -{} ({}/{})",
+            self.games(),
+            self.wins(),
+            self.draws(),
+            self.losses(),
+            self.scores.iter().sum::<f64>(),
+            self.games()
+        )?;
+        let terminations = self
+            .terminations
+            .iter()
+            .map(|(reason, count)| format!("{reason} {count}"))
+            .collect::<Vec<_>>();
+        writeln!(f, "terminations: {}", terminations.join(", "))?;
+        write!(f, "average length: {:.1} moves", self.average_length())?;
+
+        let trinomial = self.trinomial();
+        if let Some(elo) = trinomial.elo() {
+            write!(f, "\nelo: {elo} (95%), los {:.1}%", 100.0 * trinomial.los())?;
+        }
+        let pentanomial = self.pentanomial();
+        if let Some(elo) = pentanomial.elo() {
+            write!(f, "\npentanomial: {:?}, elo {elo}", pentanomial.0)?;
+        }
+        if let Some(sprt) = self.sprt {
+            let (lower, upper) = sprt.bounds();
+            let verdict = match sprt.result(&pentanomial) {
+                SprtResult::AcceptH0 => "H0 accepted",
+                SprtResult::AcceptH1 => "H1 accepted",
+                SprtResult::Continue => "inconclusive",
+            };
+            write!(
+                f,
+                "\nsprt [{}, {}]: llr {:.2} ({lower:.2}, {upper:.2}), {verdict}",
+                sprt.elo0,
+                sprt.elo1,
+                sprt.llr(&pentanomial)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// plays the match's games one after the other, handing each one to on_game as it finishes
+// along with its number, counting from 1. with an SPRT, stops as soon as that has a result
+pub fn run_match(
+    match_config: &MatchConfig,
+    mut on_game: impl FnMut(u32, &PgnGame),
+) -> Result<MatchSummary, ConfigError> {
+    let mut summary = MatchSummary {
+        sprt: match_config.sprt,
+        ..MatchSummary::default()
+    };
+    for game in 0..match_config.games {
+        let (config, state) = match_config.game(game)?;
+        let state = play_game(&config, state)?;
+
+        let first_colour = if game % 2 == 0 {
+            ChessColour::White
+        } else {
+            ChessColour::Black
+        };
+        summary.add(first_colour, &state);
+        on_game(game + 1, &game_record(&config, game + 1, &state));
+
+        if summary
+            .sprt_result()
+            .is_some_and(|r| r != SprtResult::Continue)
+        {
+            break;
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        text.split(' ').map(String::from).collect()
+    }
+
+    #[test]
+    fn openings() {
+        let text = "\
+            # a comment\n\
+            rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1\n\
+            \n\
+            rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq - bm d5; id \"queen's pawn\";\n";
+        assert_eq!(
+            parse_openings(text, Variant::Standard).unwrap(),
+            [
+                "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+                "rnbqkbnr/pppppppp/8/8/3P4/8/PPP1PPPP/RNBQKBNR b KQkq - 0 1"
+            ]
+        );
+        assert!(matches!(
+            parse_openings("8/8/8 w - -", Variant::Standard),
+            Err(ConfigError::InvalidFen(_))
+        ));
+    }
+
+    #[test]
+    fn match_config() {
+        let mut match_config =
+            MatchConfig::from_args(&args("--second search:2 --games 4 --seed 10")).unwrap();
+        assert_eq!(match_config.games, 4);
+        assert_eq!(match_config.sprt, None);
+        assert_eq!(
+            match_config.config.players,
+            [
+                PlayerConfig::Search { depth: None },
+                PlayerConfig::Search { depth: Some(2) }
+            ]
+        );
+
+        match_config.openings = vec![String::from("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")];
+        let (config, state) = match_config.game(3).unwrap();
+        assert_eq!(config.players[0], PlayerConfig::Search { depth: Some(2) });
+        assert_eq!(config.seed, Some(16));
+        assert_eq!(state.to_fen(), "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1");
+
+        // a random Chess960 start position is shared by every game, and by every run with the seed
+        let chess960 = MatchConfig::from_args(&args("--chess960 random --seed 4")).unwrap();
+        let fen = chess960.game(0).unwrap().1.to_fen();
+        assert_eq!(chess960.game(3).unwrap().1.to_fen(), fen);
+        assert_eq!(
+            MatchConfig::from_args(&args("--seed 4 --chess960 random")).unwrap(),
+            chess960
+        );
+
+        assert_eq!(
+            MatchConfig::from_args(&args("--first human")),
+            Err(ConfigError::InvalidValue(
+                String::from("first"),
+                String::from("human")
+            ))
+        );
+        assert_eq!(
+            MatchConfig::from_args(&args("--games many")),
+            Err(ConfigError::InvalidValue(
+                String::from("games"),
+                String::from("many")
+            ))
+        );
+    }
+
+    #[test]
+    fn random_games() {
+        crate::player::set_verbose(false);
+        // quick to finish: the pawn either promotes or gets taken
+        let match_config = MatchConfig {
+            games: 2,
+            openings: vec![String::from("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")],
+            config: Config {
+                players: [PlayerConfig::Random, PlayerConfig::Random],
+                seed: Some(1),
+                ..MatchConfig::default().config
+            },
+            ..MatchConfig::default()
+        };
+
+        let mut records = Vec::new();
+        let summary = run_match(&match_config, |round, game| {
+            records.push((round, game.to_string()));
+        })
+        .unwrap();
+
+        assert_eq!(summary.games(), 2);
+        assert_eq!(summary.wins() + summary.draws() + summary.losses(), 2);
+        assert_eq!(records.len(), 2);
+        assert!(records[1].1.contains("[Round \"2\"]"));
+        assert!(records[1]
+            .1
+            .contains("[FEN \"4k3/8/8/8/8/8/4P3/4K3 w - - 0 1\"]"));
+    }
+
+    #[test]
+    fn sprt_stops_the_match() {
+        crate::player::set_verbose(false);
+        // a depth 2 search mates a random mover in a handful of moves
+        let match_config = MatchConfig {
+            games: 100,
+            config: Config {
+                players: [
+                    PlayerConfig::Search { depth: Some(2) },
+                    PlayerConfig::Random,
+                ],
+                seed: Some(3),
+                ..MatchConfig::default().config
+            },
+            sprt: Sprt::parse("0,100"),
+            ..MatchConfig::default()
+        };
+
+        let summary = run_match(&match_config, |_, _| {}).unwrap();
+        assert!(summary.games() < 100);
+        assert_eq!(summary.sprt_result(), Some(SprtResult::AcceptH1));
+        assert!(summary.to_string().contains("H1 accepted"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_engines_resign() {
+        use crate::uci_engine::tests::stand_in_engine;
+
+        crate::player::set_verbose(false);
+        // without a time control, only the engine failing ends the game
+        let illegal = stand_in_engine("resigns-illegal", &["bestmove e2e5"]);
+        let hung = stand_in_engine("resigns-hung", &[]);
+        for (engine, colour) in [(illegal, ChessColour::White), (hung, ChessColour::Black)] {
+            let mut config = Config {
+                players: [PlayerConfig::Random, PlayerConfig::Random],
+                ..MatchConfig::default().config
+            };
+            config.players[colour.index()] = PlayerConfig::Uci {
+                command: engine.command.clone(),
+            };
+
+            let state = play_game(&config, State::new()).unwrap();
+            assert_eq!(
+                state.termination,
+                Some(Termination::Resignation {
+                    winner: colour.flip()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn summaries() {
+        let mut summary = MatchSummary::default();
+        let mut mated = State::from_fen("R6k/8/7K/8/8/8/8/8 b - - 0 1").unwrap();
+        mated.update_termination();
+        summary.add(ChessColour::White, &mated);
+        summary.add(ChessColour::Black, &mated);
+        let mut drawn = State::from_fen("8/8/4k3/8/8/4K3/8/8 w - - 0 1").unwrap();
+        drawn.update_termination();
+        summary.add(ChessColour::White, &drawn);
+
+        assert_eq!(
+            (summary.wins(), summary.draws(), summary.losses()),
+            (1, 1, 1)
+        );
+        assert_eq!(summary.pentanomial().0, [0, 0, 1, 0, 0]);
+        let text = summary.to_string();
+        assert!(text.starts_with(
+            "games: 3, first player +1 =1 -1 (1.5/3)\n\
+             terminations: checkmate 2, insufficient material 1\n\
+             average length: 0.0 moves\n\
+             elo: +0.0 ± "
+        ));
+        assert!(text.contains("los 50.0%"));
+        assert!(text.contains("\npentanomial: [0, 0, 1, 0, 0], elo +0.0 ± "));
+    }
+}
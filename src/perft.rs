@@ -0,0 +1,152 @@
+use crate::{chess_move::ChessMove, state::State};
+
+impl State {
+    // counts the leaf nodes of the legal move tree at the given depth,
+    // which can be compared against published numbers to validate move generation
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+
+        let moves = self.get_all_moves_for_colour(self.turn);
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+
+        moves
+            .into_iter()
+            .map(|m| {
+                self.make_move(m);
+                let nodes = self.perft(depth - 1);
+                self.unmake_move();
+                nodes
+            })
+            .sum()
+    }
+
+    // perft split up per root move, for narrowing down where a count goes wrong
+    pub fn divide(&mut self, depth: u32) -> Vec<(ChessMove, u64)> {
+        self.get_all_moves_for_colour(self.turn)
+            .into_iter()
+            .map(|m| {
+                self.make_move(m);
+                let nodes = self.perft(depth.saturating_sub(1));
+                self.unmake_move();
+                (m, nodes)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fen::STARTING_FEN;
+    use crate::state::State;
+
+    // positions and counts from https://www.chessprogramming.org/Perft_Results
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+    const POSITION_3: &str = "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1";
+    const POSITION_4: &str = "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1";
+    const POSITION_5: &str = "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8";
+    const POSITION_6: &str =
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10";
+
+    fn assert_perft(fen: &str, expected: &[u64]) {
+        let mut state = State::from_fen(fen).unwrap();
+        for (depth, nodes) in expected.iter().enumerate() {
+            assert_eq!(
+                state.perft(depth as u32 + 1),
+                *nodes,
+                "perft({}) of {fen}",
+                depth + 1
+            );
+        }
+        // perft has to leave the position exactly as it found it
+        assert_eq!(state.to_fen(), fen);
+    }
+
+    #[test]
+    fn start_position() {
+        assert_perft(STARTING_FEN, &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn kiwipete() {
+        assert_perft(KIWIPETE, &[48, 2039, 97862]);
+    }
+
+    #[test]
+    fn position_3() {
+        assert_perft(POSITION_3, &[14, 191, 2812, 43238]);
+    }
+
+    #[test]
+    fn position_4() {
+        assert_perft(POSITION_4, &[6, 264, 9467]);
+    }
+
+    #[test]
+    fn position_5() {
+        assert_perft(POSITION_5, &[44, 1486, 62379]);
+    }
+
+    #[test]
+    fn position_6() {
+        assert_perft(POSITION_6, &[46, 2079, 89890]);
+    }
+
+    #[test]
+    fn chess960() {
+        // from https://www.chessprogramming.org/Chess960_Perft_Results
+        for (fen, expected) in [
+            (
+                "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+                [21, 528, 12189],
+            ),
+            (
+                "2nnrbkr/p1qppppp/8/1ppb4/6PP/3PP3/PPP2P2/BQNNRBKR w HEhe - 1 9",
+                [21, 807, 18002],
+            ),
+            (
+                "1rqbkrbn/1ppppp1p/1n6/p1N3p1/8/2P4P/PP1PPPP1/1RQBKRBN w FBfb - 0 9",
+                [29, 502, 14569],
+            ),
+        ] {
+            let mut state = State::from_fen(fen).unwrap();
+            for (depth, nodes) in expected.iter().enumerate() {
+                assert_eq!(
+                    state.perft(depth as u32 + 1),
+                    *nodes,
+                    "perft({}) of {fen}",
+                    depth + 1
+                );
+            }
+            assert_eq!(state.to_shredder_fen(), fen);
+        }
+    }
+
+    // too slow for every test run; use `cargo test --release -- --ignored`
+    #[test]
+    #[ignore]
+    fn deep_perft() {
+        for (fen, depth, nodes) in [
+            (STARTING_FEN, 5, 4865609),
+            (KIWIPETE, 4, 4085603),
+            (POSITION_3, 5, 674624),
+            (POSITION_4, 4, 422333),
+            (POSITION_5, 4, 2103487),
+            (POSITION_6, 4, 3894594),
+        ] {
+            let mut state = State::from_fen(fen).unwrap();
+            assert_eq!(state.perft(depth), nodes, "perft({depth}) of {fen}");
+        }
+    }
+
+    #[test]
+    fn divide_sums_to_perft() {
+        let mut state = State::from_fen(KIWIPETE).unwrap();
+        let divided = state.divide(2);
+        assert_eq!(divided.len(), 48);
+        assert_eq!(divided.iter().map(|(_, nodes)| nodes).sum::<u64>(), 2039);
+    }
+}
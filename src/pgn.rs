@@ -0,0 +1,655 @@
+use crate::{
+    chess_move::ChessMove, fen::FenError, piece::ChessColour, san::SanError, state::State,
+    termination::GameResult, variant::Variant,
+};
+
+use std::fmt;
+
+// the tags every exported game starts with, in the order the PGN standard asks for
+const SEVEN_TAG_ROSTER: [(&str, &str); 7] = [
+    ("Event", "?"),
+    ("Site", "?"),
+    ("Date", "????.??.??"),
+    ("Round", "?"),
+    ("White", "?"),
+    ("Black", "?"),
+    ("Result", "*"),
+];
+
+// exported movetext lines are kept below 80 characters
+const MAX_LINE_LENGTH: usize = 79;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PgnError {
+    MalformedTag(String),
+    UnterminatedComment,
+    UnbalancedVariation,
+    UnexpectedToken(String),
+    InvalidFen(FenError),
+    InvalidMove {
+        fullmove_number: u32,
+        turn: ChessColour,
+        error: SanError,
+    },
+}
+
+impl fmt::Display for PgnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgnError::MalformedTag(t) => write!(f, "malformed tag pair \"{t}\""),
+            PgnError::UnterminatedComment => write!(f, "comment is missing its closing brace"),
+            PgnError::UnbalancedVariation => write!(f, "unbalanced parentheses around a variation"),
+            PgnError::UnexpectedToken(t) => write!(f, "unexpected \"{t}\" in movetext"),
+            PgnError::InvalidFen(e) => write!(f, "invalid FEN tag: {e}"),
+            PgnError::InvalidMove {
+                fullmove_number,
+                turn,
+                error,
+            } => {
+                let dots = if *turn == ChessColour::White {
+                    "."
+                } else {
+                    "..."
+                };
+                write!(f, "move {fullmove_number}{dots}: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PgnError {}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PgnMove {
+    pub chess_move: ChessMove,
+    // numeric annotation glyphs; suffixes like ! and ?! are read as $1 and $6
+    pub nags: Vec<u8>,
+    pub comment: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct PgnGame {
+    // in the order they are written, starting with the seven tag roster on export
+    pub tags: Vec<(String, String)>,
+    // comment before the first move
+    pub comment: Option<String>,
+    pub moves: Vec<PgnMove>,
+    // None for a game that is still going on, written as *
+    pub result: Option<GameResult>,
+    // taken from the FEN tag if there is one
+    pub starting_position: State,
+}
+
+impl PgnGame {
+    // a record of everything played so far in the given state
+    pub fn from_state(state: &State) -> Self {
+        let mut starting_position = state.clone();
+        while starting_position.unmake_move().is_some() {}
+        starting_position.termination = None;
+
+        let mut game = PgnGame {
+            tags: SEVEN_TAG_ROSTER
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect(),
+            comment: None,
+            moves: state
+                .history
+                .iter()
+                .map(|performed| PgnMove {
+                    chess_move: performed.chess_move,
+                    nags: Vec::new(),
+                    comment: None,
+                })
+                .collect(),
+            result: state.termination.map(|t| t.result()),
+            starting_position,
+        };
+
+        if let Some(result) = game.result {
+            game.set_tag("Result", &result.to_string());
+        }
+        let variant = game.starting_position.variant;
+        if variant != Variant::Standard {
+            game.set_tag("Variant", variant.name());
+        } else if game.starting_position.chess960 {
+            game.set_tag("Variant", "Chess960");
+        }
+        let fen = game.starting_position.to_fen();
+        if fen != State::new_variant(variant).to_fen() {
+            game.set_tag("SetUp", "1");
+            game.set_tag("FEN", &fen);
+        }
+
+        game
+    }
+
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // replaces the value if the tag is already there, otherwise adds it at the end
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(n, _)| n == name) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string())),
+        }
+    }
+
+    // the starting position followed by the position after every move. each one carries the
+    // history up to that point, so it can be stepped back through with unmake_move
+    pub fn positions(&self) -> Vec<State> {
+        let mut state = self.starting_position.clone();
+        let mut positions = vec![state.clone()];
+        for pgn_move in &self.moves {
+            state.make_move(pgn_move.chess_move);
+            positions.push(state.clone());
+        }
+        positions
+    }
+}
+
+impl fmt::Display for PgnGame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, value) in &self.tags {
+            let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+            writeln!(f, "[{name} \"{escaped}\"]")?;
+        }
+        writeln!(f)?;
+
+        let mut tokens = Vec::new();
+        if let Some(comment) = &self.comment {
+            tokens.push(format!("{{{comment}}}"));
+        }
+
+        // black's moves only get a number of their own if something came between them and white's
+        let mut state = self.starting_position.clone();
+        let mut needs_number = true;
+        for pgn_move in &self.moves {
+            if state.turn == ChessColour::White {
+                tokens.push(format!("{}.", state.fullmove_number));
+            } else if needs_number {
+                tokens.push(format!("{}...", state.fullmove_number));
+            }
+            tokens.push(state.move_to_san(pgn_move.chess_move));
+            tokens.extend(pgn_move.nags.iter().map(|nag| format!("${nag}")));
+            needs_number = false;
+            if let Some(comment) = &pgn_move.comment {
+                tokens.push(format!("{{{comment}}}"));
+                needs_number = true;
+            }
+            state.make_move(pgn_move.chess_move);
+        }
+        tokens.push(match self.result {
+            Some(result) => result.to_string(),
+            None => String::from("*"),
+        });
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > MAX_LINE_LENGTH {
+                writeln!(f, "{line}")?;
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        writeln!(f, "{line}")
+    }
+}
+
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Nag(u8),
+    VariationStart,
+    VariationEnd,
+    // moves and results, with move numbers already taken off
+    Symbol(String),
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, PgnError> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    let mut line_start = true;
+
+    while let Some(c) = chars.next() {
+        match c {
+            // a % in the first column escapes the rest of the line
+            '%' if line_start => {
+                chars.by_ref().find(|c| *c == '\n');
+                continue;
+            }
+            '\n' => {
+                line_start = true;
+                continue;
+            }
+            c if c.is_whitespace() => {}
+            '[' => {
+                // the closing bracket only counts outside of the quoted value
+                let mut raw = String::new();
+                let mut in_string = false;
+                loop {
+                    match chars.next() {
+                        Some(']') if !in_string => break,
+                        Some('\\') if in_string => {
+                            raw.push('\\');
+                            raw.extend(chars.next());
+                        }
+                        Some(c) => {
+                            in_string ^= c == '"';
+                            raw.push(c);
+                        }
+                        None => return Err(PgnError::MalformedTag(raw)),
+                    }
+                }
+                tokens.push(parse_tag(&raw).ok_or(PgnError::MalformedTag(raw))?);
+            }
+            '{' => {
+                let mut comment = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => comment.push(c),
+                        None => return Err(PgnError::UnterminatedComment),
+                    }
+                }
+                tokens.push(Token::Comment(comment.trim().to_string()));
+            }
+            ';' => {
+                let comment = chars
+                    .by_ref()
+                    .take_while(|c| *c != '\n')
+                    .collect::<String>();
+                tokens.push(Token::Comment(comment.trim().to_string()));
+                line_start = true;
+                continue;
+            }
+            '(' => tokens.push(Token::VariationStart),
+            ')' => tokens.push(Token::VariationEnd),
+            '$' => {
+                let mut digits = String::new();
+                while let Some(d) = chars.next_if(char::is_ascii_digit) {
+                    digits.push(d);
+                }
+                let nag = digits
+                    .parse()
+                    .map_err(|_| PgnError::UnexpectedToken(format!("${digits}")))?;
+                tokens.push(Token::Nag(nag));
+            }
+            _ => {
+                let mut symbol = String::from(c);
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !"[]{}();$%".contains(*c))
+                {
+                    symbol.push(c);
+                }
+                tokens.extend(split_symbol(&symbol));
+            }
+        }
+        line_start = false;
+    }
+
+    Ok(tokens)
+}
+
+// the inside of a tag pair, e.g. White "Someone"
+fn parse_tag(raw: &str) -> Option<Token> {
+    let (name, value) = raw.trim().split_once(char::is_whitespace)?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            unescaped.extend(chars.next());
+        } else {
+            unescaped.push(c);
+        }
+    }
+    Some(Token::Tag(name.to_string(), unescaped))
+}
+
+// separates move numbers and suffix annotations from the move itself: "12.Nf3!?" becomes Nf3 and $5
+fn split_symbol(symbol: &str) -> Vec<Token> {
+    let mut rest = symbol;
+    let digits = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+    if digits.len() < rest.len() && digits.starts_with('.') {
+        rest = digits.trim_start_matches('.');
+    }
+
+    let san = rest.trim_end_matches(['!', '?']);
+    let nag = match &rest[san.len()..] {
+        "" => None,
+        "!" => Some(1),
+        "?" => Some(2),
+        "!!" => Some(3),
+        "??" => Some(4),
+        "!?" => Some(5),
+        "?!" => Some(6),
+        other => return vec![Token::Symbol(format!("{san}{other}"))],
+    };
+
+    let mut tokens = Vec::new();
+    if !san.is_empty() {
+        tokens.push(Token::Symbol(san.to_string()));
+    }
+    tokens.extend(nag.map(Token::Nag));
+    tokens
+}
+
+fn result_from_str(text: &str) -> Option<Option<GameResult>> {
+    match text {
+        "1-0" => Some(Some(GameResult::WhiteWins)),
+        "0-1" => Some(Some(GameResult::BlackWins)),
+        "1/2-1/2" => Some(Some(GameResult::Draw)),
+        "*" => Some(None),
+        _ => None,
+    }
+}
+
+// the game is set up from the tags once its movetext starts
+fn start_game(tags: Vec<(String, String)>) -> Result<(PgnGame, State), PgnError> {
+    let fen = tags
+        .iter()
+        .find(|(name, _)| name == "FEN")
+        .map(|(_, value)| value.as_str());
+    // other programs have called chess960 a few different things
+    let variant_tag = tags
+        .iter()
+        .find(|(name, _)| name == "Variant")
+        .map(|(_, value)| value.to_lowercase());
+    let chess960 = variant_tag
+        .as_ref()
+        .is_some_and(|value| ["chess960", "chess 960", "fischerandom"].contains(&value.as_str()));
+    let variant = variant_tag
+        .and_then(|value| Variant::parse(&value))
+        .unwrap_or_default();
+    let mut starting_position = match fen {
+        Some(fen) => State::from_fen_with_variant(fen, variant).map_err(PgnError::InvalidFen)?,
+        None => State::new_variant(variant),
+    };
+    starting_position.chess960 |= chess960;
+    let result = tags
+        .iter()
+        .find(|(name, _)| name == "Result")
+        .and_then(|(_, value)| result_from_str(value))
+        .flatten();
+
+    Ok((
+        PgnGame {
+            tags,
+            comment: None,
+            moves: Vec::new(),
+            result,
+            starting_position: starting_position.clone(),
+        },
+        starting_position,
+    ))
+}
+
+// reads every game in the text. variations are skipped, as only the main line is kept
+pub fn parse_pgn(text: &str) -> Result<Vec<PgnGame>, PgnError> {
+    let mut games = Vec::new();
+    let mut tags = Vec::new();
+    // the game whose movetext is being read, along with its current position
+    let mut current: Option<(PgnGame, State)> = None;
+    let mut variation_depth = 0;
+
+    for token in tokenize(text)? {
+        if variation_depth > 0 {
+            match token {
+                Token::VariationStart => variation_depth += 1,
+                Token::VariationEnd => variation_depth -= 1,
+                _ => {}
+            }
+            continue;
+        }
+
+        let (game, state) = match token {
+            // a game without a result token still ends where the next one's tags begin
+            Token::Tag(name, value) => {
+                if let Some((game, _)) = current.take() {
+                    games.push(game);
+                }
+                tags.push((name, value));
+                continue;
+            }
+            Token::VariationStart => {
+                variation_depth = 1;
+                continue;
+            }
+            Token::VariationEnd => return Err(PgnError::UnbalancedVariation),
+            _ => match &mut current {
+                Some((game, state)) => (game, state),
+                None => {
+                    let (game, state) = current.insert(start_game(std::mem::take(&mut tags))?);
+                    (game, state)
+                }
+            },
+        };
+
+        match token {
+            Token::Comment(comment) => {
+                let target = match game.moves.last_mut() {
+                    Some(last) => &mut last.comment,
+                    None => &mut game.comment,
+                };
+                match target {
+                    Some(existing) => {
+                        existing.push(' ');
+                        existing.push_str(&comment);
+                    }
+                    None => *target = Some(comment),
+                }
+            }
+            Token::Nag(nag) => match game.moves.last_mut() {
+                Some(last) => last.nags.push(nag),
+                None => return Err(PgnError::UnexpectedToken(format!("${nag}"))),
+            },
+            Token::Symbol(symbol) => {
+                if let Some(result) = result_from_str(&symbol) {
+                    game.result = result;
+                    games.push(current.take().unwrap().0);
+                    continue;
+                }
+
+                let chess_move =
+                    state
+                        .parse_san(&symbol)
+                        .map_err(|error| PgnError::InvalidMove {
+                            fullmove_number: state.fullmove_number,
+                            turn: state.turn,
+                            error,
+                        })?;
+                state.make_move(chess_move);
+                game.moves.push(PgnMove {
+                    chess_move,
+                    nags: Vec::new(),
+                    comment: None,
+                });
+            }
+            _ => unreachable!("tags and variations are handled above"),
+        }
+    }
+
+    if variation_depth > 0 {
+        return Err(PgnError::UnbalancedVariation);
+    }
+    if let Some((game, _)) = current {
+        games.push(game);
+    } else if !tags.is_empty() {
+        games.push(start_game(tags)?.0);
+    }
+
+    Ok(games)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(state: &mut State, moves: &[&str]) {
+        for san in moves {
+            let chess_move = state.parse_san(san).unwrap();
+            state.make_move(chess_move);
+        }
+    }
+
+    #[test]
+    fn export() {
+        let mut state = State::new();
+        play(
+            &mut state,
+            &["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7"],
+        );
+        state.update_termination();
+
+        let mut game = PgnGame::from_state(&state);
+        game.set_tag("White", "Scholar");
+        game.set_tag("Annotator", "a \"quoted\" name");
+        game.moves[5].nags.push(4);
+        game.moves[5].comment = Some(String::from("misses the threat"));
+
+        assert_eq!(
+            game.to_string(),
+            "[Event \"?\"]\n\
+             [Site \"?\"]\n\
+             [Date \"????.??.??\"]\n\
+             [Round \"?\"]\n\
+             [White \"Scholar\"]\n\
+             [Black \"?\"]\n\
+             [Result \"1-0\"]\n\
+             [Annotator \"a \\\"quoted\\\" name\"]\n\
+             \n\
+             1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 $4 {misses the threat} 4. Qxf7# 1-0\n"
+        );
+    }
+
+    #[test]
+    fn export_from_position() {
+        let mut state =
+            State::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1")
+                .unwrap();
+        play(&mut state, &["O-O-O", "Nd3"]);
+
+        let text = PgnGame::from_state(&state).to_string();
+        assert!(text.contains("[SetUp \"1\"]"));
+        assert!(text.contains(
+            "[FEN \"r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1\"]"
+        ));
+        assert!(text.ends_with("1... O-O-O 2. Nd3 *\n"));
+    }
+
+    #[test]
+    fn chess960() {
+        let fen = "bqnbr1kr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR b kq - 2 9";
+        let mut state = State::from_fen(fen).unwrap();
+        play(&mut state, &["O-O", "e3"]);
+        let text = PgnGame::from_state(&state).to_string();
+        assert!(text.contains("[Variant \"Chess960\"]"));
+        assert!(text.ends_with("9... O-O 10. e3 *\n"));
+
+        let game = &parse_pgn(&text).unwrap()[0];
+        assert!(game.starting_position.chess960);
+        assert_eq!(game.positions()[2].to_fen(), state.to_fen());
+    }
+
+    #[test]
+    fn variants() {
+        let mut state = State::new_variant(Variant::Antichess);
+        play(&mut state, &["e3", "b5", "Bxb5"]);
+        let text = PgnGame::from_state(&state).to_string();
+        assert!(text.contains("[Variant \"Antichess\"]"));
+        assert!(!text.contains("[FEN "));
+
+        let game = &parse_pgn(&text).unwrap()[0];
+        assert_eq!(game.starting_position.variant, Variant::Antichess);
+        assert_eq!(game.positions()[3].to_fen(), state.to_fen());
+    }
+
+    #[test]
+    fn import() {
+        let text = r#"
+[Event "First"]
+[White "Someone"]
+[Result "1-0"]
+
+{The usual} 1.e4 e5 2. Nf3 (2. f4 exf4 {gambit} (2... d5)) 2... Nc6! $14 3. Bb5 ; Ruy Lopez
+a6 1-0
+
+% this line is ignored
+[Event "Second"]
+[SetUp "1"]
+[FEN "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1"]
+
+1. e4 Kd7 *
+"#;
+        let games = parse_pgn(text).unwrap();
+        assert_eq!(games.len(), 2);
+
+        let first = &games[0];
+        assert_eq!(first.tag("White"), Some("Someone"));
+        assert_eq!(first.tag("Black"), None);
+        assert_eq!(first.result, Some(GameResult::WhiteWins));
+        assert_eq!(first.comment.as_deref(), Some("The usual"));
+        assert_eq!(first.moves.len(), 6);
+        assert_eq!(first.moves[3].nags, vec![1, 14]);
+        assert_eq!(first.moves[4].comment.as_deref(), Some("Ruy Lopez"));
+
+        let positions = first.positions();
+        assert_eq!(positions.len(), 7);
+        assert_eq!(
+            positions[6].to_fen(),
+            "r1bqkbnr/1ppp1ppp/p1n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 0 4"
+        );
+        assert_eq!(positions[6].history.len(), 6);
+
+        let second = &games[1];
+        assert_eq!(second.result, None);
+        assert_eq!(
+            second.positions()[2].to_fen(),
+            "8/3k4/8/8/4P3/8/8/4K3 w - - 1 2"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let text =
+            "[Event \"Round trip\"]\n\n1. d4 d5 2. c4 {Queen's Gambit} 2... dxc4 $2 3. e3 *\n";
+        let games = parse_pgn(text).unwrap();
+        let exported = games[0].to_string();
+        let reimported = parse_pgn(&exported).unwrap();
+        assert_eq!(reimported[0].moves, games[0].moves);
+        assert_eq!(reimported[0].to_string(), exported);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(matches!(
+            parse_pgn("1. e4 e5 2. Ke3 *"),
+            Err(PgnError::InvalidMove {
+                fullmove_number: 2,
+                turn: ChessColour::White,
+                ..
+            })
+        ));
+        assert_eq!(
+            parse_pgn("1. e4 {never closed").err(),
+            Some(PgnError::UnterminatedComment)
+        );
+        assert_eq!(
+            parse_pgn("1. e4 (1. d4 *").err(),
+            Some(PgnError::UnbalancedVariation)
+        );
+        assert!(matches!(
+            parse_pgn("[FEN \"not a fen\"]\n\n*"),
+            Err(PgnError::InvalidFen(_))
+        ));
+    }
+}
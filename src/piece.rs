@@ -1,4 +1,4 @@
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum PieceKind {
     Pawn,
     Rook,
@@ -9,20 +9,23 @@ pub enum PieceKind {
 }
 
 impl PieceKind {
-    pub fn is_sliding(&self) -> bool {
-        match self {
-            PieceKind::Pawn | PieceKind::King | PieceKind::Knight => false,
-            _ => true,
-        }
+    // position in State's per-kind bitboard arrays
+    pub fn index(self) -> usize {
+        self as usize
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum ChessColour {
     White,
     Black,
 }
 impl ChessColour {
+    // position in State's per-colour bitboard arrays
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
     pub fn flip(&self) -> Self {
         if self == &ChessColour::White {
             ChessColour::Black
@@ -32,12 +35,10 @@ impl ChessColour {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Piece {
     pub kind: PieceKind,
     pub colour: ChessColour,
-    pub has_moved: bool,
-    pub en_passanteable: bool,
 }
 
 impl Piece {
@@ -57,14 +58,23 @@ impl Piece {
             _ => None,
         };
 
-        match kind {
-            Some(k) => Some(Piece {
-                kind: k,
-                colour,
-                has_moved: false,
-                en_passanteable: false,
-            }),
-            None => None,
+        kind.map(|k| Piece { kind: k, colour })
+    }
+
+    pub fn to_char(self) -> char {
+        let c = match self.kind {
+            PieceKind::Pawn => 'p',
+            PieceKind::Rook => 'r',
+            PieceKind::Knight => 'n',
+            PieceKind::Bishop => 'b',
+            PieceKind::Queen => 'q',
+            PieceKind::King => 'k',
+        };
+
+        if self.colour == ChessColour::White {
+            c.to_ascii_uppercase()
+        } else {
+            c
         }
     }
 }
@@ -2,10 +2,48 @@ use rand::rngs::SmallRng;
 use rand::seq::SliceRandom;
 use rand::SeedableRng;
 
+use crate::clock::Clock;
+use crate::search::Searcher;
 use crate::state::State;
-use crate::{chess_move::MoveInfo, piece::ChessColour};
+use crate::tt::TranspositionTable;
+use crate::{
+    chess_move::{ChessMove, MoveInfo, MoveKind},
+    piece::{ChessColour, PieceKind},
+};
 
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// whether players and the search log what they're up to. on unless turned off,
+// e.g. by a match runner that plays lots of games at once
+static VERBOSE: AtomicBool = AtomicBool::new(true);
+
+pub fn set_verbose(verbose: bool) {
+    VERBOSE.store(verbose, Ordering::Relaxed);
+}
+
+pub fn verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
+// how much a player may think about its next move. None means no limit of that kind;
+// players that don't search are free to ignore all of it
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub depth: Option<u32>,
+    pub movetime: Option<Duration>,
+    // the clocks and increments of both sides, indexed by ChessColour::index
+    pub time_left: [Option<Duration>; 2],
+    pub increment: [Option<Duration>; 2],
+    // moves until the next time control
+    pub moves_to_go: Option<u32>,
+    // keep thinking until told to stop
+    pub infinite: bool,
+    // set from elsewhere to ask for a move as soon as possible
+    pub stop: Arc<AtomicBool>,
+}
 
 pub trait Player: Send + Sized {
     // takes the receiver from main, which sends moves when they arrive from both this and the other player
@@ -26,32 +64,58 @@ pub trait Player: Send + Sized {
     fn ponder_new_move(&mut self);
     fn send_move_to_main(&mut self) -> Result<(), mpsc::SendError<MoveInfo>>;
 
-    // general loop
+    // starts over from the given position, playing the side to move, e.g. for UCI's position.
+    // whatever the player has learned along the way is kept
+    fn set_position(&mut self, state: &State);
+
+    // general loop. logs to stderr, as stdout may be taken by a protocol like UCI
     fn tick(&mut self) {
         // potential overload
         self.specific_tick();
 
         if self.return_new_move().is_some() {
-            println!("└─ new move is ready!");
+            log!("└─ new move is ready!");
             self.send_move_to_main()
                 .expect("Could not send new move to main");
-            println!("└─ sent new move to main thread.");
+            log!("└─ sent new move to main thread.");
         }
 
         if let Ok(new_move_to_be_applied) = self.receive_move_from_main() {
-            println!(
-                "├─ new move received, from coord {:?}",
-                new_move_to_be_applied.coord
-            );
+            log!("├─ new move received: {}", new_move_to_be_applied.move_data);
             self.apply_move(new_move_to_be_applied);
         } else {
-            println!("├─ no new move received. ");
+            log!("├─ no new move received. ");
             self.ponder_new_move();
-            println!("└─ started pondering new move.");
+            log!("└─ started pondering new move.");
         }
     }
 
     fn specific_tick(&mut self) {}
+
+    fn set_limits(&mut self, _limits: SearchLimits) {}
+
+    // engine options, e.g. as set through UCI's setoption; unknown names are ignored
+    fn set_option(&mut self, _name: &str, _value: &str) {}
+
+    // forgets what was learned from earlier games, e.g. at UCI's ucinewgame
+    fn clear(&mut self) {}
+
+    // why the player can't go on, e.g. because its engine crashed. it is then stopped, and
+    // whoever is waiting for its moves takes that as resigning
+    fn failure(&self) -> Option<String> {
+        None
+    }
+
+    // for players that make random choices, so that their games can be repeated
+    fn set_seed(&mut self, _seed: u64) {}
+
+    // the game's clock, which main keeps running. players that think may budget with it
+    fn set_clock(&mut self, _clock: Arc<Mutex<Clock>>) {}
+
+    // the options set_option understands, written the way UCI's `option` command declares them
+    fn uci_options() -> Vec<String> {
+        Vec::new()
+    }
 }
 
 pub struct RandomPlayer {
@@ -70,6 +134,8 @@ pub struct RandomPlayer {
 
     // exclusive to RandomPlayer
     rng: SmallRng,
+    // set once a move has been sent, until main echoes it back
+    awaiting_echo: bool,
 }
 
 impl Player for RandomPlayer {
@@ -88,13 +154,22 @@ impl Player for RandomPlayer {
                 move_info: None,
                 rng: SmallRng::from_entropy(),
                 colour,
+                awaiting_echo: false,
             },
             own_rx,
         )
     }
 
     fn apply_move(&mut self, mi: MoveInfo) {
-        self.internal_state.make_move(mi.coord, mi.move_data);
+        self.internal_state.make_move(mi.move_data);
+        self.awaiting_echo = false;
+    }
+
+    fn set_position(&mut self, state: &State) {
+        self.internal_state = state.clone();
+        self.colour = state.turn;
+        self.move_info = None;
+        self.awaiting_echo = false;
     }
 
     fn receive_move_from_main(&mut self) -> Result<MoveInfo, mpsc::TryRecvError> {
@@ -102,30 +177,488 @@ impl Player for RandomPlayer {
     }
 
     fn return_new_move(&self) -> Option<MoveInfo> {
-        self.move_info.clone()
+        self.move_info
     }
 
     fn send_move_to_main(&mut self) -> Result<(), mpsc::SendError<MoveInfo>> {
-        self.tx_to_main.send(self.move_info.clone().unwrap())?;
+        self.tx_to_main.send(self.move_info.unwrap())?;
         self.move_info = None;
+        self.awaiting_echo = true;
 
         Ok(())
     }
 
     fn ponder_new_move(&mut self) {
-        let mut currently_available_moves =
-            self.internal_state.get_all_moves_for_colour(self.colour);
-        currently_available_moves.shuffle(&mut self.rng);
+        // moves picked out of turn would sit in the channel and be played later, by which time
+        // they may well be illegal
+        if self.internal_state.turn != self.colour || self.awaiting_echo {
+            return;
+        }
 
-        let selected_move = currently_available_moves[0].clone();
+        let currently_available_moves = self.internal_state.get_all_moves_for_colour(self.colour);
 
-        self.move_info = Some(MoveInfo {
-            coord: selected_move.0,
-            move_data: selected_move.1,
-        });
+        // no moves means the game is over, so there is nothing left to send
+        self.move_info = currently_available_moves
+            .choose(&mut self.rng)
+            .map(|selected_move| MoveInfo {
+                move_data: *selected_move,
+            });
+    }
+
+    fn specific_tick(&mut self) {
+        log!("tick from {:?} player", self.colour);
+    }
+
+    fn set_seed(&mut self, seed: u64) {
+        self.rng = SmallRng::seed_from_u64(seed);
+    }
+}
+
+pub struct SearchPlayer {
+    tx_to_main: Sender<MoveInfo>,
+    rx_from_main: Receiver<MoveInfo>,
+
+    internal_state: State,
+
+    move_info: Option<MoveInfo>,
+
+    colour: ChessColour,
+
+    // exclusive to SearchPlayer
+    searcher: Searcher,
+    limits: SearchLimits,
+    // overrides the time limits above with whatever is left on it
+    clock: Option<Arc<Mutex<Clock>>>,
+    // set once a move has been sent, until main echoes it back; there is nothing to think about until then
+    awaiting_echo: bool,
+}
+
+impl SearchPlayer {
+    // used until set_limits says otherwise
+    pub const DEFAULT_MOVETIME: Duration = Duration::from_secs(1);
+}
+
+impl Player for SearchPlayer {
+    fn new(
+        rx_from_main: Receiver<MoveInfo>,
+        state_to_clone: &State,
+        colour: ChessColour,
+    ) -> (Self, Receiver<MoveInfo>) {
+        let (own_tx, own_rx) = mpsc::channel();
+
+        (
+            SearchPlayer {
+                rx_from_main,
+                tx_to_main: own_tx,
+                internal_state: state_to_clone.clone(),
+                move_info: None,
+                colour,
+                searcher: Searcher::new(),
+                limits: SearchLimits {
+                    movetime: Some(SearchPlayer::DEFAULT_MOVETIME),
+                    ..Default::default()
+                },
+                clock: None,
+                awaiting_echo: false,
+            },
+            own_rx,
+        )
+    }
+
+    fn apply_move(&mut self, mi: MoveInfo) {
+        self.internal_state.make_move(mi.move_data);
+        self.awaiting_echo = false;
+    }
+
+    fn set_position(&mut self, state: &State) {
+        self.internal_state = state.clone();
+        self.colour = state.turn;
+        self.move_info = None;
+        self.awaiting_echo = false;
+    }
+
+    fn receive_move_from_main(&mut self) -> Result<MoveInfo, mpsc::TryRecvError> {
+        self.rx_from_main.try_recv()
+    }
+
+    fn return_new_move(&self) -> Option<MoveInfo> {
+        self.move_info
+    }
+
+    fn send_move_to_main(&mut self) -> Result<(), mpsc::SendError<MoveInfo>> {
+        self.tx_to_main.send(self.move_info.unwrap())?;
+        self.move_info = None;
+        self.awaiting_echo = true;
+
+        Ok(())
+    }
+
+    // blocks for as long as the limits allow
+    fn ponder_new_move(&mut self) {
+        if self.internal_state.turn != self.colour || self.awaiting_echo || self.move_info.is_some()
+        {
+            return;
+        }
+
+        let limits = match &self.clock {
+            Some(clock) => clock
+                .lock()
+                .unwrap()
+                .search_limits(&self.limits, Instant::now()),
+            None => self.limits.clone(),
+        };
+        let result = self.searcher.search(&mut self.internal_state, &limits);
+        self.move_info = result.best_move.map(|move_data| MoveInfo { move_data });
     }
 
     fn specific_tick(&mut self) {
-        println!("tick from {:?} player", self.colour);
+        log!("tick from {:?} search player", self.colour);
+    }
+
+    fn set_limits(&mut self, limits: SearchLimits) {
+        self.limits = limits;
+    }
+
+    fn set_clock(&mut self, clock: Arc<Mutex<Clock>>) {
+        self.clock = Some(clock);
+    }
+
+    fn set_option(&mut self, name: &str, value: &str) {
+        // held to the range declared in uci_options, as a GUI may send anything
+        if let ("Hash", Ok(size_mb)) = (name, value.parse::<usize>()) {
+            self.searcher
+                .set_hash_size(size_mb.clamp(1, TranspositionTable::MAX_SIZE_MB));
+        }
+    }
+
+    fn clear(&mut self) {
+        self.searcher.clear();
+    }
+
+    fn uci_options() -> Vec<String> {
+        vec![format!(
+            "name Hash type spin default {} min 1 max {}",
+            TranspositionTable::DEFAULT_SIZE_MB,
+            TranspositionTable::MAX_SIZE_MB
+        )]
+    }
+}
+
+// a click on the board by the person playing as a HumanPlayer
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Click {
+    pub coord: (u8, u8),
+    // what a pawn reaching the last rank with this click turns into
+    pub promote_to: PieceKind,
+}
+
+// what a human player has picked up: one of their pieces on the board, or in crazyhouse one
+// from their pocket
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Picked {
+    Square((u8, u8)),
+    Pocket(PieceKind),
+}
+
+// what has been picked up and the moves it can make
+pub type Selection = (Picked, Vec<ChessMove>);
+
+// what main passes on, in the order it happened
+enum HumanInput {
+    Click(Click),
+    Pocket(PieceKind),
+}
+
+// main's end of a HumanPlayer: clicks go in, and the selection comes back out to be drawn.
+// cloned off the player before it is moved to its own thread
+#[derive(Clone)]
+pub struct HumanHandle {
+    tx_input: Sender<HumanInput>,
+    selection: Arc<Mutex<Option<Selection>>>,
+}
+
+impl HumanHandle {
+    // the player only hangs up when the game is torn down, at which point clicks don't matter
+    pub fn click(&self, click: Click) {
+        let _ = self.tx_input.send(HumanInput::Click(click));
+    }
+
+    // a click on a piece in the player's own pocket
+    pub fn pick_from_pocket(&self, kind: PieceKind) {
+        let _ = self.tx_input.send(HumanInput::Pocket(kind));
+    }
+
+    pub fn selection(&self) -> Option<Selection> {
+        self.selection.lock().unwrap().clone()
+    }
+}
+
+pub struct HumanPlayer {
+    tx_to_main: Sender<MoveInfo>,
+    rx_from_main: Receiver<MoveInfo>,
+
+    internal_state: State,
+
+    move_info: Option<MoveInfo>,
+
+    colour: ChessColour,
+
+    // exclusive to HumanPlayer
+    rx_input: Receiver<HumanInput>,
+    handle: HumanHandle,
+    picked: Option<Picked>,
+    // set once a move has been sent, until main echoes it back
+    awaiting_echo: bool,
+}
+
+impl HumanPlayer {
+    pub fn handle(&self) -> HumanHandle {
+        self.handle.clone()
+    }
+
+    fn may_move(&self) -> bool {
+        self.internal_state.turn == self.colour && !self.awaiting_echo && self.move_info.is_none()
+    }
+
+    fn moves_of(&self, picked: Picked) -> Vec<ChessMove> {
+        match picked {
+            Picked::Square(coord) => self.internal_state.get_moves(coord, true),
+            Picked::Pocket(kind) => self
+                .internal_state
+                .get_all_moves_for_colour(self.colour)
+                .into_iter()
+                .filter(|m| m.kind == MoveKind::Drop(kind))
+                .collect(),
+        }
+    }
+
+    fn update_selection(&self) {
+        *self.handle.selection.lock().unwrap() =
+            self.picked.map(|picked| (picked, self.moves_of(picked)));
+    }
+
+    // the first click picks up one of the player's pieces, the second puts it down.
+    // clicking another piece of their own switches to that one, and anything else drops it
+    fn handle_click(&mut self, click: Click) {
+        if !self.may_move() {
+            return;
+        }
+
+        let own_piece = self.internal_state[click.coord]
+            .content
+            .is_some_and(|p| p.colour == self.colour);
+        let chosen_move = self.picked.and_then(|picked| {
+            self.moves_of(picked).into_iter().find(|m| {
+                // castling can also be done by clicking the rook, which is the only way
+                // for a Chess960 king that stays where it is
+                let rook = self.internal_state.castling_rook_of(*m);
+                (m.dst == click.coord || rook == Some(click.coord))
+                    && match m.kind {
+                        MoveKind::Promotion { promote_to, .. } => promote_to == click.promote_to,
+                        _ => true,
+                    }
+            })
+        });
+
+        self.picked = None;
+        if let Some(move_data) = chosen_move {
+            self.move_info = Some(MoveInfo { move_data });
+        } else if own_piece {
+            self.picked = Some(Picked::Square(click.coord));
+        }
+        self.update_selection();
+    }
+
+    // the piece is dropped with the next click on the board. picking it again puts it back,
+    // and there's nothing to pick if there's none of it, or nowhere to drop it
+    fn handle_pocket(&mut self, kind: PieceKind) {
+        if !self.may_move() {
+            return;
+        }
+
+        let picked = Picked::Pocket(kind);
+        self.picked = if self.picked == Some(picked) || self.moves_of(picked).is_empty() {
+            None
+        } else {
+            Some(picked)
+        };
+        self.update_selection();
+    }
+}
+
+impl Player for HumanPlayer {
+    fn new(
+        rx_from_main: Receiver<MoveInfo>,
+        state_to_clone: &State,
+        colour: ChessColour,
+    ) -> (Self, Receiver<MoveInfo>) {
+        let (own_tx, own_rx) = mpsc::channel();
+        let (tx_input, rx_input) = mpsc::channel();
+
+        (
+            HumanPlayer {
+                rx_from_main,
+                tx_to_main: own_tx,
+                internal_state: state_to_clone.clone(),
+                move_info: None,
+                colour,
+                rx_input,
+                handle: HumanHandle {
+                    tx_input,
+                    selection: Arc::default(),
+                },
+                picked: None,
+                awaiting_echo: false,
+            },
+            own_rx,
+        )
+    }
+
+    fn apply_move(&mut self, mi: MoveInfo) {
+        self.internal_state.make_move(mi.move_data);
+        self.awaiting_echo = false;
+    }
+
+    fn set_position(&mut self, state: &State) {
+        self.internal_state = state.clone();
+        self.colour = state.turn;
+        self.move_info = None;
+        self.awaiting_echo = false;
+        self.picked = None;
+        self.update_selection();
+    }
+
+    fn receive_move_from_main(&mut self) -> Result<MoveInfo, mpsc::TryRecvError> {
+        self.rx_from_main.try_recv()
+    }
+
+    fn return_new_move(&self) -> Option<MoveInfo> {
+        self.move_info
+    }
+
+    fn send_move_to_main(&mut self) -> Result<(), mpsc::SendError<MoveInfo>> {
+        self.tx_to_main.send(self.move_info.unwrap())?;
+        self.move_info = None;
+        self.awaiting_echo = true;
+
+        Ok(())
+    }
+
+    // the move comes from the clicks instead
+    fn ponder_new_move(&mut self) {}
+
+    // ticks quickly so that clicks feel responsive, so it goes without the logging
+    fn tick(&mut self) {
+        while let Ok(input) = self.rx_input.try_recv() {
+            match input {
+                HumanInput::Click(click) => self.handle_click(click),
+                HumanInput::Pocket(kind) => self.handle_pocket(kind),
+            }
+        }
+
+        if self.return_new_move().is_some() {
+            self.send_move_to_main()
+                .expect("Could not send new move to main");
+        }
+
+        while let Ok(new_move_to_be_applied) = self.receive_move_from_main() {
+            self.apply_move(new_move_to_be_applied);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn click(coord: (u8, u8)) -> Click {
+        Click {
+            coord,
+            promote_to: PieceKind::Queen,
+        }
+    }
+
+    #[test]
+    fn human_moves_by_clicking() {
+        let (tx_to_player, rx_to_player) = mpsc::channel();
+        let (mut player, rx_from_player) =
+            HumanPlayer::new(rx_to_player, &State::new(), ChessColour::White);
+        let handle = player.handle();
+
+        // picking up the other side's pieces does nothing, nor do clicks on empty squares
+        handle.click(click((4, 6)));
+        handle.click(click((4, 4)));
+        player.tick();
+        assert!(handle.selection().is_none());
+
+        handle.click(click((4, 1)));
+        player.tick();
+        let (picked, moves) = handle.selection().unwrap();
+        assert_eq!((picked, moves.len()), (Picked::Square((4, 1)), 2));
+
+        handle.click(click((4, 3)));
+        player.tick();
+        let move_data = rx_from_player.try_recv().unwrap().move_data;
+        assert_eq!(move_data.to_string(), "e2e4");
+        assert!(handle.selection().is_none());
+
+        // nothing happens while it is black's turn
+        tx_to_player.send(MoveInfo { move_data }).unwrap();
+        handle.click(click((3, 1)));
+        player.tick();
+        assert!(handle.selection().is_none());
+    }
+
+    #[test]
+    fn human_picks_the_promotion() {
+        let state = State::from_fen("8/4P3/8/8/8/8/k7/7K w - - 0 1").unwrap();
+        let (_tx_to_player, rx_to_player) = mpsc::channel();
+        let (mut player, rx_from_player) =
+            HumanPlayer::new(rx_to_player, &state, ChessColour::White);
+        let handle = player.handle();
+
+        handle.click(click((4, 6)));
+        handle.click(Click {
+            coord: (4, 7),
+            promote_to: PieceKind::Knight,
+        });
+        player.tick();
+        assert_eq!(
+            rx_from_player.try_recv().unwrap().move_data.to_string(),
+            "e7e8n"
+        );
+    }
+
+    #[test]
+    fn human_drops_from_the_pocket() {
+        let state = State::from_fen_with_variant(
+            "4k3/8/8/8/8/8/8/4K3[N] w - - 0 1",
+            crate::variant::Variant::Crazyhouse,
+        )
+        .unwrap();
+        let (_tx_to_player, rx_to_player) = mpsc::channel();
+        let (mut player, rx_from_player) =
+            HumanPlayer::new(rx_to_player, &state, ChessColour::White);
+        let handle = player.handle();
+
+        // there are no bishops to pick
+        handle.pick_from_pocket(PieceKind::Bishop);
+        player.tick();
+        assert!(handle.selection().is_none());
+
+        handle.pick_from_pocket(PieceKind::Knight);
+        player.tick();
+        let (picked, moves) = handle.selection().unwrap();
+        assert_eq!(
+            (picked, moves.len()),
+            (Picked::Pocket(PieceKind::Knight), 62)
+        );
+
+        handle.click(click((5, 2)));
+        player.tick();
+        assert_eq!(
+            rx_from_player.try_recv().unwrap().move_data.to_string(),
+            "N@f3"
+        );
     }
 }
@@ -0,0 +1,304 @@
+use crate::{
+    chess_move::*,
+    piece::{PieceKind::*, *},
+    square::parse_coord,
+    state::State,
+};
+
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SanError {
+    Malformed(String),
+    IllegalMove(String),
+    AmbiguousMove(String),
+}
+
+impl fmt::Display for SanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SanError::Malformed(s) => write!(f, "\"{s}\" is not a move in algebraic notation"),
+            SanError::IllegalMove(s) => write!(f, "{s} is not a legal move in this position"),
+            SanError::AmbiguousMove(s) => write!(f, "{s} could be played by more than one piece"),
+        }
+    }
+}
+
+impl std::error::Error for SanError {}
+
+// SAN piece letters are always uppercase
+fn piece_letter(kind: PieceKind) -> char {
+    Piece {
+        kind,
+        colour: ChessColour::White,
+    }
+    .to_char()
+}
+
+impl State {
+    // standard algebraic notation for a legal move in the current position, e.g. Nbd7, exd5, O-O-O, e8=Q+.
+    // drops are written the same way as in UCI, e.g. N@f3 or P@e6. the move is made and taken
+    // back again to see whether it gives check, which leaves the state as it was
+    pub fn move_to_san(&mut self, chess_move: ChessMove) -> String {
+        let ChessMove { src, dst, kind } = chess_move;
+        let piece = self.moving_piece(chess_move);
+
+        let mut san = match kind {
+            MoveKind::ShortCastle => String::from("O-O"),
+            MoveKind::LongCastle => String::from("O-O-O"),
+            MoveKind::Drop(_) => chess_move.to_string(),
+            _ => {
+                let mut san = String::new();
+                if piece.kind == Pawn {
+                    // pawn captures are named after the file they come from
+                    if chess_move.is_capture() {
+                        san.push(self[src].coord().remove(0));
+                    }
+                } else {
+                    san.push(piece_letter(piece.kind));
+
+                    // other pieces of the same kind that could go to the same square. the file
+                    // is preferred to tell them apart, then the rank, and only then both
+                    let rivals = self
+                        .get_all_moves_for_colour(piece.colour)
+                        .into_iter()
+                        .filter(|m| m.dst == dst && m.src != src)
+                        .filter(|m| !matches!(m.kind, MoveKind::Drop(_)))
+                        .filter(|m| self[m.src].content.unwrap().kind == piece.kind)
+                        .collect::<Vec<_>>();
+                    let src_coord = self[src].coord();
+                    if !rivals.is_empty() {
+                        if rivals.iter().all(|m| m.src.0 != src.0) {
+                            san.push_str(&src_coord[..1]);
+                        } else if rivals.iter().all(|m| m.src.1 != src.1) {
+                            san.push_str(&src_coord[1..]);
+                        } else {
+                            san.push_str(&src_coord);
+                        }
+                    }
+                }
+
+                if chess_move.is_capture() {
+                    san.push('x');
+                }
+                san.push_str(&self[dst].coord());
+
+                if let MoveKind::Promotion { promote_to, .. } = kind {
+                    san.push('=');
+                    san.push(piece_letter(promote_to));
+                }
+
+                san
+            }
+        };
+
+        self.make_move(chess_move);
+        if self.is_in_check(self.turn) {
+            if self.get_all_moves_for_colour(self.turn).is_empty() {
+                san.push('#');
+            } else {
+                san.push('+');
+            }
+        }
+        self.unmake_move();
+
+        san
+    }
+
+    // finds the legal move described in algebraic notation. check and annotation suffixes are
+    // ignored, as are capture markers; castling may also be written with zeroes, and pawn drops
+    // without the P.
+    pub fn parse_san(&self, text: &str) -> Result<ChessMove, SanError> {
+        let malformed = || SanError::Malformed(text.to_string());
+        let trimmed = text.trim().trim_end_matches(['+', '#', '!', '?']);
+        let legal_moves = self.get_all_moves_for_colour(self.turn);
+
+        let candidates = match trimmed {
+            "O-O" | "0-0" => legal_moves
+                .into_iter()
+                .filter(|m| m.kind == MoveKind::ShortCastle)
+                .collect::<Vec<_>>(),
+            "O-O-O" | "0-0-0" => legal_moves
+                .into_iter()
+                .filter(|m| m.kind == MoveKind::LongCastle)
+                .collect(),
+            _ if trimmed.contains('@') => {
+                let (letter, dst_text) = trimmed.split_once('@').unwrap();
+                let kind = match letter {
+                    "" | "P" => Pawn,
+                    "Q" | "R" | "B" | "N" => {
+                        Piece::from_char(letter.chars().next().unwrap())
+                            .unwrap()
+                            .kind
+                    }
+                    _ => return Err(malformed()),
+                };
+                let dst = parse_coord(dst_text).ok_or_else(malformed)?;
+                legal_moves
+                    .into_iter()
+                    .filter(|m| m.kind == MoveKind::Drop(kind) && m.dst == dst)
+                    .collect()
+            }
+            _ => {
+                let (kind, rest) = match trimmed.chars().next() {
+                    Some(c @ ('K' | 'Q' | 'R' | 'B' | 'N')) => {
+                        (Piece::from_char(c).unwrap().kind, &trimmed[1..])
+                    }
+                    Some('a'..='h') => (Pawn, trimmed),
+                    _ => return Err(malformed()),
+                };
+
+                // the promotion piece, either as e8=Q or e8Q. antichess also has e8=K
+                let (rest, promotion) = match rest.char_indices().last() {
+                    Some((i, c @ ('Q' | 'R' | 'B' | 'N' | 'K'))) if kind == Pawn => {
+                        let promote_to = Piece::from_char(c).unwrap().kind;
+                        (rest[..i].trim_end_matches('='), Some(promote_to))
+                    }
+                    _ => (rest, None),
+                };
+
+                let rest = rest.replace(['x', ':'], "");
+                if rest.len() < 2 || !rest.is_ascii() {
+                    return Err(malformed());
+                }
+                let (disambiguation, dst_text) = rest.split_at(rest.len() - 2);
+                let dst = parse_coord(dst_text).ok_or_else(malformed)?;
+
+                let mut from_file = None;
+                let mut from_rank = None;
+                for c in disambiguation.chars() {
+                    match c {
+                        'a'..='h' => from_file = Some(c as u8 - b'a'),
+                        '1'..='8' => from_rank = Some(c as u8 - b'1'),
+                        _ => return Err(malformed()),
+                    }
+                }
+
+                legal_moves
+                    .into_iter()
+                    .filter(|m| m.dst == dst && !matches!(m.kind, MoveKind::Drop(_)))
+                    .filter(|m| self[m.src].content.unwrap().kind == kind)
+                    .filter(|m| from_file.is_none_or(|x| m.src.0 == x))
+                    .filter(|m| from_rank.is_none_or(|y| m.src.1 == y))
+                    .filter(|m| match m.kind {
+                        MoveKind::Promotion { promote_to, .. } => promotion == Some(promote_to),
+                        _ => promotion.is_none(),
+                    })
+                    .collect()
+            }
+        };
+
+        match candidates[..] {
+            [chess_move] => Ok(chess_move),
+            [] => Err(SanError::IllegalMove(text.to_string())),
+            _ => Err(SanError::AmbiguousMove(text.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn san_of(fen: &str, uci: &str) -> String {
+        let mut state = State::from_fen(fen).unwrap();
+        let chess_move = state
+            .get_all_moves_for_colour(state.turn)
+            .into_iter()
+            .find(|m| m.to_string() == uci)
+            .unwrap();
+        state.move_to_san(chess_move)
+    }
+
+    #[test]
+    fn formatting() {
+        let start = crate::fen::STARTING_FEN;
+        assert_eq!(san_of(start, "e2e4"), "e4");
+        assert_eq!(san_of(start, "g1f3"), "Nf3");
+
+        let kiwipete = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        assert_eq!(san_of(kiwipete, "e1g1"), "O-O");
+        assert_eq!(san_of(kiwipete, "e1c1"), "O-O-O");
+        assert_eq!(san_of(kiwipete, "d5e6"), "dxe6");
+        assert_eq!(san_of(kiwipete, "e5f7"), "Nxf7");
+        assert_eq!(san_of(kiwipete, "e5d3"), "Nd3");
+
+        let two_knights = "rn1qkb1r/ppp1pppp/5n2/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1";
+        assert_eq!(san_of(two_knights, "b8d7"), "Nbd7");
+        assert_eq!(san_of(two_knights, "f6d7"), "Nfd7");
+
+        // files can't tell these rooks apart, so the rank does
+        assert_eq!(san_of("7k/8/R7/8/8/8/8/R5K1 w - - 0 1", "a1a3"), "R1a3");
+        // three queens, two sharing a file and two sharing a rank with the one moving
+        assert_eq!(san_of("1k6/8/8/8/4Q2Q/8/K7/7Q w - - 0 1", "h4e1"), "Qh4e1");
+
+        assert_eq!(san_of("8/4P3/8/8/8/8/k7/7K w - - 0 1", "e7e8q"), "e8=Q");
+        assert_eq!(san_of("k7/4P3/8/8/8/8/8/7K w - - 0 1", "e7e8r"), "e8=R+");
+        assert_eq!(
+            san_of(
+                "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2",
+                "d8h4"
+            ),
+            "Qh4#"
+        );
+    }
+
+    #[test]
+    fn parsing() {
+        let state =
+            State::from_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")
+                .unwrap();
+        assert_eq!(state.parse_san("O-O").unwrap().to_string(), "e1g1");
+        assert_eq!(state.parse_san("0-0-0").unwrap().to_string(), "e1c1");
+        assert_eq!(state.parse_san("Nxf7!?").unwrap().to_string(), "e5f7");
+        assert_eq!(state.parse_san("Qxh3").unwrap().to_string(), "f3h3");
+        assert_eq!(
+            state.parse_san("Ke3"),
+            Err(SanError::IllegalMove(String::from("Ke3")))
+        );
+        assert!(matches!(
+            state.parse_san("Zz9"),
+            Err(SanError::Malformed(_))
+        ));
+
+        let two_knights =
+            State::from_fen("rn1qkb1r/ppp1pppp/5n2/8/8/8/PPPPPPPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        assert_eq!(
+            two_knights.parse_san("Nd7"),
+            Err(SanError::AmbiguousMove(String::from("Nd7")))
+        );
+        assert_eq!(two_knights.parse_san("N8d7").unwrap().to_string(), "b8d7");
+
+        let promotion = State::from_fen("8/4P3/8/8/8/8/k7/7K w - - 0 1").unwrap();
+        assert_eq!(promotion.parse_san("e8=N").unwrap().to_string(), "e7e8n");
+        assert_eq!(promotion.parse_san("e8Q").unwrap().to_string(), "e7e8q");
+        assert!(promotion.parse_san("e8").is_err());
+
+        let drops = State::from_fen_with_variant(
+            "4k3/8/8/8/8/8/8/4K3[NP] w - - 0 1",
+            crate::variant::Variant::Crazyhouse,
+        )
+        .unwrap();
+        assert_eq!(drops.parse_san("N@f3").unwrap().to_string(), "N@f3");
+        assert_eq!(drops.parse_san("@e4").unwrap().to_string(), "P@e4");
+        assert!(drops.parse_san("P@e8").is_err());
+        assert!(drops.parse_san("Q@d1").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+        ] {
+            let mut state = State::from_fen(fen).unwrap();
+            let hash = state.hash;
+            for m in state.get_all_moves_for_colour(state.turn) {
+                let san = state.move_to_san(m);
+                assert_eq!(state.hash, hash);
+                assert_eq!(state.parse_san(&san), Ok(m), "{san} in {fen}");
+            }
+        }
+    }
+}
@@ -0,0 +1,470 @@
+use crate::{
+    bitboard::square_index,
+    chess_move::{ChessMove, MoveKind},
+    eval::{Evaluator, StandardEvaluator},
+    piece::{PieceKind::*, *},
+    player::SearchLimits,
+    state::State,
+    termination::{GameResult, Termination},
+    tt::{Bound, TranspositionTable},
+};
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// centipawns, indexed by PieceKind::index; only used to order captures
+pub const PIECE_VALUES: [i32; 6] = [100, 500, 320, 330, 900, 20_000];
+
+// a mate n plies from the root scores MATE_SCORE - n, so that quicker mates are preferred
+pub const MATE_SCORE: i32 = 30_000;
+pub const MAX_PLY: usize = 128;
+// anything scoring beyond this is a forced mate
+pub const MATE_THRESHOLD: i32 = MATE_SCORE - MAX_PLY as i32;
+const INFINITY: i32 = 32_000;
+
+// iterative deepening stops here even without any other limit
+const MAX_DEPTH: u32 = 64;
+// how many nodes are searched between looking at the clock and the stop flag
+const CHECK_INTERVAL: u64 = 2048;
+
+// ordering scores: captures and promotions come first, then killers, then the history heuristic
+const CAPTURE_BONUS: i32 = 1_000_000;
+const KILLER_BONUS: i32 = 900_000;
+const HISTORY_LIMIT: i32 = 800_000;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SearchResult {
+    pub best_move: Option<ChessMove>,
+    // in centipawns, from the point of view of the side to move
+    pub score: i32,
+    // the deepest iteration that was completed
+    pub depth: u32,
+    pub nodes: u64,
+}
+
+// a finished game, scored like a mate the given number of plies from the root
+fn termination_score(termination: Termination, turn: ChessColour, ply: usize) -> i32 {
+    match termination.result() {
+        GameResult::Draw => 0,
+        result if result == GameResult::win_for(turn) => MATE_SCORE - ply as i32,
+        _ => -(MATE_SCORE - ply as i32),
+    }
+}
+
+// how long to think for with the given limits. None means until stopped or out of depth
+pub fn time_budget(limits: &SearchLimits, col: ChessColour) -> Option<Duration> {
+    if limits.infinite {
+        return None;
+    }
+    if limits.movetime.is_some() {
+        return limits.movetime;
+    }
+
+    // an even share of the remaining time plus most of the increment, but never more than half the clock
+    let time_left = limits.time_left[col.index()]?;
+    let increment = limits.increment[col.index()].unwrap_or_default();
+    let moves_to_go = limits.moves_to_go.unwrap_or(30).max(1);
+    Some((time_left / moves_to_go + increment * 3 / 4).min(time_left / 2))
+}
+
+// iterative deepening negamax with alpha-beta pruning and a quiescence search.
+// the transposition table and history heuristic carry over between searches, so one Searcher
+// is meant to last a whole game
+pub struct Searcher {
+    evaluator: Box<dyn Evaluator>,
+    tt: TranspositionTable,
+    // two quiet moves per ply that recently caused a beta cutoff
+    killers: [[Option<ChessMove>; 2]; MAX_PLY],
+    // how well quiet moves from one square to another have done, per colour
+    history: [[[i32; 64]; 64]; 2],
+    nodes: u64,
+    deadline: Option<Instant>,
+    stop: Arc<AtomicBool>,
+    stopped: bool,
+}
+
+impl Searcher {
+    pub fn new() -> Self {
+        Searcher::with_evaluator(Box::new(StandardEvaluator))
+    }
+
+    pub fn with_evaluator(evaluator: Box<dyn Evaluator>) -> Self {
+        Searcher {
+            evaluator,
+            tt: TranspositionTable::default(),
+            killers: [[None; 2]; MAX_PLY],
+            history: [[[0; 64]; 64]; 2],
+            nodes: 0,
+            deadline: None,
+            stop: Arc::default(),
+            stopped: false,
+        }
+    }
+
+    // the size of the transposition table in megabytes; clears it
+    pub fn set_hash_size(&mut self, size_mb: usize) {
+        self.tt.resize(size_mb);
+    }
+
+    // forgets everything learned from earlier searches, e.g. for a new game
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        self.killers = [[None; 2]; MAX_PLY];
+        self.history = [[[0; 64]; 64]; 2];
+    }
+
+    // the best move found within the limits. the state is handed back unchanged
+    pub fn search(&mut self, state: &mut State, limits: &SearchLimits) -> SearchResult {
+        let start = Instant::now();
+        let budget = time_budget(limits, state.turn);
+        self.deadline = budget.map(|b| start + b);
+        self.stop = limits.stop.clone();
+        self.stopped = false;
+        self.nodes = 0;
+        self.killers = [[None; 2]; MAX_PLY];
+        self.tt.new_search();
+        // old history still says something about the position, just not as much
+        for entry in self.history.iter_mut().flatten().flatten() {
+            *entry /= 2;
+        }
+
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 0,
+            nodes: 0,
+        };
+        if state.get_all_moves_for_colour(state.turn).is_empty() {
+            result.score = termination_score(state.no_moves_termination(), state.turn, 0);
+            return result;
+        }
+
+        let max_depth = limits.depth.unwrap_or(MAX_DEPTH).clamp(1, MAX_DEPTH);
+        for depth in 1..=max_depth {
+            let (score, best_move) = self.search_root(state, depth, result.best_move);
+            if !self.stopped {
+                self.tt
+                    .store(state.hash, depth, Bound::Exact, score, best_move, 0);
+            }
+
+            // an unfinished iteration can't be trusted, unless there is nothing better to go on
+            if self.stopped && result.best_move.is_some() {
+                break;
+            }
+            result = SearchResult {
+                best_move,
+                score,
+                depth,
+                nodes: self.nodes,
+            };
+            log!(
+                "depth {depth} score {score} nodes {} time {}ms best {}",
+                self.nodes,
+                start.elapsed().as_millis(),
+                best_move.unwrap()
+            );
+
+            // the next iteration takes longer than all previous ones together, so it won't finish anyway
+            if self.stopped || budget.is_some_and(|b| start.elapsed() > b / 2) {
+                break;
+            }
+        }
+
+        result.nodes = self.nodes;
+        result
+    }
+
+    fn search_root(
+        &mut self,
+        state: &mut State,
+        depth: u32,
+        previous_best: Option<ChessMove>,
+    ) -> (i32, Option<ChessMove>) {
+        let mut moves = state.get_all_moves_for_colour(state.turn);
+        self.order_moves(state, &mut moves, 0, previous_best);
+
+        let mut alpha = -INFINITY;
+        let mut best_move = moves.first().copied();
+        for chess_move in moves {
+            state.make_move(chess_move);
+            let score = -self.negamax(state, depth - 1, 1, -INFINITY, -alpha);
+            state.unmake_move();
+
+            if self.stopped {
+                break;
+            }
+            if score > alpha {
+                alpha = score;
+                best_move = Some(chess_move);
+            }
+        }
+
+        (alpha, best_move)
+    }
+
+    fn negamax(
+        &mut self,
+        state: &mut State,
+        depth: u32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        if self.should_stop() {
+            return 0;
+        }
+        // a position that has been seen before in the game or the tree is as good as a draw
+        if state.halfmove_clock >= 100
+            || state.repetition_count() >= 2
+            || state.is_insufficient_material()
+        {
+            return 0;
+        }
+
+        let in_check = state.is_in_check(state.turn);
+        // checks are searched a ply deeper, so that they don't get pushed past the horizon
+        let depth = if in_check { depth + 1 } else { depth };
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(state, ply, alpha, beta);
+        }
+        self.nodes += 1;
+
+        // an earlier search of this position may already settle it, or at least say what to try first
+        let original_alpha = alpha;
+        let tt_entry = self.tt.probe(state.hash, ply);
+        if let Some(entry) = tt_entry.filter(|e| e.depth as u32 >= depth) {
+            match entry.bound {
+                Bound::Exact => return entry.score,
+                Bound::Lower if entry.score >= beta => return entry.score,
+                Bound::Upper if entry.score <= alpha => return entry.score,
+                _ => {}
+            }
+        }
+
+        let mut moves = state.get_all_moves_for_colour(state.turn);
+        if moves.is_empty() {
+            return termination_score(state.no_moves_termination(), state.turn, ply);
+        }
+        self.order_moves(state, &mut moves, ply, tt_entry.and_then(|e| e.best_move));
+
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        for chess_move in moves {
+            state.make_move(chess_move);
+            let score = -self.negamax(state, depth - 1, ply + 1, -beta, -alpha);
+            state.unmake_move();
+
+            if self.stopped {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                best_move = Some(chess_move);
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                if is_quiet(chess_move) {
+                    self.record_cutoff(state.turn, chess_move, ply, depth);
+                }
+                break;
+            }
+        }
+
+        let bound = if best <= original_alpha {
+            Bound::Upper
+        } else if best >= beta {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        // after failing low every move was just as bad, so there's no best one to remember
+        let best_move = best_move.filter(|_| bound != Bound::Upper);
+        self.tt
+            .store(state.hash, depth, bound, best, best_move, ply);
+
+        best
+    }
+
+    // only captures and promotions are searched, until the position is quiet enough to evaluate
+    fn quiescence(&mut self, state: &mut State, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        if self.should_stop() {
+            return 0;
+        }
+        self.nodes += 1;
+
+        // a capture can also reach a variant's goal, e.g. a king taking on the hill
+        if let Some(termination) = state.variant_termination() {
+            return termination_score(termination, state.turn, ply);
+        }
+
+        // the side to move can usually do at least as well as the static evaluation
+        let stand_pat = self.evaluator.evaluate(state);
+        if stand_pat >= beta || ply >= MAX_PLY - 1 {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let mut moves = state.get_all_moves_for_colour(state.turn);
+        moves.retain(|m| !is_quiet(*m));
+        self.order_moves(state, &mut moves, ply, None);
+
+        let mut best = stand_pat;
+        for chess_move in moves {
+            state.make_move(chess_move);
+            let score = -self.quiescence(state, ply + 1, -beta, -alpha);
+            state.unmake_move();
+
+            if self.stopped {
+                return 0;
+            }
+            best = best.max(score);
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        best
+    }
+
+    fn should_stop(&mut self) -> bool {
+        if !self.stopped && self.nodes.is_multiple_of(CHECK_INTERVAL) {
+            self.stopped = self.stop.load(Ordering::Relaxed)
+                || self.deadline.is_some_and(|d| Instant::now() >= d);
+        }
+        self.stopped
+    }
+
+    fn record_cutoff(&mut self, col: ChessColour, chess_move: ChessMove, ply: usize, depth: u32) {
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(chess_move) {
+            killers[1] = killers[0];
+            killers[0] = Some(chess_move);
+        }
+
+        let (src, dst) = (square_index(chess_move.src), square_index(chess_move.dst));
+        let entry = &mut self.history[col.index()][src][dst];
+        *entry += (depth * depth) as i32;
+        // kept below the killers, by scaling everything down once it gets too big
+        if *entry > HISTORY_LIMIT {
+            for entry in self.history[col.index()].iter_mut().flatten() {
+                *entry /= 2;
+            }
+        }
+    }
+
+    // the best move so far goes first, then the rest by how likely they are to cause a cutoff
+    fn order_moves(
+        &self,
+        state: &State,
+        moves: &mut [ChessMove],
+        ply: usize,
+        best_first: Option<ChessMove>,
+    ) {
+        moves.sort_by_cached_key(|m| {
+            if Some(*m) == best_first {
+                i32::MIN
+            } else {
+                -self.move_score(state, *m, ply)
+            }
+        });
+    }
+
+    fn move_score(&self, state: &State, chess_move: ChessMove, ply: usize) -> i32 {
+        let attacker = state.moving_piece(chess_move);
+        let promotion = match chess_move.kind {
+            MoveKind::Promotion { promote_to, .. } => PIECE_VALUES[promote_to.index()],
+            _ => 0,
+        };
+        let victim = match chess_move.kind {
+            MoveKind::EnPassant => Some(Pawn),
+            // a Chess960 king may castle onto its own rook
+            MoveKind::ShortCastle | MoveKind::LongCastle => None,
+            _ => state[chess_move.dst].content.map(|p| p.kind),
+        };
+
+        if let Some(victim) = victim {
+            // most valuable victim, least valuable attacker
+            CAPTURE_BONUS + promotion + 10 * PIECE_VALUES[victim.index()]
+                - PIECE_VALUES[attacker.kind.index()]
+        } else if promotion > 0 {
+            CAPTURE_BONUS + promotion
+        } else if self.killers[ply].contains(&Some(chess_move)) {
+            KILLER_BONUS
+        } else {
+            let (src, dst) = (square_index(chess_move.src), square_index(chess_move.dst));
+            self.history[attacker.colour.index()][src][dst]
+        }
+    }
+}
+
+impl Default for Searcher {
+    fn default() -> Self {
+        Searcher::new()
+    }
+}
+
+fn is_quiet(chess_move: ChessMove) -> bool {
+    !chess_move.is_capture() && !matches!(chess_move.kind, MoveKind::Promotion { .. })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn search(fen: &str, depth: u32) -> SearchResult {
+        let mut state = State::from_fen(fen).unwrap();
+        let limits = SearchLimits {
+            depth: Some(depth),
+            ..Default::default()
+        };
+        let result = Searcher::new().search(&mut state, &limits);
+        assert_eq!(state.to_fen(), fen, "the search left the position changed");
+        result
+    }
+
+    #[test]
+    fn finds_mates() {
+        let back_rank = search("6k1/5ppp/8/8/8/8/5PPP/3R2K1 w - - 0 1", 2);
+        assert_eq!(back_rank.best_move.unwrap().to_string(), "d1d8");
+        assert_eq!(back_rank.score, MATE_SCORE - 1);
+
+        // the rooks take turns cutting off the king
+        let ladder = search("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1", 3);
+        assert_eq!(ladder.score, MATE_SCORE - 3);
+
+        let mated = search("R6k/8/7K/8/8/8/8/8 b - - 0 1", 3);
+        assert_eq!(mated.best_move, None);
+        assert_eq!(mated.score, -MATE_SCORE);
+    }
+
+    #[test]
+    fn wins_material() {
+        // a free queen
+        let result = search("4k3/8/8/3q4/8/2N5/8/4K3 w - - 0 1", 3);
+        assert_eq!(result.best_move.unwrap().to_string(), "c3d5");
+
+        // taking the pawn loses the queen to the other pawn, so the quiescence search has to see that
+        let poisoned = search("4k3/8/2p5/3p4/8/8/8/3QK3 w - - 0 1", 1);
+        assert_ne!(poisoned.best_move.unwrap().to_string(), "d1d5");
+    }
+
+    #[test]
+    fn captures_are_ordered_first() {
+        let state = State::from_fen("4k3/8/8/3q4/2P5/8/3Q4/4K3 w - - 0 1").unwrap();
+        let mut moves = state.get_all_moves_for_colour(state.turn);
+        Searcher::new().order_moves(&state, &mut moves, 0, None);
+        assert_eq!(moves[0].to_string(), "c4d5");
+        assert_eq!(moves[1].to_string(), "d2d5");
+    }
+
+    #[test]
+    fn stops_when_asked() {
+        let mut state = State::new();
+        let limits = SearchLimits::default();
+        limits.stop.store(true, Ordering::Relaxed);
+        let result = Searcher::new().search(&mut state, &limits);
+        assert!(result.best_move.is_some());
+    }
+}
@@ -1,6 +1,4 @@
-use crate::Piece;
-
-use sdl2::pixels::Color;
+use crate::piece::Piece;
 
 #[derive(Clone, Copy, Debug)]
 pub struct Square {
@@ -8,6 +6,12 @@ pub struct Square {
     pub content: Option<Piece>,
 }
 
+impl Default for Square {
+    fn default() -> Self {
+        Square::new()
+    }
+}
+
 impl Square {
     pub fn new() -> Self {
         Square {
@@ -16,20 +20,9 @@ impl Square {
         }
     }
 
-    pub fn colour(&self, mouse_over: bool) -> Color {
-        if (self.coords.0 + self.coords.1) % 2 == 1 {
-            if mouse_over {
-                Color::RGB(141, 177, 196)
-            } else {
-                Color::RGB(161, 222, 255)
-            }
-        } else {
-            if mouse_over {
-                Color::RGB(4, 38, 56)
-            } else {
-                Color::RGB(0, 79, 122)
-            }
-        }
+    // a1 is dark, h1 is light
+    pub fn is_light(&self) -> bool {
+        (self.coords.0 + self.coords.1) % 2 == 1
     }
 
     pub fn coord(&self) -> String {
@@ -38,3 +31,15 @@ impl Square {
         format!("{file}{rank}")
     }
 }
+
+// inverse of Square::coord; "a1" is (0, 0)
+pub fn parse_coord(text: &str) -> Option<(u8, u8)> {
+    let mut chars = text.chars();
+    let file = chars.next()?;
+    let rank = chars.next()?;
+    if chars.next().is_some() || !('a'..='h').contains(&file) || !('1'..='8').contains(&rank) {
+        return None;
+    }
+
+    Some((file as u8 - b'a', rank as u8 - b'1'))
+}
@@ -1,566 +1,748 @@
 use crate::{
+    bitboard::*,
     chess_move::*,
+    fen::STARTING_FEN,
     piece::{PieceKind::*, *},
     square::*,
+    termination::Termination,
+    variant::{blast_squares, Variant},
+    zobrist::ZOBRIST,
 };
 
-use std::collections::HashSet;
-use std::ops::{Index, IndexMut};
-use std::sync::{Arc, Mutex};
+use std::ops::Index;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct CastlingRights {
+    pub white_short: bool,
+    pub white_long: bool,
+    pub black_short: bool,
+    pub black_long: bool,
+}
+
+impl CastlingRights {
+    pub fn get(&self, col: ChessColour, long_castle: bool) -> bool {
+        match (col, long_castle) {
+            (ChessColour::White, false) => self.white_short,
+            (ChessColour::White, true) => self.white_long,
+            (ChessColour::Black, false) => self.black_short,
+            (ChessColour::Black, true) => self.black_long,
+        }
+    }
+
+    pub fn set(&mut self, col: ChessColour, long_castle: bool, value: bool) {
+        match (col, long_castle) {
+            (ChessColour::White, false) => self.white_short = value,
+            (ChessColour::White, true) => self.white_long = value,
+            (ChessColour::Black, false) => self.black_short = value,
+            (ChessColour::Black, true) => self.black_long = value,
+        }
+    }
+
+    pub fn remove(&mut self, col: ChessColour, long_castle: bool) {
+        self.set(col, long_castle, false);
+    }
+}
+
+pub fn back_rank(col: ChessColour) -> u8 {
+    match col {
+        ChessColour::White => 0,
+        ChessColour::Black => 7,
+    }
+}
 
 #[derive(Clone)]
 pub struct State {
+    // the board is stored twice: once square by square for the GUI and Index,
+    // and once as bitboards for move generation. only ever change it through set_piece,
+    // which keeps the two in sync
     pub squares: [Square; 64],
+    // indexed by ChessColour::index and then PieceKind::index
+    pub pieces: [[Bitboard; 6]; 2],
+    // all pieces per colour
+    pub occupancy: [Bitboard; 2],
     pub turn: ChessColour,
-    pub selected_square: Option<(u8, u8)>,
-    pub mouse_pressed_previous: bool,
-    pub game_running: bool,
+    pub variant: Variant,
+    // how many times each side has given check, for three-check
+    pub checks_given: [u8; 2],
+    // captured pieces waiting to be dropped in crazyhouse, indexed by ChessColour::index and then
+    // PieceKind::index. kings can't be captured, so there's no room for them
+    pub pockets: [[u8; 5]; 2],
+    // the squares of pieces that started out as pawns, which go back to being pawns when captured
+    // in crazyhouse
+    pub promoted: Bitboard,
+    // zobrist hash of the position, see zobrist.rs
+    pub hash: u64,
+    // set once the game is over; see termination.rs
+    pub termination: Option<Termination>,
+    // doubles as the undo stack for unmake_move
     pub history: Vec<PerformedMove>,
+    pub castling_rights: CastlingRights,
+    // the files the castling rooks start on, indexed by ChessColour::index and then by
+    // long_castle as usize. always h and a, except in Chess960
+    pub castling_files: [[u8; 2]; 2],
+    // Chess960 castling is written as the king taking its own rook, in UCI moves and in FENs
+    // whenever the rook isn't the outermost one
+    pub chess960: bool,
+    // the square a pawn skipped over with a double push on the previous move
+    pub en_passant: Option<(u8, u8)>,
+    // the piece the GUI promotes to when a human player moves a pawn to the last rank
     pub next_promotor: PieceKind,
+    // plies since the last capture or pawn move, for the fifty-move rule
+    pub halfmove_clock: u32,
+    // starts at 1 and is incremented after every black move
+    pub fullmove_number: u32,
 }
 
 impl State {
     pub fn new() -> Self {
+        State::from_fen(STARTING_FEN).expect("Starting position FEN is malformed")
+    }
+
+    // a board without any pieces on it, used as the starting point for FEN parsing
+    pub fn empty() -> Self {
         let mut squares = [Square::new(); 64];
         for y in 0..8u8 {
             for x in 0..8u8 {
-                let idx = (x + 8 * y) as usize;
-                squares[idx].coords = (x, y);
-                squares[idx].content = Piece::from_char(match (x, y) {
-                    (_, 1) => 'P',
-                    (_, 6) => 'p',
-
-                    (0, 0) | (7, 0) => 'R',
-                    (0, 7) | (7, 7) => 'r',
-
-                    (1, 0) | (6, 0) => 'N',
-                    (1, 7) | (6, 7) => 'n',
-
-                    (2, 0) | (5, 0) => 'B',
-                    (2, 7) | (5, 7) => 'b',
-
-                    (3, 0) => 'Q',
-                    (3, 7) => 'q',
-
-                    (4, 0) => 'K',
-                    (4, 7) => 'k',
-
-                    _ => ' ', // intentionally give an invalid character to the from_char function
-                });
+                squares[(x + 8 * y) as usize].coords = (x, y);
             }
         }
 
         State {
             squares,
+            pieces: [[0; 6]; 2],
+            occupancy: [0; 2],
             turn: ChessColour::White,
-            selected_square: None,
-            mouse_pressed_previous: false,
-            game_running: true,
+            variant: Variant::Standard,
+            checks_given: [0; 2],
+            pockets: [[0; 5]; 2],
+            promoted: 0,
+            hash: 0,
+            termination: None,
             history: Vec::new(),
+            castling_rights: CastlingRights::default(),
+            castling_files: [[7, 0]; 2],
+            chess960: false,
+            en_passant: None,
             next_promotor: Queen,
+            halfmove_clock: 0,
+            fullmove_number: 1,
         }
     }
 
+    pub fn set_piece(&mut self, coord: (u8, u8), content: Option<Piece>) {
+        let mask = bit(coord);
+        if let Some(old) = self[coord].content {
+            self.pieces[old.colour.index()][old.kind.index()] &= !mask;
+            self.occupancy[old.colour.index()] &= !mask;
+            self.hash ^= ZOBRIST.piece(old, coord);
+        }
+        if let Some(new) = content {
+            self.pieces[new.colour.index()][new.kind.index()] |= mask;
+            self.occupancy[new.colour.index()] |= mask;
+            self.hash ^= ZOBRIST.piece(new, coord);
+        }
+        self.squares[square_index(coord)].content = content;
+    }
+
+    // the pocket counterpart of set_piece, which keeps the hash up to date
+    pub fn set_pocket(&mut self, col: ChessColour, kind: PieceKind, count: u8) {
+        let held = self.pockets[col.index()][kind.index()];
+        self.hash ^= ZOBRIST.pocket(col, kind, held) ^ ZOBRIST.pocket(col, kind, count);
+        self.pockets[col.index()][kind.index()] = count;
+    }
+
+    // the piece making the move, which for a drop comes out of the pocket of the side to move
+    pub fn moving_piece(&self, chess_move: ChessMove) -> Piece {
+        match chess_move.kind {
+            MoveKind::Drop(kind) => Piece {
+                kind,
+                colour: self.turn,
+            },
+            _ => self[chess_move.src]
+                .content
+                .unwrap_or_else(|| panic!("{:?}", self.squares)),
+        }
+    }
+
+    pub fn occupied(&self) -> Bitboard {
+        self.occupancy[0] | self.occupancy[1]
+    }
+
     pub fn get_king_coord(&self, col: ChessColour) -> (u8, u8) {
-        self.squares
-            .into_iter()
-            .find(|s| {
-                if let Some(p) = s.content {
-                    p.kind == PieceKind::King && p.colour == col
-                } else {
-                    false
-                }
-            })
-            .expect(&format!("{:?}", self.squares))
-            .coords
+        self.find_king(col)
+            .unwrap_or_else(|| panic!("{col:?} has no king on the board"))
+    }
+
+    // for the variants where a side may not have a king (any more)
+    pub fn find_king(&self, col: ChessColour) -> Option<(u8, u8)> {
+        let kings = self.pieces[col.index()][King.index()];
+        (kings != 0).then(|| index_coord(kings.trailing_zeros() as usize))
     }
 
+    // never, if the variant doesn't treat the king as royal, or there's no king. in atomic,
+    // kings that touch can't be in check, as taking one would blow up the other
     pub fn is_in_check(&self, col: ChessColour) -> bool {
-        let king_coord = self.get_king_coord(col);
-        for enemy_piece in self
-            .squares
-            .into_iter()
-            .filter(|s| s.content.is_some())
-            .map(|s| s.coords)
-        {
-            let enemy_moves = self
-                .get_moves(enemy_piece, false)
-                .into_iter()
-                .map(|m| m.dst)
-                .collect::<Vec<_>>();
-            if enemy_moves.contains(&king_coord) {
-                return true;
-            }
+        let Some(king) = self.find_king(col).filter(|_| self.variant.royal_king()) else {
+            return false;
+        };
+        let enemy_king = self.pieces[col.flip().index()][King.index()];
+        if self.variant == Variant::Atomic && KING_ATTACKS[square_index(king)] & enemy_king != 0 {
+            return false;
+        }
+        self.is_attacked(king, col.flip())
+    }
+
+    // whether any piece of colour `by` attacks the given square, regardless of whose turn it is
+    pub fn is_attacked(&self, coord: (u8, u8), by: ChessColour) -> bool {
+        State::square_attacked(&self.pieces, self.occupied(), square_index(coord), by)
+    }
+
+    // works backwards from the square: e.g. a knight on any square a knight could jump to from here attacks it.
+    // takes the bitboards as arguments so that legality testing can ask about hypothetical boards
+    fn square_attacked(
+        pieces: &[[Bitboard; 6]; 2],
+        occupied: Bitboard,
+        index: usize,
+        by: ChessColour,
+    ) -> bool {
+        let attackers = &pieces[by.index()];
+        let straight = attackers[Rook.index()] | attackers[Queen.index()];
+        let diagonal = attackers[Bishop.index()] | attackers[Queen.index()];
+
+        // an attacking pawn sits where a pawn of the other colour on this square would capture
+        PAWN_ATTACKS[by.flip().index()][index] & attackers[Pawn.index()] != 0
+            || KNIGHT_ATTACKS[index] & attackers[Knight.index()] != 0
+            || KING_ATTACKS[index] & attackers[King.index()] != 0
+            || rook_attacks(index, occupied) & straight != 0
+            || bishop_attacks(index, occupied) & diagonal != 0
+    }
+
+    // the square the castling rook starts on
+    pub fn castling_rook(&self, col: ChessColour, long_castle: bool) -> (u8, u8) {
+        (
+            self.castling_files[col.index()][long_castle as usize],
+            back_rank(col),
+        )
+    }
+
+    // where the king and rook end up, which is the same as in classical chess wherever they started
+    pub fn castling_targets(col: ChessColour, long_castle: bool) -> ((u8, u8), (u8, u8)) {
+        let rank = back_rank(col);
+        if long_castle {
+            ((2, rank), (3, rank))
+        } else {
+            ((6, rank), (5, rank))
         }
+    }
 
-        false
+    // the rook a castling move castles with, or None for any other move
+    pub fn castling_rook_of(&self, chess_move: ChessMove) -> Option<(u8, u8)> {
+        let long_castle = match chess_move.kind {
+            MoveKind::ShortCastle => false,
+            MoveKind::LongCastle => true,
+            _ => return None,
+        };
+        let col = if chess_move.src.1 == 0 {
+            ChessColour::White
+        } else {
+            ChessColour::Black
+        };
+        Some(self.castling_rook(col, long_castle))
     }
 
     // assumes the necessary checks have been performed
     pub fn perform_castle(&mut self, long_castle: bool, col: ChessColour) {
-        let rook_coord: (u8, u8) = (
-            if long_castle { 0 } else { 7 },
-            if col == ChessColour::White { 0 } else { 7 },
-        );
         let king_coord = self.get_king_coord(col);
+        let rook_coord = self.castling_rook(col, long_castle);
+        let (king_target, rook_target) = State::castling_targets(col, long_castle);
+
+        // pieces are moved directly, as going through make_move would flip the turn and tick the clocks.
+        // in Chess960 either one may land where the other started, so both are lifted off first
+        let (king, rook) = (self[king_coord].content, self[rook_coord].content);
+        self.set_piece(king_coord, None);
+        self.set_piece(rook_coord, None);
+        self.set_piece(king_target, king);
+        self.set_piece(rook_target, rook);
+    }
+
+    // returns the undo record, which is also pushed onto the history
+    pub fn make_move(&mut self, chess_move: ChessMove) -> PerformedMove {
+        let ChessMove { src, dst, kind } = chess_move;
+        let moving_piece = self.moving_piece(chess_move);
 
-        let rook_target: (u8, u8) = (if long_castle { 3 } else { 5 }, rook_coord.1);
-        let king_target: (u8, u8) = (if long_castle { 2 } else { 6 }, king_coord.1);
-
-        self.make_move(rook_coord, ChessMove::dummy(rook_target));
-        self.make_move(king_coord, ChessMove::dummy(king_target));
-    }
-
-    pub fn make_move(&mut self, src: (u8, u8), chess_move: ChessMove) {
-        let mut function = chess_move
-            .function
-            .lock()
-            .expect("Could not acquire mutex lock on move function");
-        if !(function)(self) {
-            // if executing the move's function didn't already handle piece movement for us,
-            // it has to be done "manually" like this:
-            let dst = chess_move.dst;
-            self[dst].content = Some(Piece {
-                has_moved: true,
-                ..self[src]
-                    .content
-                    .unwrap_or_else(|| panic!("{:?}", self.squares))
-            });
-            self[src].content = None;
+        let captured_square = match kind {
+            MoveKind::EnPassant => (dst.0, src.1),
+            _ => dst,
+        };
+        let captured = match kind {
+            // a Chess960 king may castle onto its own rook
+            MoveKind::ShortCastle | MoveKind::LongCastle => None,
+            _ => self[captured_square].content,
+        };
+        let mut performed = PerformedMove {
+            chess_move,
+            captured,
+            castling_rights: self.castling_rights,
+            en_passant: self.en_passant,
+            halfmove_clock: self.halfmove_clock,
+            checks_given: self.checks_given,
+            pockets: self.pockets,
+            promoted: self.promoted,
+            exploded: [None; 9],
+            hash: self.hash,
         };
 
-        self.turn = self.turn.flip();
+        if self.variant.has_pockets() {
+            if let Some(captured) = captured {
+                let kind = if self.promoted & bit(captured_square) != 0 {
+                    Pawn
+                } else {
+                    captured.kind
+                };
+                let held = self.pockets[self.turn.index()][kind.index()];
+                self.set_pocket(self.turn, kind, held + 1);
+            }
+            // a promoted piece stays promoted wherever it goes
+            let promoted =
+                self.promoted & bit(src) != 0 || matches!(kind, MoveKind::Promotion { .. });
+            self.promoted &= !(bit(src) | bit(captured_square));
+            if promoted {
+                self.promoted |= bit(dst);
+            }
+        }
 
-        // explicit drop call for mutex stuff
-        drop(function);
-    }
-
-    pub fn get_all_moves_for_colour(&self, col: ChessColour) -> Vec<((u8, u8), ChessMove)> {
-        self.squares
-            .iter()
-            .map(|s| (s.coords, s.content))
-            .filter(|(coord, content)| content.is_some())
-            .map(|(coord, content)| {
-                self.get_moves(coord, true)
-                    .iter()
-                    .cloned()
-                    .map(|generated_move| (coord, generated_move))
-                    .collect::<Vec<_>>()
-            })
-            .flatten()
-            .collect()
-    }
-
-    // returns whether or not the move was correctly carried out
-    pub fn attempt_move(&mut self, target_coordinate: (u8, u8)) -> bool {
-        //TODO: implement this properly
-        let source_coordinate = self.selected_square.unwrap();
-        let source_piece = self[source_coordinate].content.unwrap();
-        let target_square = self[target_coordinate];
-
-        let mut return_value = false;
-
-        if source_coordinate != target_coordinate {
-            if !(target_square.content.is_some()
-                && target_square.content.unwrap().colour == source_piece.colour)
-            {
-                let moves = self.get_moves(source_coordinate, true);
-                //TODO: rewrite
-                if moves.contains(&ChessMove::dummy(target_coordinate)) {
-                    let move_to_be_made = moves
-                        .into_iter()
-                        .filter(|m| *m == target_coordinate)
-                        .next()
-                        .unwrap();
-                    self.make_move(source_coordinate, move_to_be_made);
-                    return_value = true;
+        // pieces are hashed by set_piece, everything else is swapped out here and back in below
+        self.hash ^= ZOBRIST.castling_rights(self.castling_rights) ^ self.en_passant_key();
+
+        match kind {
+            MoveKind::ShortCastle => self.perform_castle(false, moving_piece.colour),
+            MoveKind::LongCastle => self.perform_castle(true, moving_piece.colour),
+            MoveKind::EnPassant => self.perform_en_passant(src, dst),
+            MoveKind::Promotion { promote_to, .. } => self.promote_pawn(src, dst, promote_to),
+            MoveKind::Drop(dropped) => {
+                let held = self.pockets[self.turn.index()][dropped.index()];
+                self.set_pocket(self.turn, dropped, held - 1);
+                self.set_piece(dst, Some(moving_piece));
+            }
+            MoveKind::Quiet | MoveKind::Capture | MoveKind::DoublePush => {
+                self.set_piece(dst, Some(moving_piece));
+                self.set_piece(src, None);
+            }
+        }
+
+        // in atomic, the capturing piece goes up along with everything around it but pawns
+        let mut blown_up = 0;
+        if self.variant == Variant::Atomic && captured.is_some() {
+            let pawns = self.pieces[0][Pawn.index()] | self.pieces[1][Pawn.index()];
+            for (i, coord) in blast_squares(dst).enumerate() {
+                if coord == dst || pawns & bit(coord) == 0 {
+                    performed.exploded[i] = self[coord].content;
+                    self.set_piece(coord, None);
+                    blown_up |= bit(coord);
                 }
             }
         }
-        self.selected_square = None;
 
-        return_value
+        // moving the king loses both rights, and so does losing it; anything leaving, arriving
+        // at or blowing up a castling rook's square means that rook is gone
+        for col in [ChessColour::White, ChessColour::Black] {
+            let king_gone = self.pieces[col.index()][King.index()] == 0;
+            for long_castle in [false, true] {
+                let rook = self.castling_rook(col, long_castle);
+                let king_moved = moving_piece.kind == King && moving_piece.colour == col;
+                if src == rook
+                    || dst == rook
+                    || blown_up & bit(rook) != 0
+                    || king_moved
+                    || king_gone
+                {
+                    self.castling_rights.remove(col, long_castle);
+                }
+            }
+        }
+
+        // the chance to capture en passant only lasts for a single reply
+        self.en_passant = if kind == MoveKind::DoublePush {
+            Some((src.0, (src.1 + dst.1) / 2))
+        } else {
+            None
+        };
+
+        if moving_piece.kind == Pawn || captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if self.turn == ChessColour::Black {
+            self.fullmove_number += 1;
+        }
+
+        self.turn = self.turn.flip();
+
+        if self.variant == Variant::ThreeCheck && self.is_in_check(self.turn) {
+            let checker = self.turn.flip().index();
+            let checks = &mut self.checks_given[checker];
+            self.hash ^= ZOBRIST.checks_given[checker][*checks as usize];
+            *checks = (*checks + 1).min(3);
+            self.hash ^= ZOBRIST.checks_given[checker][*checks as usize];
+        }
+
+        self.hash ^= ZOBRIST.castling_rights(self.castling_rights)
+            ^ self.en_passant_key()
+            ^ ZOBRIST.black_to_move;
+
+        self.history.push(performed);
+        performed
+    }
+
+    // takes back the last move in the history, restoring the position exactly
+    pub fn unmake_move(&mut self) -> Option<PerformedMove> {
+        let performed = self.history.pop()?;
+        let ChessMove { src, dst, kind } = performed.chess_move;
+
+        self.turn = self.turn.flip();
+        if self.turn == ChessColour::Black {
+            self.fullmove_number -= 1;
+        }
+
+        // whatever an atomic capture blew up goes back first, after which it's like any other capture
+        for (coord, piece) in blast_squares(dst).zip(performed.exploded) {
+            if piece.is_some() {
+                self.set_piece(coord, piece);
+            }
+        }
+
+        let moved_piece = self[dst].content;
+        match kind {
+            MoveKind::ShortCastle | MoveKind::LongCastle => {
+                let long_castle = kind == MoveKind::LongCastle;
+                let rook_coord = self.castling_rook(self.turn, long_castle);
+                let (_, rook_target) = State::castling_targets(self.turn, long_castle);
+                let rook = self[rook_target].content;
+                self.set_piece(dst, None);
+                self.set_piece(rook_target, None);
+                self.set_piece(src, moved_piece);
+                self.set_piece(rook_coord, rook);
+            }
+            MoveKind::EnPassant => {
+                self.set_piece(src, moved_piece);
+                self.set_piece(dst, None);
+                self.set_piece((dst.0, src.1), performed.captured);
+            }
+            MoveKind::Promotion { .. } => {
+                self.set_piece(
+                    src,
+                    Some(Piece {
+                        kind: Pawn,
+                        colour: self.turn,
+                    }),
+                );
+                self.set_piece(dst, performed.captured);
+            }
+            MoveKind::Drop(_) => self.set_piece(dst, None),
+            MoveKind::Quiet | MoveKind::Capture | MoveKind::DoublePush => {
+                self.set_piece(src, moved_piece);
+                self.set_piece(dst, performed.captured);
+            }
+        }
+
+        self.castling_rights = performed.castling_rights;
+        self.en_passant = performed.en_passant;
+        self.halfmove_clock = performed.halfmove_clock;
+        self.checks_given = performed.checks_given;
+        self.pockets = performed.pockets;
+        self.promoted = performed.promoted;
+        self.hash = performed.hash;
+
+        Some(performed)
+    }
+
+    pub fn get_all_moves_for_colour(&self, col: ChessColour) -> Vec<ChessMove> {
+        // once a variant's goal has been reached there's nothing left to play
+        if self.variant_termination().is_some() {
+            return Vec::new();
+        }
+        self.legal_moves(col)
+    }
+
+    // the same, whether or not the game is already over
+    pub fn legal_moves(&self, col: ChessColour) -> Vec<ChessMove> {
+        let mut moves = squares_of(self.occupancy[col.index()])
+            .flat_map(|coord| self.get_pseudo_legal_moves(coord))
+            .collect::<Vec<_>>();
+        // drops always come from the pocket of the side to move
+        if col == self.turn {
+            moves.extend(self.drop_moves());
+        }
+        self.retain_legal(&mut moves);
+        self.variant.restrict_moves(&mut moves);
+        moves
     }
 
     // assumes the move's availability checks have been performed properly
-    pub fn promote_pawn(&mut self, src: (u8, u8), dst: (u8, u8)) {
-        match self.next_promotor {
-            Pawn | King => unreachable!(),
+    pub fn promote_pawn(&mut self, src: (u8, u8), dst: (u8, u8), promote_to: PieceKind) {
+        match promote_to {
+            Pawn => unreachable!(),
             _ => {
-                self[dst].content = Some(Piece {
-                    kind: self.next_promotor,
-                    colour: self[src].content.unwrap().colour,
-                    has_moved: true,
-                    en_passanteable: false,
-                });
-                self[src].content = None;
+                self.set_piece(
+                    dst,
+                    Some(Piece {
+                        kind: promote_to,
+                        colour: self[src].content.unwrap().colour,
+                    }),
+                );
+                self.set_piece(src, None);
             }
         }
     }
 
-    pub fn en_passant(&mut self, src: (u8, u8), dst: (u8, u8)) {
-        self[dst].content = self[src].content;
-        self[(dst.0, src.1)].content = None;
-        self[src].content = None;
+    pub fn perform_en_passant(&mut self, src: (u8, u8), dst: (u8, u8)) {
+        self.set_piece(dst, self[src].content);
+        self.set_piece((dst.0, src.1), None);
+        self.set_piece(src, None);
     }
 
     pub fn get_moves(&self, coord: (u8, u8), test_for_checks: bool) -> Vec<ChessMove> {
-        //determine piece type, and possible move offsets
-        let piece = self[coord].content.unwrap();
+        if test_for_checks {
+            // the variant's restrictions may depend on what the other pieces can do
+            let col = self[coord].content.unwrap().colour;
+            return self
+                .get_all_moves_for_colour(col)
+                .into_iter()
+                .filter(|m| m.src == coord)
+                .collect();
+        }
+        self.get_pseudo_legal_moves(coord)
+    }
 
-        let mut moves: HashSet<(u8, u8)> = HashSet::new();
+    // we need to test if a move would cause the player to be in check.
+    // instead of playing the move, only the bitboards are updated on a copy
+    fn retain_legal(&self, moves: &mut Vec<ChessMove>) {
+        if !self.variant.royal_king() {
+            return;
+        }
+        moves.retain(|possibly_checking_move| !self.leaves_king_in_check(*possibly_checking_move));
+    }
 
-        // for moves whose function we already know during move calculation
-        let mut moves_with_fn = Vec::new();
-        let boxed_coord = Box::new(coord);
-        let static_coord: &'static (u8, u8) = Box::<(u8, u8)>::leak(boxed_coord);
+    fn leaves_king_in_check(&self, chess_move: ChessMove) -> bool {
+        let ChessMove { src, dst, kind } = chess_move;
+        let piece = self.moving_piece(chess_move);
+        let (own, enemy) = (piece.colour, piece.colour.flip());
 
-        if piece.kind.is_sliding() {
-            let offsets: &[(i8, i8)] = match piece.kind {
-                Queen => &QUEEN_OFFSETS,
-                Rook => &ROOK_OFFSETS,
-                Bishop => &BISHOP_OFFSETS,
-                _ => unreachable!("Supposed sliding piece isn't a queen, rook or bishop"),
-            };
+        let mut pieces = self.pieces;
+        let captured_square = if kind == MoveKind::EnPassant {
+            bit((dst.0, src.1))
+        } else {
+            bit(dst)
+        };
+        for enemy_kind in pieces[enemy.index()].iter_mut() {
+            *enemy_kind &= !captured_square;
+        }
+        let pawns = pieces[0][Pawn.index()] | pieces[1][Pawn.index()];
+        // a promoting pawn blocks the same lines as the piece it turns into, and a dropped piece
+        // just appears, as its src is its dst. a Chess960 king may
+        // stay where it is when castling, so it is taken off and put back rather than toggled
+        let moved = &mut pieces[own.index()][piece.kind.index()];
+        *moved = (*moved & !bit(src)) | bit(dst);
+        // the castling rook can uncover an attack along the back rank
+        if let Some(rook) = self.castling_rook_of(chess_move) {
+            let long_castle = kind == MoveKind::LongCastle;
+            let (_, rook_target) = State::castling_targets(own, long_castle);
+            let rooks = &mut pieces[own.index()][Rook.index()];
+            *rooks = (*rooks & !bit(rook)) | bit(rook_target);
+        }
 
-            for direction in offsets {
-                let mut current_coord = coord;
-                loop {
-                    let next_coord = (
-                        current_coord.0 as i8 + direction.0,
-                        current_coord.1 as i8 + direction.1,
-                    );
+        if self.variant == Variant::Atomic && chess_move.is_capture() {
+            let blast = bit(dst) | KING_ATTACKS[square_index(dst)] & !pawns;
+            for bitboard in pieces.iter_mut().flatten() {
+                *bitboard &= !blast;
+            }
+        }
 
-                    if !(0..8).contains(&next_coord.0) || !(0..8).contains(&next_coord.1) {
-                        break;
-                    }
+        let occupied = pieces.iter().flatten().fold(0, |acc, bb| acc | bb);
+        let king = pieces[own.index()][King.index()];
+        let enemy_king = pieces[enemy.index()][King.index()];
+        // blowing up their own king is never allowed, while blowing up the other one wins on
+        // the spot. kings that touch can't be in check
+        if self.variant == Variant::Atomic {
+            if king == 0 {
+                return true;
+            }
+            if enemy_king == 0 || KING_ATTACKS[king.trailing_zeros() as usize] & enemy_king != 0 {
+                return false;
+            }
+        }
+        // in racing kings, giving check is just as illegal as being in it
+        if self.variant == Variant::RacingKings
+            && State::square_attacked(&pieces, occupied, enemy_king.trailing_zeros() as usize, own)
+        {
+            return true;
+        }
+        // the horde has no king to keep safe
+        if king == 0 {
+            return false;
+        }
 
-                    let next_as_valid = (next_coord.0 as u8, next_coord.1 as u8);
-
-                    if let Some(next_hit_piece) = self[next_as_valid].content {
-                        if next_hit_piece.colour == piece.colour.flip() {
-                            moves.insert(current_coord);
-                            moves.insert(next_as_valid);
-                            break;
-                        } else {
-                            moves.insert(current_coord);
-                            break;
-                        }
-                    }
-                    current_coord = next_as_valid;
+        State::square_attacked(&pieces, occupied, king.trailing_zeros() as usize, enemy)
+    }
 
-                    moves.insert(current_coord);
-                }
+    // every empty square the side to move could drop a piece from their pocket on. pawns can't
+    // go on the first or last rank
+    fn drop_moves(&self) -> Vec<ChessMove> {
+        let empty = !self.occupied();
+        let mut moves = Vec::new();
+        for kind in [Pawn, Rook, Knight, Bishop, Queen] {
+            if self.pockets[self.turn.index()][kind.index()] == 0 {
+                continue;
             }
-
-            // sliding pieces have this problem; cba to figure out why so manually remove it
-            moves.remove(&coord);
-        } else {
-            let offsets_raw: &[(i8, i8)] = match piece.kind {
-                Pawn => {
-                    if piece.colour == ChessColour::White {
-                        if coord.1 == 6 {
-                            &[]
-                        } else {
-                            if piece.has_moved {
-                                &[(0, 1)]
-                            } else {
-                                &[(0, 1), (0, 2)]
-                            }
-                        }
-                    } else {
-                        if coord.1 == 1 {
-                            &[]
-                        } else {
-                            if piece.has_moved {
-                                &[(0, -1)]
-                            } else {
-                                &[(0, -1), (0, -2)]
-                            }
-                        }
-                    }
-                }
-                Knight => &KNIGHT_MOVES_RAW,
-                King => &KING_MOVES_RAW,
-                _ => unreachable!("supposedly nonsliding piece was a rook, bishop or queen"),
+            let targets = match kind {
+                Pawn => empty & !(rank_mask(0) | rank_mask(7)),
+                _ => empty,
             };
+            for dst in squares_of(targets) {
+                moves.push(ChessMove::new(dst, dst, MoveKind::Drop(kind)));
+            }
+        }
+        moves
+    }
 
-            moves = offsets_raw
-                .into_iter()
-                .map(|m| (coord.0 as i8 + m.0, coord.1 as i8 + m.1))
-                .filter(|m| (0..8).contains(&m.0) && (0..8).contains(&m.1))
-                .map(|m| (m.0 as u8, m.1 as u8))
-                .filter(|s| {
-                    self[*s].content.is_none()
-                        || self[*s].content.unwrap().colour == piece.colour.flip()
-                })
-                .collect::<HashSet<_>>();
-
-            if piece.kind == Pawn {
-                let up_dir: i8 = if piece.colour == ChessColour::White {
-                    1
+    // every move the piece on the given square could make if its own king's safety didn't matter
+    fn get_pseudo_legal_moves(&self, coord: (u8, u8)) -> Vec<ChessMove> {
+        let piece = self[coord].content.unwrap();
+        let index = square_index(coord);
+        let own = self.occupancy[piece.colour.index()];
+        let enemy = piece.colour.flip();
+        let occupied = self.occupied();
+
+        let mut generated_moves = Vec::new();
+
+        if piece.kind == Pawn {
+            let (up_dir, starting_rank, promotion_rank): (i8, u8, u8) =
+                if piece.colour == ChessColour::White {
+                    (1, 1, 7)
                 } else {
-                    -1
+                    (-1, 6, 0)
                 };
 
-                let up_coord = (coord.0, (coord.1 as i8 + up_dir) as u8);
-                let double_up_coord = (coord.0, (coord.1 as i8 + 2 * up_dir) as u8);
+            // pawns on their last rank would already have promoted, so there is always a square in front
+            let up_coord = (coord.0, (coord.1 as i8 + up_dir) as u8);
+            let mut targets =
+                PAWN_ATTACKS[piece.colour.index()][index] & self.occupancy[enemy.index()];
+            if occupied & bit(up_coord) == 0 {
+                targets |= bit(up_coord);
 
-                // manually remove double forward capture when
-                // pawn has not yet moved
-                if !piece.has_moved && self[double_up_coord].content.is_some() {
-                    moves.remove(&double_up_coord);
+                // double pawn moves can't jump over a piece either. the horde's pawns may also
+                // make one from the first rank
+                let double_up_coord = (coord.0, (coord.1 as i8 + 2 * up_dir) as u8);
+                let horde_first_rank = self.variant == Variant::Horde && coord.1 == 0;
+                if (coord.1 == starting_rank || horde_first_rank)
+                    && occupied & bit(double_up_coord) == 0
+                {
+                    generated_moves.push(ChessMove::new(
+                        coord,
+                        double_up_coord,
+                        MoveKind::DoublePush,
+                    ));
                 }
+            }
 
-                // cannot move forwards
-
-                if (0..8).contains(&up_coord.1) {
-                    if self[up_coord].content.is_some() {
-                        moves.remove(&up_coord);
-                    }
-
-                    // only check for leftwards pawn captures if the pawn is not on the a file
-                    if coord.0 >= 1 {
-                        // following line does not cause board overflow because a pawn on 1st or 8th rank promotes
-                        let target_coord = (coord.0 - 1, (coord.1 as i8 + up_dir) as u8);
-                        let left_capture = self[target_coord].content;
-                        if left_capture.is_some()
-                            && left_capture.unwrap().colour == piece.colour.flip()
-                        {
-                            moves.insert(target_coord);
-                        }
-
-                        // also, leftward en passant captures!
-                        if let Some(en_passant_pawn) = self[(coord.0 - 1, coord.1)].content {
-                            if en_passant_pawn.en_passanteable {
-                                let dst = (coord.0 - 1, (coord.1 as i8 + up_dir) as u8);
-
-                                let boxed_dst = Box::new(dst);
-                                let static_dst: &'static (u8, u8) =
-                                    Box::<(u8, u8)>::leak(boxed_dst);
-
-                                moves_with_fn.push(ChessMove {
-                                    dst,
-                                    function: Arc::new(Mutex::new(|state: &mut State| {
-                                        state
-                                            .history
-                                            .push(PerformedMove::new(*static_coord, *static_dst));
-                                        state.en_passant(*static_coord, *static_dst);
-                                        true
-                                    })),
-                                })
-                            }
-                        }
-                    }
-
-                    // only check for rightwards pawn captures if the pawn is not on the h file
-                    if coord.0 <= 6 {
-                        // following line does not cause board overflow because a pawn on 1st or 8th rank promotes
-                        let target_coord = (coord.0 + 1, (coord.1 as i8 + up_dir) as u8);
-                        let right_capture = self[target_coord].content;
-                        if right_capture.is_some()
-                            && right_capture.unwrap().colour == piece.colour.flip()
-                        {
-                            moves.insert(target_coord);
-                        }
-
-                        // also, rightward en passant captures!
-                        if let Some(en_passant_pawn) = self[(coord.0 + 1, coord.1)].content {
-                            if en_passant_pawn.en_passanteable {
-                                let dst = (coord.0 + 1, (coord.1 as i8 + up_dir) as u8);
-
-                                let boxed_dst = Box::new(dst);
-                                let static_dst: &'static (u8, u8) =
-                                    Box::<(u8, u8)>::leak(boxed_dst);
-
-                                moves_with_fn.push(ChessMove {
-                                    dst,
-                                    function: Arc::new(Mutex::new(|state: &mut State| {
-                                        state
-                                            .history
-                                            .push(PerformedMove::new(*static_coord, *static_dst));
-                                        state.en_passant(*static_coord, *static_dst);
-                                        true
-                                    })),
-                                })
-                            }
-                        }
+            for dst in squares_of(targets) {
+                let capture = dst.0 != coord.0;
+                if dst.1 == promotion_rank {
+                    for &promote_to in self.variant.promotions() {
+                        generated_moves.push(ChessMove::new(
+                            coord,
+                            dst,
+                            MoveKind::Promotion {
+                                promote_to,
+                                capture,
+                            },
+                        ));
                     }
+                } else if capture {
+                    generated_moves.push(ChessMove::new(coord, dst, MoveKind::Capture));
+                } else {
+                    generated_moves.push(ChessMove::new(coord, dst, MoveKind::Quiet));
                 }
             }
-        }
 
-        // we need to test if this move would cause the player to be in check
-        // we do this by iterating over every piece the opponent has, and seeing if capturing the king is a possible move
-        // if so, the move is invalid. sadly this process is fairly lengthy
-
-        let nonchecking_moves: Vec<(u8, u8)> = if test_for_checks {
-            moves
-                .into_iter()
-                .filter(|possibly_checking_move| {
-                    // if piece.kind == Pawn && (possibly_checking_move.1 == 0 || possibly_checking_move.1 == 7) {
-                    //     return true;
-                    // }
+            if let Some(target) = self.en_passant {
+                if PAWN_ATTACKS[piece.colour.index()][index] & bit(target) != 0 {
+                    generated_moves.push(ChessMove::new(coord, target, MoveKind::EnPassant));
+                }
+            }
 
-                    let mut test_board = self.clone();
-                    test_board.make_move(coord, ChessMove::dummy(*possibly_checking_move));
+            return generated_moves;
+        }
 
-                    !test_board.is_in_check(piece.colour)
-                })
-                .collect()
-        } else {
-            moves.into_iter().collect()
+        let targets = match piece.kind {
+            Knight => KNIGHT_ATTACKS[index],
+            King => KING_ATTACKS[index],
+            Bishop => bishop_attacks(index, occupied),
+            Rook => rook_attacks(index, occupied),
+            Queen => queen_attacks(index, occupied),
+            Pawn => unreachable!("pawn moves are generated separately"),
+        } & !own;
+        // an atomic king can't capture, as it would blow itself up
+        let targets = match (piece.kind, self.variant) {
+            (King, Variant::Atomic) => targets & !self.occupancy[enemy.index()],
+            _ => targets,
         };
 
-        // all hitherto calculated moves have no extra "functionality"
-        // there are three main exceptions to this: en passant, castling and pawn promotion
-
-        for m in nonchecking_moves {
-            let boxed_move = Box::new(m);
-            let static_move: &'static (u8, u8) = Box::<(u8, u8)>::leak(boxed_move);
-
-            if piece.kind == Pawn && m.1 == 0 || m.1 == 7 {
-                moves_with_fn.push(ChessMove {
-                    dst: m,
-                    function: Arc::new(Mutex::new(|state: &mut State| {
-                        state
-                            .history
-                            .push(PerformedMove::new(*static_coord, *static_move));
-                        state.promote_pawn(*static_coord, *static_move);
-                        true
-                    })),
-                })
-            } else if piece.kind == Pawn
-                && (m.1 as i8 - coord.1 as i8).abs() == 2
-                && !piece.has_moved
-            {
-                // double pawn move
-                let boxed_col = Box::new(piece.colour);
-                let static_col: &'static ChessColour = Box::<ChessColour>::leak(boxed_col);
-
-                moves_with_fn.push(ChessMove {
-                    dst: m,
-                    function: Arc::new(Mutex::new(|state: &mut State| {
-                        state
-                            .history
-                            .push(PerformedMove::new(*static_coord, *static_move));
-                        state[*static_coord].content = Some(Piece {
-                            en_passanteable: true,
-                            has_moved: true,
-                            colour: *static_col,
-                            kind: Pawn,
-                        }); // we set the en_passanteable field, then pass movement on
-                        false
-                    })),
-                })
+        for dst in squares_of(targets) {
+            let kind = if occupied & bit(dst) != 0 {
+                MoveKind::Capture
             } else {
-                moves_with_fn.push(ChessMove {
-                    dst: m,
-                    function: Arc::new(Mutex::new(|state: &mut State| {
-                        state
-                            .history
-                            .push(PerformedMove::new(*static_coord, *static_move));
-
-                        false
-                    })),
-                });
-            }
+                MoveKind::Quiet
+            };
+            generated_moves.push(ChessMove::new(coord, dst, kind));
         }
 
-        // potentially adding in castling
+        // the king may not castle out of or through check; castling into check is caught
+        // by the legality test like any other move
+        if piece.kind == King && !self.is_in_check(piece.colour) {
+            let rank = coord.1;
+            let between = |a: u8, b: u8| (a.min(b)..=a.max(b)).map(move |x| (x, rank));
 
-        let king_coord = self.get_king_coord(piece.colour);
-        if coord == king_coord && !self[king_coord].content.unwrap().has_moved {
-            // long castle
-            if let Some(piece_on_a_file) = self[(0, king_coord.1)].content {
-                if piece_on_a_file.kind == Rook
-                    && !piece_on_a_file.has_moved
-                    && self[(1, king_coord.1)].content.is_none()
-                    && self[(2, king_coord.1)].content.is_none()
-                    && self[(3, king_coord.1)].content.is_none()
-                {
-                    let target_move = (king_coord.0 - 2, king_coord.1);
-
-                    let boxed_move = Box::new(target_move);
-                    let static_move: &'static (u8, u8) = Box::<(u8, u8)>::leak(boxed_move); //TODO: optimise multiple leak calls away
-
-                    if piece.colour == ChessColour::White {
-                        moves_with_fn.push(ChessMove {
-                            dst: target_move,
-                            function: Arc::new(Mutex::new(|state: &mut State| {
-                                state
-                                    .history
-                                    .push(PerformedMove::new(*static_coord, *static_move));
-                                state.perform_castle(true, ChessColour::White);
-                                true
-                            })),
-                        });
-                    } else {
-                        moves_with_fn.push(ChessMove {
-                            dst: target_move,
-                            function: Arc::new(Mutex::new(|state: &mut State| {
-                                state
-                                    .history
-                                    .push(PerformedMove::new(*static_coord, *static_move));
-                                state.perform_castle(true, ChessColour::Black);
-                                true
-                            })),
-                        });
-                    }
+            for (long_castle, kind) in
+                [(true, MoveKind::LongCastle), (false, MoveKind::ShortCastle)]
+            {
+                if !self.castling_rights.get(piece.colour, long_castle) {
+                    continue;
                 }
-            }
-
-            if let Some(piece_on_h_file) = self[(7, king_coord.1)].content {
-                if piece_on_h_file.kind == Rook
-                    && !piece_on_h_file.has_moved
-                    && self[(5, king_coord.1)].content.is_none()
-                    && self[(6, king_coord.1)].content.is_none()
-                {
-                    let target_move = (king_coord.0 + 2, king_coord.1);
-
-                    let boxed_move = Box::new(target_move);
-                    let static_move: &'static (u8, u8) = Box::<(u8, u8)>::leak(boxed_move); //TODO: optimise multiple leak calls away
-
-                    if piece.colour == ChessColour::White {
-                        moves_with_fn.push(ChessMove {
-                            dst: target_move,
-                            function: Arc::new(Mutex::new(|state: &mut State| {
-                                state
-                                    .history
-                                    .push(PerformedMove::new(*static_coord, *static_move));
-                                state.perform_castle(false, ChessColour::White);
-                                true
-                            })),
-                        });
-                    } else {
-                        moves_with_fn.push(ChessMove {
-                            dst: target_move,
-                            function: Arc::new(Mutex::new(|state: &mut State| {
-                                state
-                                    .history
-                                    .push(PerformedMove::new(*static_coord, *static_move));
-                                state.perform_castle(false, ChessColour::Black);
-                                true
-                            })),
-                        });
-                    }
+                let rook = self.castling_rook(piece.colour, long_castle);
+                let (king_target, rook_target) = State::castling_targets(piece.colour, long_castle);
+
+                // everything either of them passes over or lands on has to be empty, apart from
+                // the two of them, which only matters in Chess960
+                let path = between(coord.0, king_target.0)
+                    .chain(between(rook.0, rook_target.0))
+                    .fold(0, |acc, square| acc | bit(square))
+                    & !bit(coord)
+                    & !bit(rook);
+                let safe = between(coord.0, king_target.0)
+                    .filter(|square| *square != coord && *square != king_target)
+                    .all(|square| !self.is_attacked(square, enemy));
+
+                if occupied & path == 0 && safe {
+                    generated_moves.push(ChessMove::new(coord, king_target, kind));
                 }
             }
         }
 
-        moves_with_fn
+        generated_moves
     }
 }
 
-impl Index<(u8, u8)> for State {
-    type Output = Square;
-    fn index(&self, index: (u8, u8)) -> &Self::Output {
-        &self.squares[(index.0 + 8 * index.1) as usize]
+impl Default for State {
+    fn default() -> Self {
+        State::new()
     }
 }
 
-impl IndexMut<(u8, u8)> for State {
-    fn index_mut(&mut self, index: (u8, u8)) -> &mut Self::Output {
-        &mut self.squares[(index.0 + 8 * index.1) as usize]
+impl Index<(u8, u8)> for State {
+    type Output = Square;
+    fn index(&self, index: (u8, u8)) -> &Self::Output {
+        &self.squares[square_index(index)]
     }
 }
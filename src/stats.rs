@@ -0,0 +1,292 @@
+use std::fmt;
+
+// how far apart two players are when the first is expected to score this much per game
+pub fn elo_difference(score: f64) -> f64 {
+    // adding zero turns -0 into 0
+    -400.0 * (1.0 / score - 1.0).log10() + 0.0
+}
+
+// the inverse of elo_difference
+pub fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// the error function, to within about 1e-7 (Abramowitz and Stegun 7.1.26)
+fn erf(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.3275911 * x.abs());
+    let poly = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    (1.0 - poly * (-x * x).exp()).copysign(x)
+}
+
+// likelihood of superiority: how sure we can be that the first player is the stronger one,
+// going by wins and losses only, as draws say nothing about which side is better
+pub fn los(wins: u32, losses: u32) -> f64 {
+    if wins + losses == 0 {
+        return 0.5;
+    }
+    0.5 * (1.0 + erf((wins as f64 - losses as f64) / (2.0 * (wins + losses) as f64).sqrt()))
+}
+
+// the mean and variance of a score that takes the given values with the given frequencies,
+// along with how many there were. the prior is added to every count, so that e.g. a perfect
+// score still has some variance
+fn mean_and_variance(values: &[f64], counts: &[u32], prior: f64) -> (f64, f64, f64) {
+    let counts = counts.iter().map(|c| *c as f64 + prior).collect::<Vec<_>>();
+    let n = counts.iter().sum::<f64>();
+    let mean = values.iter().zip(&counts).map(|(v, c)| v * c).sum::<f64>() / n;
+    let variance = values
+        .iter()
+        .zip(&counts)
+        .map(|(v, c)| c * (v - mean).powi(2))
+        .sum::<f64>()
+        / n;
+    (mean, variance, n)
+}
+
+// an Elo difference with its 95% confidence interval
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct EloEstimate {
+    pub elo: f64,
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl EloEstimate {
+    fn new(values: &[f64], counts: &[u32]) -> Option<Self> {
+        if counts.iter().sum::<u32>() == 0 {
+            return None;
+        }
+        let (mean, variance, n) = mean_and_variance(values, counts, 1e-3);
+        let margin = 1.96 * (variance / n).sqrt();
+        let clamp = |score: f64| score.clamp(1e-6, 1.0 - 1e-6);
+        Some(EloEstimate {
+            elo: elo_difference(clamp(mean)),
+            lower: elo_difference(clamp(mean - margin)),
+            upper: elo_difference(clamp(mean + margin)),
+        })
+    }
+}
+
+impl fmt::Display for EloEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:+.1} ± {:.1}",
+            self.elo,
+            (self.upper - self.lower) / 2.0
+        )
+    }
+}
+
+// wins, draws and losses of single games
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Trinomial {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Trinomial {
+    const VALUES: [f64; 3] = [0.0, 0.5, 1.0];
+
+    fn counts(&self) -> [u32; 3] {
+        [self.losses, self.draws, self.wins]
+    }
+
+    pub fn elo(&self) -> Option<EloEstimate> {
+        EloEstimate::new(&Trinomial::VALUES, &self.counts())
+    }
+
+    pub fn los(&self) -> f64 {
+        los(self.wins, self.losses)
+    }
+}
+
+// the scores of game pairs played from the same opening with colours swapped, which cancels
+// out most of what the opening does. indexed by the pair's total score times two, so
+// [0] counts pairs that were both lost and [4] pairs that were both won
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Pentanomial(pub [u32; 5]);
+
+impl Pentanomial {
+    // per game, so that it compares with the trinomial numbers
+    const VALUES: [f64; 5] = [0.0, 0.25, 0.5, 0.75, 1.0];
+
+    // the scores of both games, from the same player's point of view
+    pub fn add(&mut self, first: f64, second: f64) {
+        self.0[((first + second) * 2.0).round() as usize] += 1;
+    }
+
+    pub fn pairs(&self) -> u32 {
+        self.0.iter().sum()
+    }
+
+    pub fn elo(&self) -> Option<EloEstimate> {
+        EloEstimate::new(&Pentanomial::VALUES, &self.0)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SprtResult {
+    // the player is no better than elo0
+    AcceptH0,
+    // the player is at least elo1 better
+    AcceptH1,
+    Continue,
+}
+
+// a sequential probability ratio test of whether the first player is elo0 (H0) or elo1 (H1)
+// stronger, with false positive and false negative rates alpha and beta.
+// uses the normal approximation to the generalized SPRT on pentanomial results
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+impl Sprt {
+    // <elo0>,<elo1>[,<alpha>,<beta>], where alpha and beta default to 0.05
+    pub fn parse(text: &str) -> Option<Self> {
+        let numbers = text
+            .split(',')
+            .map(|n| n.trim().parse::<f64>().ok())
+            .collect::<Option<Vec<_>>>()?;
+        let (elo0, elo1, alpha, beta) = match numbers[..] {
+            [elo0, elo1] => (elo0, elo1, 0.05, 0.05),
+            [elo0, elo1, alpha, beta] => (elo0, elo1, alpha, beta),
+            _ => return None,
+        };
+        let rate = 0.0..0.5;
+        (elo0 < elo1 && rate.contains(&alpha) && alpha > 0.0 && rate.contains(&beta) && beta > 0.0)
+            .then_some(Sprt {
+                elo0,
+                elo1,
+                alpha,
+                beta,
+            })
+    }
+
+    // the log-likelihood ratio at which H0 and H1 are accepted
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    pub fn llr(&self, pentanomial: &Pentanomial) -> f64 {
+        if pentanomial.pairs() == 0 {
+            return 0.0;
+        }
+        // half a pair of every kind, as the normal approximation is poor for the first few pairs
+        // and would otherwise let them decide the test on their own
+        let (mean, variance, n) = mean_and_variance(&Pentanomial::VALUES, &pentanomial.0, 0.5);
+        let (score0, score1) = (expected_score(self.elo0), expected_score(self.elo1));
+        n * (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance)
+    }
+
+    pub fn result(&self, pentanomial: &Pentanomial) -> SprtResult {
+        let llr = self.llr(pentanomial);
+        let (lower, upper) = self.bounds();
+        if llr <= lower {
+            SprtResult::AcceptH0
+        } else if llr >= upper {
+            SprtResult::AcceptH1
+        } else {
+            SprtResult::Continue
+        }
+    }
+}
+
+// written the way Sprt::parse reads it
+impl fmt::Display for Sprt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.elo0, self.elo1, self.alpha, self.beta
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64, tolerance: f64) -> bool {
+        (a - b).abs() < tolerance
+    }
+
+    #[test]
+    fn elo_and_scores() {
+        assert_eq!(elo_difference(0.5), 0.0);
+        assert!(close(elo_difference(0.75), 190.85, 0.01));
+        assert!(close(expected_score(elo_difference(0.3)), 0.3, 1e-9));
+        assert!(close(erf(1.0), 0.842700793, 1e-6));
+        assert!(close(erf(-0.5), -0.520499878, 1e-6));
+    }
+
+    #[test]
+    fn likelihood_of_superiority() {
+        assert_eq!(los(0, 0), 0.5);
+        assert!(close(los(10, 10), 0.5, 1e-6));
+        assert!(close(los(60, 40) + los(40, 60), 1.0, 1e-9));
+        // 60 to 40 is about two standard deviations
+        assert!(close(los(60, 40), 0.977, 0.001));
+    }
+
+    #[test]
+    fn estimates() {
+        let even = Trinomial {
+            wins: 30,
+            draws: 40,
+            losses: 30,
+        };
+        let elo = even.elo().unwrap();
+        assert!(close(elo.elo, 0.0, 1e-9));
+        assert!(elo.lower < -30.0 && elo.upper > 30.0);
+        assert_eq!(Trinomial::default().elo(), None);
+
+        let mut pentanomial = Pentanomial::default();
+        pentanomial.add(1.0, 0.5);
+        pentanomial.add(0.5, 0.5);
+        pentanomial.add(0.0, 1.0);
+        pentanomial.add(1.0, 1.0);
+        assert_eq!(pentanomial.0, [0, 0, 2, 1, 1]);
+        // 5.5 out of 8
+        assert!(close(
+            pentanomial.elo().unwrap().elo,
+            elo_difference(5.5 / 8.0),
+            0.5
+        ));
+    }
+
+    #[test]
+    fn sprt() {
+        assert_eq!(Sprt::parse("0,5").unwrap().to_string(), "0,5,0.05,0.05");
+        assert_eq!(Sprt::parse("-3,1,0.1,0.2").unwrap().beta, 0.2);
+        assert_eq!(Sprt::parse("5,0"), None);
+        assert_eq!(Sprt::parse("0,5,0.05"), None);
+
+        let sprt = Sprt::parse("0,10").unwrap();
+        let (lower, upper) = sprt.bounds();
+        assert!(close(lower, -2.944, 0.001) && close(upper, 2.944, 0.001));
+
+        assert_eq!(
+            sprt.result(&Pentanomial([2, 5, 10, 5, 2])),
+            SprtResult::Continue
+        );
+        assert_eq!(
+            sprt.result(&Pentanomial([20, 200, 300, 300, 80])),
+            SprtResult::AcceptH1
+        );
+        assert_eq!(
+            sprt.result(&Pentanomial([80, 300, 300, 200, 20])),
+            SprtResult::AcceptH0
+        );
+    }
+}
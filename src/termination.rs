@@ -0,0 +1,387 @@
+use crate::{
+    bitboard::*,
+    piece::{PieceKind::*, *},
+    state::State,
+    variant::Variant,
+};
+
+use std::fmt;
+
+// a1 is dark, so the light squares are b1, a2 and so on
+const LIGHT_SQUARES: Bitboard = 0x55AA_55AA_55AA_55AA;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameResult {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl GameResult {
+    pub fn win_for(col: ChessColour) -> Self {
+        match col {
+            ChessColour::White => GameResult::WhiteWins,
+            ChessColour::Black => GameResult::BlackWins,
+        }
+    }
+}
+
+// the score notation used in game records
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GameResult::WhiteWins => write!(f, "1-0"),
+            GameResult::BlackWins => write!(f, "0-1"),
+            GameResult::Draw => write!(f, "1/2-1/2"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Termination {
+    Checkmate {
+        winner: ChessColour,
+    },
+    Stalemate,
+    // the fifty-move rule and threefold repetition only end the game when a player claims them;
+    // the seventy-five-move rule and fivefold repetition end it automatically
+    FiftyMoveRule,
+    SeventyFiveMoveRule,
+    ThreefoldRepetition,
+    FivefoldRepetition,
+    InsufficientMaterial,
+    Resignation {
+        winner: ChessColour,
+    },
+    // no winner if the side whose clock did not run out has no way of mating
+    Timeout {
+        winner: Option<ChessColour>,
+    },
+    // the variant's own way of ending the game, see variant.rs
+    VariantEnd {
+        winner: Option<ChessColour>,
+        reason: &'static str,
+    },
+}
+
+impl Termination {
+    pub fn result(&self) -> GameResult {
+        match self {
+            Termination::Checkmate { winner }
+            | Termination::Resignation { winner }
+            | Termination::Timeout {
+                winner: Some(winner),
+            }
+            | Termination::VariantEnd {
+                winner: Some(winner),
+                ..
+            } => GameResult::win_for(*winner),
+            _ => GameResult::Draw,
+        }
+    }
+
+    // how the game ended, without who won
+    pub fn reason(&self) -> &'static str {
+        match self {
+            Termination::Checkmate { .. } => "checkmate",
+            Termination::Stalemate => "stalemate",
+            Termination::FiftyMoveRule => "fifty-move rule",
+            Termination::SeventyFiveMoveRule => "75-move rule",
+            Termination::ThreefoldRepetition => "threefold repetition",
+            Termination::FivefoldRepetition => "fivefold repetition",
+            Termination::InsufficientMaterial => "insufficient material",
+            Termination::Resignation { .. } => "resignation",
+            Termination::Timeout { .. } => "timeout",
+            Termination::VariantEnd { reason, .. } => reason,
+        }
+    }
+}
+
+impl fmt::Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |col: &ChessColour| match col {
+            ChessColour::White => "white",
+            ChessColour::Black => "black",
+        };
+        match self {
+            Termination::Checkmate { winner } => write!(f, "checkmate, {} wins", name(winner)),
+            Termination::Stalemate => write!(f, "draw by stalemate"),
+            Termination::FiftyMoveRule => write!(f, "draw by the fifty-move rule"),
+            Termination::SeventyFiveMoveRule => write!(f, "draw by the 75-move rule"),
+            Termination::ThreefoldRepetition => write!(f, "draw by threefold repetition"),
+            Termination::FivefoldRepetition => write!(f, "draw by fivefold repetition"),
+            Termination::InsufficientMaterial => write!(f, "draw by insufficient material"),
+            Termination::Resignation { winner } => {
+                write!(f, "{} resigns, {} wins", name(&winner.flip()), name(winner))
+            }
+            Termination::Timeout {
+                winner: Some(winner),
+            } => write!(f, "{} wins on time", name(winner)),
+            Termination::Timeout { winner: None } => {
+                write!(f, "draw, time ran out against a lone king")
+            }
+            Termination::VariantEnd {
+                winner: Some(winner),
+                reason,
+            } => write!(f, "{reason}, {} wins", name(winner)),
+            Termination::VariantEnd {
+                winner: None,
+                reason,
+            } => write!(f, "draw, {reason}"),
+        }
+    }
+}
+
+impl State {
+    // sets State::termination if the game has ended through the position on the board.
+    // claimable draws are treated as claimed straight away
+    pub fn update_termination(&mut self) {
+        if self.termination.is_none() {
+            self.termination = self.forced_termination().or_else(|| self.claimable_draw());
+        }
+    }
+
+    // terminations that apply without either player having to do anything
+    pub fn forced_termination(&self) -> Option<Termination> {
+        if self.get_all_moves_for_colour(self.turn).is_empty() {
+            return Some(self.no_moves_termination());
+        }
+
+        if self.halfmove_clock >= 150 {
+            Some(Termination::SeventyFiveMoveRule)
+        } else if self.repetition_count() >= 5 {
+            Some(Termination::FivefoldRepetition)
+        } else if self.is_insufficient_material() {
+            Some(Termination::InsufficientMaterial)
+        } else {
+            None
+        }
+    }
+
+    // draws that the player to move is entitled to claim
+    pub fn claimable_draw(&self) -> Option<Termination> {
+        if self.halfmove_clock >= 100 {
+            Some(Termination::FiftyMoveRule)
+        } else if self.repetition_count() >= 3 {
+            Some(Termination::ThreefoldRepetition)
+        } else {
+            None
+        }
+    }
+
+    pub fn resign(&mut self, col: ChessColour) {
+        self.termination = Some(Termination::Resignation { winner: col.flip() });
+    }
+
+    // for when a player's clock runs out
+    pub fn flag(&mut self, col: ChessColour) {
+        let opponent = col.flip();
+        self.termination = Some(Termination::Timeout {
+            winner: self.has_mating_material(opponent).then_some(opponent),
+        });
+    }
+
+    // how many times the current position has occurred, including now.
+    // captures and pawn moves can't be undone, so only the last halfmove_clock plies are searched,
+    // and only every other one of those has the same side to move
+    pub fn repetition_count(&self) -> usize {
+        let window = (self.halfmove_clock as usize).min(self.history.len());
+        1 + self
+            .history
+            .iter()
+            .rev()
+            .take(window)
+            .skip(1)
+            .step_by(2)
+            .filter(|performed| performed.hash == self.hash)
+            .count()
+    }
+
+    // neither side can ever mate: only kings and minor pieces are left, and either at most
+    // one minor piece or only bishops that all stand on the same square colour.
+    // variants can be won in other ways, so this only applies to standard chess
+    pub fn is_insufficient_material(&self) -> bool {
+        if self.variant != Variant::Standard {
+            return false;
+        }
+        let of_kind = |kind: PieceKind| self.pieces[0][kind.index()] | self.pieces[1][kind.index()];
+        if of_kind(Pawn) | of_kind(Rook) | of_kind(Queen) != 0 {
+            return false;
+        }
+
+        let knights = of_kind(Knight);
+        let bishops = of_kind(Bishop);
+
+        (knights | bishops).count_ones() <= 1 || (knights == 0 && on_one_colour(bishops))
+    }
+
+    // used when a player runs out of time: their opponent only wins if some series of legal
+    // moves could still get them mated. a lone king never mates, nor do bishops that all stand
+    // on one square colour or a single minor piece, unless the other king is hemmed in by its
+    // own pieces
+    pub fn has_mating_material(&self, col: ChessColour) -> bool {
+        if self.variant != Variant::Standard {
+            return true;
+        }
+        let own = &self.pieces[col.index()];
+        let heavy_or_pawns = own[Pawn.index()] | own[Rook.index()] | own[Queen.index()];
+        let minors = own[Knight.index()] | own[Bishop.index()];
+        if heavy_or_pawns != 0 {
+            return true;
+        }
+        if minors == 0 || self.is_insufficient_material() {
+            return false;
+        }
+
+        let other = col.flip().index();
+        let other_besides_king = self.occupancy[other] & !self.pieces[other][King.index()];
+        let mates_alone = minors.count_ones() > 1
+            && !(own[Knight.index()] == 0 && on_one_colour(own[Bishop.index()]));
+
+        mates_alone || other_besides_king != 0
+    }
+}
+
+// whether the bishops, if any, all stand on light squares or all on dark ones
+fn on_one_colour(bishops: Bitboard) -> bool {
+    bishops & LIGHT_SQUARES == 0 || bishops & !LIGHT_SQUARES == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn play(state: &mut State, moves: &[&str]) {
+        for text in moves {
+            let chess_move = state
+                .get_all_moves_for_colour(state.turn)
+                .into_iter()
+                .find(|m| m.to_string() == *text)
+                .unwrap_or_else(|| panic!("{text} is not legal"));
+            state.make_move(chess_move);
+        }
+    }
+
+    #[test]
+    fn fools_mate() {
+        let mut state = State::new();
+        play(&mut state, &["f2f3", "e7e5", "g2g4", "d8h4"]);
+        state.update_termination();
+        assert_eq!(
+            state.termination,
+            Some(Termination::Checkmate {
+                winner: ChessColour::Black
+            })
+        );
+        assert_eq!(state.termination.unwrap().result(), GameResult::BlackWins);
+    }
+
+    #[test]
+    fn stalemate() {
+        let state = State::from_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert_eq!(state.forced_termination(), Some(Termination::Stalemate));
+    }
+
+    #[test]
+    fn repetitions() {
+        let mut state = State::new();
+        let shuffle = ["g1f3", "g8f6", "f3g1", "f6g8"];
+        play(&mut state, &shuffle);
+        assert_eq!(state.repetition_count(), 2);
+        assert_eq!(state.claimable_draw(), None);
+
+        play(&mut state, &shuffle);
+        assert_eq!(
+            state.claimable_draw(),
+            Some(Termination::ThreefoldRepetition)
+        );
+        assert_eq!(state.forced_termination(), None);
+
+        play(&mut state, &shuffle);
+        play(&mut state, &shuffle);
+        assert_eq!(
+            state.forced_termination(),
+            Some(Termination::FivefoldRepetition)
+        );
+    }
+
+    #[test]
+    fn move_rules() {
+        let fifty = State::from_fen("8/8/4k3/8/8/4K3/4R3/8 w - - 100 80").unwrap();
+        assert_eq!(fifty.claimable_draw(), Some(Termination::FiftyMoveRule));
+        assert_eq!(fifty.forced_termination(), None);
+
+        let seventy_five = State::from_fen("8/8/4k3/8/8/4K3/4R3/8 w - - 150 100").unwrap();
+        assert_eq!(
+            seventy_five.forced_termination(),
+            Some(Termination::SeventyFiveMoveRule)
+        );
+    }
+
+    #[test]
+    fn insufficient_material() {
+        for fen in [
+            "8/8/4k3/8/8/4K3/8/8 w - - 0 1",
+            "8/8/4k3/8/8/4K3/4N3/8 w - - 0 1",
+            "8/8/2b1k3/8/8/4K3/4B3/8 w - - 0 1",
+        ] {
+            let state = State::from_fen(fen).unwrap();
+            assert!(state.is_insufficient_material(), "{fen}");
+        }
+        for fen in [
+            "8/8/4k3/8/8/4K3/4P3/8 w - - 0 1",
+            "8/8/3bk3/8/8/4K3/4B3/8 w - - 0 1",
+            "8/8/4k3/8/8/4K3/3NN3/8 w - - 0 1",
+        ] {
+            let state = State::from_fen(fen).unwrap();
+            assert!(!state.is_insufficient_material(), "{fen}");
+        }
+    }
+
+    #[test]
+    fn timeout_against_lone_king() {
+        let mut state = State::from_fen("8/8/4k3/8/8/4K3/4R3/8 w - - 0 1").unwrap();
+        state.flag(ChessColour::Black);
+        assert_eq!(state.termination.unwrap().result(), GameResult::WhiteWins);
+
+        let mut state = State::from_fen("8/8/4k3/8/8/4K3/4R3/8 w - - 0 1").unwrap();
+        state.flag(ChessColour::White);
+        assert_eq!(state.termination.unwrap().result(), GameResult::Draw);
+    }
+
+    #[test]
+    fn timeout_with_minor_pieces() {
+        let flag_black = |fen: &str| {
+            let mut state = State::from_fen(fen).unwrap();
+            state.flag(ChessColour::Black);
+            state.termination.unwrap().result()
+        };
+
+        // bishops on one square colour can't mate, however many there are
+        assert_eq!(
+            flag_black("8/8/4k3/8/8/4K3/3B1B2/8 w - - 0 1"),
+            GameResult::Draw
+        );
+        assert_eq!(
+            flag_black("8/8/4k3/8/8/4K3/3BB3/8 w - - 0 1"),
+            GameResult::WhiteWins
+        );
+        assert_eq!(
+            flag_black("8/8/4k3/8/8/4K3/3NN3/8 w - - 0 1"),
+            GameResult::WhiteWins
+        );
+        // a lone knight can, if black's own knight takes a square from its king
+        assert_eq!(
+            flag_black("8/8/4k3/8/8/4K3/4N3/8 w - - 0 1"),
+            GameResult::Draw
+        );
+        assert_eq!(
+            flag_black("8/8/4kn2/8/8/4K3/4N3/8 w - - 0 1"),
+            GameResult::WhiteWins
+        );
+        assert_eq!(
+            flag_black("8/8/2b1k3/8/8/4K3/4B3/8 w - - 0 1"),
+            GameResult::Draw
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{chess_move::ChessMove, search::MATE_THRESHOLD};
+
+// how the stored score relates to the real one, which depends on where the search cut off
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Bound {
+    Exact,
+    // the search failed high: the real score is at least this
+    Lower,
+    // the search failed low: the real score is at most this
+    Upper,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TtEntry {
+    // the full zobrist hash, as many positions share a slot
+    pub key: u64,
+    pub depth: u8,
+    pub bound: Bound,
+    pub score: i32,
+    pub best_move: Option<ChessMove>,
+    // the search that stored the entry, so that older ones can be replaced first
+    generation: u8,
+}
+
+// a fixed number of slots, indexed by the position's zobrist hash
+pub struct TranspositionTable {
+    entries: Vec<Option<TtEntry>>,
+    generation: u8,
+}
+
+impl TranspositionTable {
+    pub const DEFAULT_SIZE_MB: usize = 16;
+    // the most UCI's Hash option allows
+    pub const MAX_SIZE_MB: usize = 4096;
+
+    pub fn new(size_mb: usize) -> Self {
+        let bytes = size_mb.saturating_mul(1024 * 1024);
+        let slots = (bytes / std::mem::size_of::<Option<TtEntry>>()).max(1);
+        TranspositionTable {
+            entries: vec![None; slots],
+            generation: 0,
+        }
+    }
+
+    // throws away everything stored so far
+    pub fn resize(&mut self, size_mb: usize) {
+        *self = TranspositionTable::new(size_mb);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+        self.generation = 0;
+    }
+
+    // called before every search, so that entries from earlier ones are the first to go
+    pub fn new_search(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+
+    // the entry for this position, if there is one. mate scores are turned back into
+    // distances from the root, given how far from the root the position was reached
+    pub fn probe(&self, key: u64, ply: usize) -> Option<TtEntry> {
+        let entry = self.entries[self.slot(key)].filter(|e| e.key == key)?;
+        Some(TtEntry {
+            score: score_from_tt(entry.score, ply),
+            ..entry
+        })
+    }
+
+    // an entry is replaced by results for the same position, by results from a newer search,
+    // and otherwise only by results from at least as deep a search
+    pub fn store(
+        &mut self,
+        key: u64,
+        depth: u32,
+        bound: Bound,
+        score: i32,
+        best_move: Option<ChessMove>,
+        ply: usize,
+    ) {
+        let slot = self.slot(key);
+        let depth = depth.min(u8::MAX as u32) as u8;
+        if let Some(existing) = self.entries[slot] {
+            if existing.key != key
+                && existing.generation == self.generation
+                && existing.depth > depth
+            {
+                return;
+            }
+        }
+
+        // a stored move is better than none, even if it's from a shallower search
+        let best_move = best_move.or_else(|| {
+            self.entries[slot]
+                .filter(|e| e.key == key)
+                .and_then(|e| e.best_move)
+        });
+        self.entries[slot] = Some(TtEntry {
+            key,
+            depth,
+            bound,
+            score: score_to_tt(score, ply),
+            best_move,
+            generation: self.generation,
+        });
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        TranspositionTable::new(TranspositionTable::DEFAULT_SIZE_MB)
+    }
+}
+
+// the search scores mates by their distance from the root, but the same position can be reached
+// at different depths; stored mate scores are counted from the position itself instead
+fn score_to_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score + ply as i32
+    } else if score <= -MATE_THRESHOLD {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn score_from_tt(score: i32, ply: usize) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score - ply as i32
+    } else if score <= -MATE_THRESHOLD {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chess_move::MoveKind, search::MATE_SCORE};
+
+    #[test]
+    fn store_and_probe() {
+        let mut tt = TranspositionTable::new(1);
+        let e2e4 = ChessMove::new((4, 1), (4, 3), MoveKind::DoublePush);
+        tt.store(12345, 4, Bound::Lower, 30, Some(e2e4), 0);
+
+        let entry = tt.probe(12345, 0).unwrap();
+        assert_eq!(
+            (entry.depth, entry.bound, entry.score, entry.best_move),
+            (4, Bound::Lower, 30, Some(e2e4))
+        );
+        assert_eq!(tt.probe(54321, 0), None);
+
+        // another position in the same slot isn't mistaken for this one
+        let colliding = 12345 + tt.entries.len() as u64;
+        assert_eq!(tt.probe(colliding, 0), None);
+
+        tt.clear();
+        assert_eq!(tt.probe(12345, 0), None);
+    }
+
+    #[test]
+    fn replacement() {
+        let mut tt = TranspositionTable::new(1);
+        let slots = tt.entries.len() as u64;
+
+        tt.store(7, 6, Bound::Exact, 10, None, 0);
+        // shallower results for another position don't push out deeper ones...
+        tt.store(7 + slots, 2, Bound::Exact, 20, None, 0);
+        assert_eq!(tt.probe(7, 0).unwrap().score, 10);
+        // ...unless they're from a newer search
+        tt.new_search();
+        tt.store(7 + slots, 2, Bound::Exact, 20, None, 0);
+        assert_eq!(tt.probe(7, 0), None);
+        assert_eq!(tt.probe(7 + slots, 0).unwrap().score, 20);
+    }
+
+    #[test]
+    fn mate_scores() {
+        let mut tt = TranspositionTable::new(1);
+        // found 5 plies from the root, with mate 3 plies after that
+        tt.store(99, 3, Bound::Exact, MATE_SCORE - 8, None, 5);
+        // reached 2 plies from the root in another search, the mate is now 5 plies away
+        assert_eq!(tt.probe(99, 2).unwrap().score, MATE_SCORE - 5);
+
+        tt.store(100, 3, Bound::Exact, -(MATE_SCORE - 8), None, 5);
+        assert_eq!(tt.probe(100, 2).unwrap().score, -(MATE_SCORE - 5));
+    }
+}
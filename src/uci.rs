@@ -0,0 +1,687 @@
+use crate::{
+    chess_move::{ChessMove, MoveInfo},
+    fen::FenError,
+    player::{Player, SearchLimits},
+    state::State,
+    variant::Variant,
+};
+
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::sync::atomic::Ordering;
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UciError {
+    MissingPosition,
+    InvalidFen(FenError),
+    IllegalMove(String),
+}
+
+impl fmt::Display for UciError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UciError::MissingPosition => write!(f, "expected startpos or fen"),
+            UciError::InvalidFen(e) => write!(f, "invalid fen: {e}"),
+            UciError::IllegalMove(m) => write!(f, "{m} is not a legal move"),
+        }
+    }
+}
+
+impl std::error::Error for UciError {}
+
+impl State {
+    // the move in UCI's long algebraic notation, e.g. e2e4 or e7e8q. in Chess960, castling is
+    // written as the king taking its own rook, as the king may not move at all
+    pub fn uci_move(&self, chess_move: ChessMove) -> String {
+        match self.castling_rook_of(chess_move) {
+            Some(rook) if self.chess960 => {
+                let mut text = chess_move.to_string();
+                text.replace_range(2.., &self[rook].coord());
+                text
+            }
+            _ => chess_move.to_string(),
+        }
+    }
+
+    // finds the legal move written the way uci_move writes it
+    pub fn parse_uci_move(&self, text: &str) -> Option<ChessMove> {
+        self.get_all_moves_for_colour(self.turn)
+            .into_iter()
+            .find(|m| self.uci_move(*m) == text)
+    }
+
+    // the arguments of `position` that lead to this position: where the game started, and the
+    // moves played since. the reverse of parse_position
+    pub fn uci_position(&self) -> String {
+        let mut start = self.clone();
+        while start.unmake_move().is_some() {}
+
+        let mut text = match start.to_fen() {
+            fen if fen == State::new_variant(self.variant).to_fen() => String::from("startpos"),
+            fen => format!("fen {fen}"),
+        };
+        if !self.history.is_empty() {
+            text.push_str(" moves");
+            for performed in &self.history {
+                text.push_str(&format!(" {}", self.uci_move(performed.chess_move)));
+            }
+        }
+        text
+    }
+}
+
+// the arguments of `position`: either startpos or fen <fen>, optionally followed by moves <m1> <m2> ...
+// the variant and chess960 are what the GUI has set UCI_Variant and UCI_Chess960 to; the latter
+// changes how castling is written
+pub fn parse_position(args: &[&str], variant: Variant, chess960: bool) -> Result<State, UciError> {
+    let moves_at = args
+        .iter()
+        .position(|a| *a == "moves")
+        .unwrap_or(args.len());
+    let mut state = match args.first() {
+        Some(&"startpos") => State::new_variant(variant),
+        Some(&"fen") => State::from_fen_with_variant(&args[1..moves_at].join(" "), variant)
+            .map_err(UciError::InvalidFen)?,
+        _ => return Err(UciError::MissingPosition),
+    };
+    state.chess960 |= chess960;
+
+    for text in args.iter().skip(moves_at + 1) {
+        let chess_move = state
+            .parse_uci_move(text)
+            .ok_or_else(|| UciError::IllegalMove(text.to_string()))?;
+        state.make_move(chess_move);
+    }
+
+    Ok(state)
+}
+
+// the arguments of `go`. anything that isn't understood is skipped
+pub fn parse_go(args: &[&str]) -> SearchLimits {
+    let mut limits = SearchLimits::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if *arg == "infinite" {
+            limits.infinite = true;
+            continue;
+        }
+
+        let Some(value) = args.clone().next().and_then(|v| v.parse::<u64>().ok()) else {
+            continue;
+        };
+        let millis = Some(Duration::from_millis(value));
+        match *arg {
+            "depth" => limits.depth = Some(value as u32),
+            "movetime" => limits.movetime = millis,
+            "wtime" => limits.time_left[0] = millis,
+            "btime" => limits.time_left[1] = millis,
+            "winc" => limits.increment[0] = millis,
+            "binc" => limits.increment[1] = millis,
+            "movestogo" => limits.moves_to_go = Some(value as u32),
+            _ => continue,
+        }
+        args.next();
+    }
+    limits
+}
+
+// the arguments of `go` for the given limits. the reverse of parse_go
+pub fn go_arguments(limits: &SearchLimits) -> String {
+    let millis = |d: Duration| d.as_millis().to_string();
+    let mut args = Vec::new();
+    let mut add = |name: &str, value: Option<String>| {
+        if let Some(value) = value {
+            args.push(format!("{name} {value}"));
+        }
+    };
+
+    add("wtime", limits.time_left[0].map(millis));
+    add("btime", limits.time_left[1].map(millis));
+    add("winc", limits.increment[0].map(millis));
+    add("binc", limits.increment[1].map(millis));
+    add("movestogo", limits.moves_to_go.map(|m| m.to_string()));
+    add("depth", limits.depth.map(|d| d.to_string()));
+    add("movetime", limits.movetime.map(millis));
+    if limits.infinite {
+        args.push(String::from("infinite"));
+    }
+    args.join(" ")
+}
+
+// the player picking the moves. it lasts the whole session, so that what it learns, e.g. in
+// its transposition table, carries over from one search to the next
+struct Engine<P> {
+    player: P,
+    rx_from_player: mpsc::Receiver<MoveInfo>,
+    // nothing is ever sent, but the player shouldn't think main has gone away
+    _tx_to_player: mpsc::Sender<MoveInfo>,
+}
+
+impl<P: Player> Engine<P> {
+    fn new(state: &State, options: &[(String, String)]) -> Self {
+        let (tx_to_player, rx_to_player) = mpsc::channel::<MoveInfo>();
+        let (mut player, rx_from_player) = P::new(rx_to_player, state, state.turn);
+        for (name, value) in options {
+            player.set_option(name, value);
+        }
+        Engine {
+            player,
+            rx_from_player,
+            _tx_to_player: tx_to_player,
+        }
+    }
+}
+
+// the search running in the background, which prints bestmove once it is done and then hands
+// the engine back
+struct Search<P> {
+    limits: SearchLimits,
+    handle: JoinHandle<Engine<P>>,
+}
+
+impl<P: Player + 'static> Search<P> {
+    // without an engine, e.g. before the first search, one is made with the given options
+    fn start<W: Write + Send + 'static>(
+        engine: Option<Engine<P>>,
+        state: &State,
+        limits: SearchLimits,
+        options: Vec<(String, String)>,
+        output: Arc<Mutex<W>>,
+    ) -> Self {
+        let state = state.clone();
+        let thread_limits = limits.clone();
+
+        let handle = thread::spawn(move || {
+            let mut engine = engine.unwrap_or_else(|| Engine::new(&state, &options));
+            let best_move = if state.get_all_moves_for_colour(state.turn).is_empty() {
+                None
+            } else {
+                engine.player.set_position(&state);
+                engine.player.set_limits(thread_limits.clone());
+
+                loop {
+                    engine.player.tick();
+                    if let Ok(move_info) = engine.rx_from_player.try_recv() {
+                        break Some(move_info.move_data);
+                    }
+                    // it won't move again, so there is nothing better to answer
+                    if let Some(failure) = engine.player.failure() {
+                        let mut output = output.lock().unwrap();
+                        let _ = writeln!(output, "info string {failure}");
+                        break None;
+                    }
+                }
+            };
+
+            // with go infinite, bestmove may only be sent after stop
+            while thread_limits.infinite && !thread_limits.stop.load(Ordering::Relaxed) {
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            let mut output = output.lock().unwrap();
+            match best_move {
+                Some(m) => writeln!(output, "bestmove {}", state.uci_move(m)),
+                // the conventional answer when there is nothing to play
+                None => writeln!(output, "bestmove 0000"),
+            }
+            .and_then(|_| output.flush())
+            .expect("could not write to the GUI");
+            engine
+        });
+
+        Search { limits, handle }
+    }
+
+    // asks for the move right away and waits until it is written. if the search panicked,
+    // nothing was written and the engine is lost, which is left to the caller
+    fn stop(self) -> thread::Result<Engine<P>> {
+        self.limits.stop.store(true, Ordering::Relaxed);
+        self.handle.join()
+    }
+}
+
+// speaks UCI on the given streams until quit or the end of the input, letting P pick the moves
+pub fn run<P: Player + 'static, R: BufRead, W: Write + Send + 'static>(
+    name: &str,
+    input: R,
+    output: W,
+) {
+    let output = Arc::new(Mutex::new(output));
+    let reply = |text: &str| {
+        let mut output = output.lock().unwrap_or_else(PoisonError::into_inner);
+        writeln!(output, "{text}")
+            .and_then(|_| output.flush())
+            .expect("could not write to the GUI");
+    };
+    // the GUI is still waiting for a move from a search that panicked. the next search
+    // starts over with a new engine
+    let stop = |search: Search<P>| match search.stop() {
+        Ok(engine) => Some(engine),
+        Err(_) => {
+            reply("info string the search failed");
+            reply("bestmove 0000");
+            None
+        }
+    };
+
+    // None after a position that was refused, until the GUI sends one that isn't
+    let mut state = Some(State::new());
+    let mut options = Vec::new();
+    let mut variant = Variant::Standard;
+    let mut chess960 = false;
+    let mut engine: Option<Engine<P>> = None;
+    let mut search: Option<Search<P>> = None;
+
+    for line in input.lines() {
+        let Ok(line) = line else { break };
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        let Some((command, args)) = words.split_first() else {
+            continue;
+        };
+
+        // the engine is only around while nothing is being searched
+        if matches!(*command, "ucinewgame" | "setoption" | "go" | "stop") {
+            if let Some(search) = search.take() {
+                engine = stop(search);
+            }
+        }
+
+        match *command {
+            "uci" => {
+                reply(&format!("id name {name}"));
+                reply("id author the schaak developers");
+                // understood by the protocol itself, whatever the player
+                reply("option name UCI_Chess960 type check default false");
+                let variants = Variant::ALL.map(|v| format!("var {}", v.uci_name()));
+                reply(&format!(
+                    "option name UCI_Variant type combo default chess {}",
+                    variants.join(" ")
+                ));
+                for option in P::uci_options() {
+                    reply(&format!("option {option}"));
+                }
+                reply("uciok");
+            }
+            "isready" => reply("readyok"),
+            "ucinewgame" => {
+                if let Some(engine) = &mut engine {
+                    engine.player.clear();
+                }
+                state = Some(State::new_variant(variant));
+            }
+            "position" => {
+                state = parse_position(args, variant, chess960)
+                    // UCI has no way to report errors besides info strings
+                    .inspect_err(|e| reply(&format!("info string {e}")))
+                    .ok();
+            }
+            "setoption" => {
+                // setoption name <name> [value <value>], where both may contain spaces
+                let text = args.join(" ");
+                let text = text.strip_prefix("name ").unwrap_or(&text);
+                let (name, value) = text.split_once(" value ").unwrap_or((text, ""));
+                if name == "UCI_Chess960" {
+                    chess960 = value == "true";
+                    continue;
+                }
+                if name == "UCI_Variant" {
+                    match Variant::parse(value) {
+                        Some(v) => variant = v,
+                        None => reply(&format!("info string unknown variant {value}")),
+                    }
+                    continue;
+                }
+                if let Some(engine) = &mut engine {
+                    engine.player.set_option(name, value);
+                }
+                // kept for when the engine has to be made again
+                let (name, value) = (name.to_string(), value.to_string());
+                options.retain(|(n, _): &(String, String)| *n != name);
+                options.push((name, value));
+            }
+            "go" => {
+                // searching whatever came before would only give a move that doesn't fit
+                let Some(state) = &state else {
+                    reply("bestmove 0000");
+                    continue;
+                };
+                search = Some(Search::start(
+                    engine.take(),
+                    state,
+                    parse_go(args),
+                    options.clone(),
+                    output.clone(),
+                ));
+            }
+            "quit" => break,
+            _ => {}
+        }
+    }
+
+    if let Some(search) = search.take() {
+        stop(search);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{piece::ChessColour, player::RandomPlayer};
+
+    use std::io::Cursor;
+
+    // a writer that can be looked at after the session has taken ownership of it
+    #[derive(Clone, Default)]
+    struct SharedOutput(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedOutput {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn session(input: &str) -> Vec<String> {
+        session_with::<RandomPlayer>(input)
+    }
+
+    fn session_with<P: Player + 'static>(input: &str) -> Vec<String> {
+        let output = SharedOutput::default();
+        run::<P, _, _>("schaak", Cursor::new(input.to_string()), output.clone());
+        let bytes = output.0.lock().unwrap().clone();
+        String::from_utf8(bytes)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn positions() {
+        let state = parse_position(
+            &["startpos", "moves", "e2e4", "e7e5", "g1f3"],
+            Variant::Standard,
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            state.to_fen(),
+            "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+        );
+
+        let args = "fen 8/4P3/8/8/8/8/k7/7K w - - 0 1 moves e7e8n";
+        assert_eq!(
+            parse_position(
+                &args.split(' ').collect::<Vec<_>>(),
+                Variant::Standard,
+                false
+            )
+            .unwrap()
+            .to_fen(),
+            "4N3/8/8/8/8/8/k7/7K b - - 0 1"
+        );
+
+        assert_eq!(
+            parse_position(&["startpos", "moves", "e2e5"], Variant::Standard, false).err(),
+            Some(UciError::IllegalMove(String::from("e2e5")))
+        );
+        assert_eq!(
+            parse_position(&["moves"], Variant::Standard, false).err(),
+            Some(UciError::MissingPosition)
+        );
+    }
+
+    #[test]
+    fn position_arguments() {
+        let state = parse_position(
+            &["startpos", "moves", "e2e4", "c7c5"],
+            Variant::Standard,
+            false,
+        )
+        .unwrap();
+        assert_eq!(state.uci_position(), "startpos moves e2e4 c7c5");
+
+        let fen = "8/4P3/8/8/8/8/k7/7K w - - 0 1";
+        let mut state = State::from_fen(fen).unwrap();
+        assert_eq!(state.uci_position(), format!("fen {fen}"));
+        state.make_move(state.parse_uci_move("e7e8q").unwrap());
+        assert_eq!(state.uci_position(), format!("fen {fen} moves e7e8q"));
+
+        // with the king on g1, short castling leaves it where it is
+        let fen = "bqnbr1kr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR b kq - 2 9";
+        let args = format!("fen {fen} moves g8h8");
+        let state = parse_position(
+            &args.split(' ').collect::<Vec<_>>(),
+            Variant::Standard,
+            true,
+        )
+        .unwrap();
+        assert_eq!(state[(6, 7)].content.unwrap().to_char(), 'k');
+        assert_eq!(state[(5, 7)].content.unwrap().to_char(), 'r');
+        assert_eq!(state.uci_position(), args);
+
+        let args = "startpos moves e2e4 e7e5 g1f3 b8c6 f1c4 g8f6";
+        let state = parse_position(
+            &args.split(' ').collect::<Vec<_>>(),
+            Variant::Standard,
+            true,
+        )
+        .unwrap();
+        assert!(state.parse_uci_move("e1g1").is_none());
+        assert!(state.parse_uci_move("e1h1").is_some());
+    }
+
+    #[test]
+    fn go_parsing() {
+        let limits = parse_go(&[
+            "wtime", "60000", "btime", "59000", "winc", "1000", "depth", "6",
+        ]);
+        assert_eq!(limits.time_left[0], Some(Duration::from_secs(60)));
+        assert_eq!(limits.time_left[1], Some(Duration::from_secs(59)));
+        assert_eq!(limits.increment, [Some(Duration::from_secs(1)), None]);
+        assert_eq!(limits.depth, Some(6));
+        assert!(!limits.infinite);
+
+        assert!(parse_go(&["infinite"]).infinite);
+        assert_eq!(
+            parse_go(&["ponder", "movetime", "250"]).movetime,
+            Some(Duration::from_millis(250))
+        );
+
+        let text = "wtime 60000 btime 59000 winc 1000 binc 1000 movestogo 12 depth 6";
+        let limits = parse_go(&text.split(' ').collect::<Vec<_>>());
+        assert_eq!(go_arguments(&limits), text);
+        assert_eq!(go_arguments(&parse_go(&["infinite"])), "infinite");
+    }
+
+    #[test]
+    fn handshake_and_search() {
+        let lines = session("uci\nisready\nposition startpos moves e2e4\ngo depth 1\nquit\n");
+        assert_eq!(
+            lines[..6],
+            [
+                "id name schaak",
+                "id author the schaak developers",
+                "option name UCI_Chess960 type check default false",
+                "option name UCI_Variant type combo default chess var chess var 3check \
+                 var kingofthehill var antichess var crazyhouse var atomic var horde var racingkings",
+                "uciok",
+                "readyok"
+            ]
+        );
+
+        let best_move = lines[6].strip_prefix("bestmove ").unwrap();
+        let state =
+            parse_position(&["startpos", "moves", "e2e4"], Variant::Standard, false).unwrap();
+        assert!(state.parse_uci_move(best_move).is_some());
+    }
+
+    #[test]
+    fn variants() {
+        // taking is compulsory, so there is only one move
+        let lines = session(
+            "setoption name UCI_Variant value antichess\n\
+             position startpos moves e2e3 b7b5\n\
+             go depth 2\n",
+        );
+        assert_eq!(lines, ["bestmove f1b5"]);
+
+        // crazyhouse drops are written P@e2
+        let args = "startpos moves e2e4 d7d5 e4d5 d8d5 b1c3 d5e5 P@e2"
+            .split(' ')
+            .collect::<Vec<_>>();
+        let state = parse_position(&args, Variant::Crazyhouse, false).unwrap();
+        assert_eq!(
+            state.to_fen(),
+            "rnb1kbnr/ppp1pppp/8/4q3/8/2N5/PPPPPPPP/R1BQKBNR[p] b KQkq - 0 4"
+        );
+        assert_eq!(state.uci_position(), args.join(" "));
+
+        let lines = session("setoption name UCI_Variant value shogi\n");
+        assert_eq!(lines, ["info string unknown variant shogi"]);
+    }
+
+    #[test]
+    fn infinite_waits_for_stop() {
+        let lines = session("position fen 7k/8/8/8/8/8/8/R6K w - - 0 1\ngo infinite\nstop\n");
+        assert_eq!(lines.len(), 1);
+        assert!(lines[0].starts_with("bestmove "));
+
+        // checkmated, so there is nothing to play
+        let lines = session("position fen R6k/8/7K/8/8/8/8/8 b - - 0 1\ngo\n");
+        assert_eq!(lines, ["bestmove 0000"]);
+    }
+
+    // a player that never gets as far as picking a move
+    struct PanickingPlayer;
+
+    impl Player for PanickingPlayer {
+        fn new(
+            _: mpsc::Receiver<MoveInfo>,
+            _: &State,
+            _: ChessColour,
+        ) -> (Self, mpsc::Receiver<MoveInfo>) {
+            panic!("no moves here");
+        }
+        fn receive_move_from_main(&mut self) -> Result<MoveInfo, mpsc::TryRecvError> {
+            unreachable!()
+        }
+        fn apply_move(&mut self, _: MoveInfo) {}
+        fn set_position(&mut self, _: &State) {}
+        fn return_new_move(&self) -> Option<MoveInfo> {
+            None
+        }
+        fn ponder_new_move(&mut self) {}
+        fn send_move_to_main(&mut self) -> Result<(), mpsc::SendError<MoveInfo>> {
+            unreachable!()
+        }
+    }
+
+    #[test]
+    fn broken_positions_and_searches() {
+        // a position that can't come up in a game is refused, rather than searched
+        let lines = session(
+            "position fen P3k3/8/8/8/8/8/8/4K3 w - - 0 1\ngo depth 1\n\
+             position startpos moves e2e5\ngo depth 1\n\
+             position startpos\ngo depth 1\n",
+        );
+        assert_eq!(
+            lines[..4],
+            [
+                "info string invalid fen: there can't be a pawn on a8",
+                "bestmove 0000",
+                "info string e2e5 is not a legal move",
+                "bestmove 0000"
+            ]
+        );
+        // until a position that is fine comes along
+        assert_eq!(lines.len(), 5);
+        assert_ne!(lines[4], "bestmove 0000");
+
+        // a search that falls over still answers
+        let lines = session_with::<PanickingPlayer>("position startpos\ngo\nstop\nisready\n");
+        assert_eq!(
+            lines,
+            ["info string the search failed", "bestmove 0000", "readyok"]
+        );
+    }
+
+    // what the RecordingPlayer was made to do, as it is hidden away in the search threads
+    static CALLS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    // a random player that writes down how it is set up
+    struct RecordingPlayer(RandomPlayer);
+
+    impl Player for RecordingPlayer {
+        fn new(
+            rx_from_main: mpsc::Receiver<MoveInfo>,
+            state: &State,
+            colour: ChessColour,
+        ) -> (Self, mpsc::Receiver<MoveInfo>) {
+            CALLS.lock().unwrap().push(String::from("new"));
+            let (player, rx) = RandomPlayer::new(rx_from_main, state, colour);
+            (RecordingPlayer(player), rx)
+        }
+        fn receive_move_from_main(&mut self) -> Result<MoveInfo, mpsc::TryRecvError> {
+            self.0.receive_move_from_main()
+        }
+        fn apply_move(&mut self, mi: MoveInfo) {
+            self.0.apply_move(mi);
+        }
+        fn set_position(&mut self, state: &State) {
+            CALLS
+                .lock()
+                .unwrap()
+                .push(format!("position {}", state.uci_position()));
+            self.0.set_position(state);
+        }
+        fn return_new_move(&self) -> Option<MoveInfo> {
+            self.0.return_new_move()
+        }
+        fn ponder_new_move(&mut self) {
+            self.0.ponder_new_move();
+        }
+        fn send_move_to_main(&mut self) -> Result<(), mpsc::SendError<MoveInfo>> {
+            self.0.send_move_to_main()
+        }
+        fn set_option(&mut self, name: &str, value: &str) {
+            CALLS.lock().unwrap().push(format!("{name} {value}"));
+        }
+        fn clear(&mut self) {
+            CALLS.lock().unwrap().push(String::from("clear"));
+        }
+    }
+
+    #[test]
+    fn one_player_for_the_session() {
+        let lines = session_with::<RecordingPlayer>(
+            "setoption name Hash value 16\n\
+             position startpos\n\
+             go depth 1\n\
+             position startpos moves e2e4\n\
+             go depth 1\n\
+             setoption name Hash value 32\n\
+             ucinewgame\n\
+             go depth 1\n",
+        );
+        assert_eq!(lines.len(), 3);
+        assert_eq!(
+            *CALLS.lock().unwrap(),
+            [
+                "new",
+                "Hash 16",
+                "position startpos",
+                "position startpos moves e2e4",
+                "Hash 32",
+                "clear",
+                "position startpos"
+            ]
+        );
+    }
+}
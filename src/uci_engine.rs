@@ -0,0 +1,654 @@
+use crate::{
+    chess_move::{ChessMove, MoveInfo},
+    clock::Clock,
+    piece::ChessColour,
+    player::{Player, SearchLimits},
+    state::State,
+    uci::go_arguments,
+    variant::Variant,
+};
+
+use std::fmt;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// how long an engine gets to answer anything but go
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+// how much longer than its time an engine gets to answer go, or to answer stop
+const GO_MARGIN: Duration = Duration::from_secs(2);
+// how long a search without any time limit, e.g. go depth 20, may take
+const UNTIMED_GO_TIMEOUT: Duration = Duration::from_secs(600);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum UciEngineError {
+    EmptyCommand,
+    Spawn(String, String),
+    // the engine's streams broke, usually because it exited
+    Disconnected,
+    Timeout(&'static str),
+    IllegalMove(String),
+}
+
+impl fmt::Display for UciEngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UciEngineError::EmptyCommand => write!(f, "no engine command given"),
+            UciEngineError::Spawn(command, e) => write!(f, "could not start {command}: {e}"),
+            UciEngineError::Disconnected => write!(f, "the engine has stopped responding"),
+            UciEngineError::Timeout(waiting_for) => {
+                write!(f, "the engine did not send {waiting_for} in time")
+            }
+            UciEngineError::IllegalMove(m) => write!(f, "the engine played {m}, which is illegal"),
+        }
+    }
+}
+
+impl std::error::Error for UciEngineError {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Score {
+    Centipawns(i32),
+    // in moves rather than plies, negative when the engine is getting mated
+    Mate(i32),
+}
+
+// the parts of an info line worth keeping. anything missing is None
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct EngineInfo {
+    pub depth: Option<u32>,
+    pub score: Option<Score>,
+    pub nodes: Option<u64>,
+    pub pv: Vec<String>,
+}
+
+impl EngineInfo {
+    // everything after `info`
+    pub fn parse(args: &[&str]) -> Self {
+        let mut info = EngineInfo::default();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match *arg {
+                "depth" => info.depth = args.next().and_then(|d| d.parse().ok()),
+                "nodes" => info.nodes = args.next().and_then(|n| n.parse().ok()),
+                "score" => {
+                    let (kind, value) = (args.next(), args.next().and_then(|v| v.parse().ok()));
+                    info.score = match (kind, value) {
+                        (Some(&"cp"), Some(cp)) => Some(Score::Centipawns(cp)),
+                        (Some(&"mate"), Some(moves)) => Some(Score::Mate(moves)),
+                        _ => None,
+                    };
+                }
+                // the rest of the line is the pv
+                "pv" => info.pv = args.by_ref().map(|m| m.to_string()).collect(),
+                // the rest of the line is free text
+                "string" => break,
+                _ => {}
+            }
+        }
+        info
+    }
+}
+
+// a running engine process, spoken to over its stdin and stdout
+pub struct UciEngine {
+    child: Child,
+    stdin: ChildStdin,
+    // filled by a thread reading the engine's stdout, so that waiting can time out
+    lines: Receiver<String>,
+    pub name: Option<String>,
+}
+
+impl UciEngine {
+    // runs the command, split on whitespace, and waits for uciok and readyok
+    pub fn spawn(command: &str) -> Result<Self, UciEngineError> {
+        let mut words = command.split_whitespace();
+        let program = words.next().ok_or(UciEngineError::EmptyCommand)?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(|e| UciEngineError::Spawn(command.to_string(), e.to_string()))?;
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (tx_lines, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if tx_lines.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = UciEngine {
+            child,
+            stdin,
+            lines,
+            name: None,
+        };
+        engine.send("uci")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let line = engine.receive(deadline, "uciok")?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = Some(name.to_string());
+            } else if line.trim() == "uciok" {
+                break;
+            }
+        }
+        engine.sync()?;
+        Ok(engine)
+    }
+
+    pub fn send(&mut self, line: &str) -> Result<(), UciEngineError> {
+        writeln!(self.stdin, "{line}")
+            .and_then(|_| self.stdin.flush())
+            .map_err(|_| UciEngineError::Disconnected)
+    }
+
+    fn receive(
+        &self,
+        deadline: Instant,
+        waiting_for: &'static str,
+    ) -> Result<String, UciEngineError> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        self.lines.recv_timeout(timeout).map_err(|e| match e {
+            RecvTimeoutError::Timeout => UciEngineError::Timeout(waiting_for),
+            RecvTimeoutError::Disconnected => UciEngineError::Disconnected,
+        })
+    }
+
+    // isready, and wait for readyok
+    pub fn sync(&mut self) -> Result<(), UciEngineError> {
+        self.send("isready")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        while self.receive(deadline, "readyok")?.trim() != "readyok" {}
+        Ok(())
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), UciEngineError> {
+        self.send(&format!("setoption name {name} value {value}"))
+    }
+
+    pub fn new_game(&mut self) -> Result<(), UciEngineError> {
+        self.send("ucinewgame")?;
+        self.sync()
+    }
+
+    // when an engine that hasn't sent bestmove by now has hung. an infinite search has no
+    // deadline until it is stopped
+    fn go_deadline(limits: &SearchLimits, turn: ChessColour, start: Instant) -> Option<Instant> {
+        let time = [limits.movetime, limits.time_left[turn.index()]]
+            .into_iter()
+            .flatten()
+            .min();
+        match time {
+            Some(time) => Some(start + time + GO_MARGIN),
+            None if limits.infinite => None,
+            None => Some(start + UNTIMED_GO_TIMEOUT),
+        }
+    }
+
+    // has the engine search the position and returns its move, along with the last info it sent.
+    // None means the engine had nothing to play. setting the limits' stop flag sends stop
+    pub fn go(
+        &mut self,
+        state: &State,
+        limits: &SearchLimits,
+    ) -> Result<(Option<ChessMove>, EngineInfo), UciEngineError> {
+        self.send(&format!("position {}", state.uci_position()))?;
+        self.send(format!("go {}", go_arguments(limits)).trim_end())?;
+
+        let mut info = EngineInfo::default();
+        let mut stop_sent = false;
+        let mut deadline = UciEngine::go_deadline(limits, state.turn, Instant::now());
+        loop {
+            if !stop_sent && limits.stop.load(Ordering::Relaxed) {
+                self.send("stop")?;
+                stop_sent = true;
+                let answer_by = Instant::now() + GO_MARGIN;
+                deadline = Some(deadline.map_or(answer_by, |d| d.min(answer_by)));
+            }
+
+            let line = match self.lines.recv_timeout(Duration::from_millis(10)) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    if deadline.is_some_and(|d| Instant::now() >= d) {
+                        return Err(UciEngineError::Timeout("bestmove"));
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => return Err(UciEngineError::Disconnected),
+            };
+            let words = line.split_whitespace().collect::<Vec<_>>();
+            match words.split_first() {
+                Some((&"info", args)) => {
+                    let new_info = EngineInfo::parse(args);
+                    // info lines that only report e.g. currmove don't replace the search results
+                    if new_info.depth.is_some() || new_info.score.is_some() {
+                        info = new_info;
+                    }
+                }
+                Some((&"bestmove", args)) => {
+                    let text = args.first().copied().unwrap_or("0000");
+                    if text == "0000" || text == "(none)" {
+                        return Ok((None, info));
+                    }
+                    return state
+                        .parse_uci_move(text)
+                        .map(|m| (Some(m), info))
+                        .ok_or_else(|| UciEngineError::IllegalMove(text.to_string()));
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    // asks nicely first, but doesn't wait long for the engine to listen
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        for _ in 0..50 {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+pub struct UciEnginePlayer {
+    tx_to_main: Sender<MoveInfo>,
+    rx_from_main: Receiver<MoveInfo>,
+
+    internal_state: State,
+
+    move_info: Option<MoveInfo>,
+
+    colour: ChessColour,
+
+    // exclusive to UciEnginePlayer
+    // None until start_engine, and again after the engine has failed
+    engine: Option<UciEngine>,
+    // what went wrong with the engine, after which the player doesn't move again
+    failure: Option<UciEngineError>,
+    // set before the engine was started, and sent to it once it is
+    options: Vec<(String, String)>,
+    limits: SearchLimits,
+    clock: Option<Arc<Mutex<Clock>>>,
+    // set once a move has been sent, until main echoes it back
+    awaiting_echo: bool,
+}
+
+impl UciEnginePlayer {
+    // used until set_limits says otherwise
+    pub const DEFAULT_MOVETIME: Duration = Duration::from_secs(1);
+
+    // the player doesn't move until this has succeeded, as Player::new has no way of knowing
+    // which engine to run
+    pub fn start_engine(&mut self, command: &str) -> Result<(), UciEngineError> {
+        let mut engine = UciEngine::spawn(command)?;
+        for (name, value) in &self.options {
+            engine.set_option(name, value)?;
+        }
+        if self.internal_state.chess960 {
+            engine.set_option("UCI_Chess960", "true")?;
+        }
+        if self.internal_state.variant != Variant::Standard {
+            engine.set_option("UCI_Variant", self.internal_state.variant.uci_name())?;
+        }
+        engine.new_game()?;
+        self.engine = Some(engine);
+        Ok(())
+    }
+
+    pub fn engine_name(&self) -> Option<&str> {
+        self.engine.as_ref()?.name.as_deref()
+    }
+
+    // an engine that got something wrong can't be trusted with the rest of the game, so it is
+    // let go, and the error is left for whoever runs the player to pick up through failure
+    fn fail(&mut self, e: UciEngineError) {
+        self.engine = None;
+        self.failure = Some(e);
+    }
+}
+
+impl Player for UciEnginePlayer {
+    fn new(
+        rx_from_main: Receiver<MoveInfo>,
+        state_to_clone: &State,
+        colour: ChessColour,
+    ) -> (Self, Receiver<MoveInfo>) {
+        let (own_tx, own_rx) = mpsc::channel();
+
+        (
+            UciEnginePlayer {
+                rx_from_main,
+                tx_to_main: own_tx,
+                internal_state: state_to_clone.clone(),
+                move_info: None,
+                colour,
+                engine: None,
+                failure: None,
+                options: Vec::new(),
+                limits: SearchLimits {
+                    movetime: Some(UciEnginePlayer::DEFAULT_MOVETIME),
+                    ..Default::default()
+                },
+                clock: None,
+                awaiting_echo: false,
+            },
+            own_rx,
+        )
+    }
+
+    fn apply_move(&mut self, mi: MoveInfo) {
+        self.internal_state.make_move(mi.move_data);
+        self.awaiting_echo = false;
+    }
+
+    fn set_position(&mut self, state: &State) {
+        self.internal_state = state.clone();
+        self.colour = state.turn;
+        self.move_info = None;
+        self.awaiting_echo = false;
+    }
+
+    fn receive_move_from_main(&mut self) -> Result<MoveInfo, mpsc::TryRecvError> {
+        self.rx_from_main.try_recv()
+    }
+
+    fn return_new_move(&self) -> Option<MoveInfo> {
+        self.move_info
+    }
+
+    fn send_move_to_main(&mut self) -> Result<(), mpsc::SendError<MoveInfo>> {
+        self.tx_to_main.send(self.move_info.unwrap())?;
+        self.move_info = None;
+        self.awaiting_echo = true;
+
+        Ok(())
+    }
+
+    // blocks until the engine answers
+    fn ponder_new_move(&mut self) {
+        if self.internal_state.turn != self.colour || self.awaiting_echo || self.move_info.is_some()
+        {
+            return;
+        }
+        let Some(engine) = &mut self.engine else {
+            return;
+        };
+
+        let limits = match &self.clock {
+            Some(clock) => clock
+                .lock()
+                .unwrap()
+                .search_limits(&self.limits, Instant::now()),
+            None => self.limits.clone(),
+        };
+        match engine.go(&self.internal_state, &limits) {
+            Ok((best_move, info)) => {
+                log!(
+                    "engine: depth {:?} score {:?} nodes {:?} pv {}",
+                    info.depth,
+                    info.score,
+                    info.nodes,
+                    info.pv.join(" ")
+                );
+                self.move_info = best_move.map(|move_data| MoveInfo { move_data });
+            }
+            Err(e) => self.fail(e),
+        }
+    }
+
+    fn specific_tick(&mut self) {
+        log!("tick from {:?} uci engine player", self.colour);
+    }
+
+    fn set_limits(&mut self, limits: SearchLimits) {
+        self.limits = limits;
+    }
+
+    // passed on to the engine as they are
+    fn set_option(&mut self, name: &str, value: &str) {
+        self.options.retain(|(n, _)| n != name);
+        self.options.push((name.to_string(), value.to_string()));
+        if let Some(Err(e)) = self.engine.as_mut().map(|e| e.set_option(name, value)) {
+            self.fail(e);
+        }
+    }
+
+    fn clear(&mut self) {
+        if let Some(Err(e)) = self.engine.as_mut().map(UciEngine::new_game) {
+            self.fail(e);
+        }
+    }
+
+    fn set_clock(&mut self, clock: Arc<Mutex<Clock>>) {
+        self.clock = Some(clock);
+    }
+
+    fn failure(&self) -> Option<String> {
+        self.failure.as_ref().map(|e| e.to_string())
+    }
+}
+
+#[cfg(all(test, unix))]
+pub(crate) mod tests {
+    use super::*;
+
+    // a shell script that speaks just enough UCI, in a directory of its own that is removed
+    // again once the test is done with it
+    pub(crate) struct StandIn {
+        pub(crate) command: String,
+        dir: std::path::PathBuf,
+    }
+
+    impl StandIn {
+        // everything the engine was sent
+        fn log(&self) -> String {
+            std::fs::read_to_string(self.dir.join("engine.log")).unwrap()
+        }
+    }
+
+    impl Drop for StandIn {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    // `go` is answered with the given lines
+    pub(crate) fn stand_in_engine(name: &str, answer: &[&str]) -> StandIn {
+        let dir = std::env::temp_dir().join(format!("schaak-{}-{name}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let script = dir.join("engine.sh");
+        let log = dir.join("engine.log");
+
+        let answer = answer
+            .iter()
+            .map(|line| format!("echo '{line}'; "))
+            .collect::<String>();
+        std::fs::write(
+            &script,
+            format!(
+                "while read -r line; do\n\
+                 echo \"$line\" >> '{}'\n\
+                 case \"$line\" in\n\
+                 uci) echo 'id name stand-in'; echo 'option name Hash type spin default 1 min 1 max 8'; echo uciok ;;\n\
+                 isready) echo readyok ;;\n\
+                 go*) {answer};;\n\
+                 quit) exit 0 ;;\n\
+                 esac\n\
+                 done\n",
+                log.display()
+            ),
+        )
+        .unwrap();
+        StandIn {
+            command: format!("sh {}", script.display()),
+            dir,
+        }
+    }
+
+    fn play_one_move(command: &str, state: &State) -> Result<Option<ChessMove>, UciEngineError> {
+        let (_tx_to_player, rx_to_player) = mpsc::channel();
+        let (mut player, rx_from_player) = UciEnginePlayer::new(rx_to_player, state, state.turn);
+        player.set_option("Hash", "8");
+        player.start_engine(command)?;
+        assert_eq!(player.engine_name(), Some("stand-in"));
+
+        player.tick();
+        player.tick();
+        Ok(rx_from_player.try_recv().ok().map(|mi| mi.move_data))
+    }
+
+    #[test]
+    fn plays_the_engines_move() {
+        let engine = stand_in_engine(
+            "move",
+            &[
+                "info depth 3 score cp 25 nodes 1234 pv e7e5 g1f3",
+                "bestmove e7e5",
+            ],
+        );
+        let mut state = State::new();
+        state.make_move(state.parse_uci_move("e2e4").unwrap());
+
+        let best_move = play_one_move(&engine.command, &state).unwrap();
+        assert_eq!(best_move.unwrap().to_string(), "e7e5");
+
+        let log = engine.log();
+        let sent = log.lines().collect::<Vec<_>>();
+        assert_eq!(
+            sent,
+            [
+                "uci",
+                "isready",
+                "setoption name Hash value 8",
+                "ucinewgame",
+                "isready",
+                "position startpos moves e2e4",
+                "go movetime 1000",
+                // once the player is dropped
+                "quit",
+            ]
+        );
+    }
+
+    #[test]
+    fn engine_errors() {
+        let state = State::new();
+        let engine = stand_in_engine("illegal", &["bestmove e2e5"]);
+        let (_tx_to_player, rx_to_player) = mpsc::channel();
+        let (mut player, _) = UciEnginePlayer::new(rx_to_player, &state, state.turn);
+        player.start_engine(&engine.command).unwrap();
+        assert_eq!(
+            player
+                .engine
+                .as_mut()
+                .unwrap()
+                .go(&state, &player.limits)
+                .err(),
+            Some(UciEngineError::IllegalMove(String::from("e2e5")))
+        );
+
+        // the player stops, and says why
+        let (_tx_to_player, rx_to_player) = mpsc::channel();
+        let (mut player, rx_from_player) = UciEnginePlayer::new(rx_to_player, &state, state.turn);
+        player.start_engine(&engine.command).unwrap();
+        player.tick();
+        assert!(rx_from_player.try_recv().is_err());
+        assert_eq!(
+            player.failure().as_deref(),
+            Some("the engine played e2e5, which is illegal")
+        );
+
+        // as it does when the engine goes away in between moves
+        let engine = stand_in_engine("gone", &[]);
+        let (_tx_to_player, rx_to_player) = mpsc::channel();
+        let (mut player, _) = UciEnginePlayer::new(rx_to_player, &state, state.turn);
+        player.start_engine(&engine.command).unwrap();
+        let running = player.engine.as_mut().unwrap();
+        running.send("quit").unwrap();
+        running.child.wait().unwrap();
+        player.clear();
+        assert_eq!(
+            player.failure(),
+            Some(UciEngineError::Disconnected.to_string())
+        );
+
+        let engine = stand_in_engine("none", &["bestmove (none)"]);
+        assert_eq!(play_one_move(&engine.command, &state), Ok(None));
+
+        // an engine that never answers is given up on once its time is well past
+        let engine = stand_in_engine("hung", &[]);
+        let mut hung = UciEngine::spawn(&engine.command).unwrap();
+        let limits = SearchLimits {
+            movetime: Some(Duration::from_millis(10)),
+            ..SearchLimits::default()
+        };
+        let start = Instant::now();
+        assert_eq!(
+            hung.go(&state, &limits).err(),
+            Some(UciEngineError::Timeout("bestmove"))
+        );
+        assert!(start.elapsed() >= GO_MARGIN);
+        // only stopping gives an infinite search a deadline
+        let infinite = SearchLimits {
+            infinite: true,
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            UciEngine::go_deadline(&infinite, ChessColour::White, start),
+            None
+        );
+        let clock = SearchLimits {
+            time_left: [Some(Duration::from_secs(30)), Some(Duration::from_secs(1))],
+            ..SearchLimits::default()
+        };
+        assert_eq!(
+            UciEngine::go_deadline(&clock, ChessColour::Black, start),
+            Some(start + Duration::from_secs(1) + GO_MARGIN)
+        );
+
+        assert!(matches!(
+            UciEngine::spawn("/nonexistent/engine").err(),
+            Some(UciEngineError::Spawn(..))
+        ));
+        assert_eq!(
+            UciEngine::spawn(" ").err(),
+            Some(UciEngineError::EmptyCommand)
+        );
+    }
+
+    #[test]
+    fn info_lines() {
+        let text = "depth 12 seldepth 18 score mate -3 nodes 99000 nps 1000 pv e2e4 e7e5";
+        assert_eq!(
+            EngineInfo::parse(&text.split(' ').collect::<Vec<_>>()),
+            EngineInfo {
+                depth: Some(12),
+                score: Some(Score::Mate(-3)),
+                nodes: Some(99000),
+                pv: vec![String::from("e2e4"), String::from("e7e5")],
+            }
+        );
+        assert_eq!(
+            EngineInfo::parse(&["string", "depth", "5"]),
+            EngineInfo::default()
+        );
+    }
+}
@@ -0,0 +1,549 @@
+use crate::{
+    bitboard::*,
+    chess_move::ChessMove,
+    fen::STARTING_FEN,
+    piece::{PieceKind::*, *},
+    state::State,
+    termination::Termination,
+};
+
+use std::fmt;
+
+// the rules a game is played by. every variant starts from standard chess, and changes which
+// moves are legal, how the game can end, or both
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum Variant {
+    #[default]
+    Standard,
+    // whoever gives the third check wins
+    ThreeCheck,
+    // whoever gets their king to one of the four centre squares wins
+    KingOfTheHill,
+    // captures are compulsory, the king is just another piece, and whoever loses everything wins
+    Antichess,
+    // captured pieces change sides, and can be dropped back onto the board instead of moving
+    Crazyhouse,
+    // captures blow up everything around them apart from pawns, and whoever loses their king loses
+    Atomic,
+    // white has a horde of pawns and no king, and loses once they're all gone
+    Horde,
+    // nobody may give check, and the first king to the last rank wins
+    RacingKings,
+}
+
+// d4, e4, d5 and e5
+pub const HILL: Bitboard = 0x0000_0018_1800_0000;
+
+// the squares an atomic capture on the given square may blow up: that square first, and then the
+// ones around it, lowest first
+pub fn blast_squares(centre: (u8, u8)) -> impl Iterator<Item = (u8, u8)> {
+    std::iter::once(centre).chain(squares_of(KING_ATTACKS[square_index(centre)]))
+}
+
+impl Variant {
+    pub const ALL: [Variant; 8] = [
+        Variant::Standard,
+        Variant::ThreeCheck,
+        Variant::KingOfTheHill,
+        Variant::Antichess,
+        Variant::Crazyhouse,
+        Variant::Atomic,
+        Variant::Horde,
+        Variant::RacingKings,
+    ];
+
+    // as written in PGN's Variant tag
+    pub fn name(self) -> &'static str {
+        match self {
+            Variant::Standard => "Standard",
+            Variant::ThreeCheck => "Three-check",
+            Variant::KingOfTheHill => "King of the Hill",
+            Variant::Antichess => "Antichess",
+            Variant::Crazyhouse => "Crazyhouse",
+            Variant::Atomic => "Atomic",
+            Variant::Horde => "Horde",
+            Variant::RacingKings => "Racing Kings",
+        }
+    }
+
+    // as used for UCI_Variant, where most engines follow Fairy-Stockfish
+    pub fn uci_name(self) -> &'static str {
+        match self {
+            Variant::Standard => "chess",
+            Variant::ThreeCheck => "3check",
+            Variant::KingOfTheHill => "kingofthehill",
+            Variant::Antichess => "antichess",
+            Variant::Crazyhouse => "crazyhouse",
+            Variant::Atomic => "atomic",
+            Variant::Horde => "horde",
+            Variant::RacingKings => "racingkings",
+        }
+    }
+
+    // either name, ignoring case, spaces and dashes
+    pub fn parse(text: &str) -> Option<Self> {
+        let simplified = |name: &str| name.to_lowercase().replace([' ', '-'], "");
+        let text = simplified(text);
+        Variant::ALL
+            .into_iter()
+            .find(|v| text == simplified(v.name()) || text == simplified(v.uci_name()))
+    }
+
+    pub fn start_fen(self) -> &'static str {
+        match self {
+            Variant::Antichess => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            Variant::Crazyhouse => "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1",
+            Variant::Horde => {
+                "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1"
+            }
+            Variant::RacingKings => "8/8/8/8/8/8/krbnNBRK/qrbnNBRQ w - - 0 1",
+            _ => STARTING_FEN,
+        }
+    }
+
+    // whether kings can be put in check, which also decides whether they can castle
+    pub fn royal_king(self) -> bool {
+        self != Variant::Antichess
+    }
+
+    // whether the back rank can be shuffled the way Chess960 does, which only makes sense when
+    // the game starts from the usual pieces and has castling
+    pub fn allows_chess960(self) -> bool {
+        !matches!(
+            self,
+            Variant::Antichess | Variant::Horde | Variant::RacingKings
+        )
+    }
+
+    // whether the given side starts with a king at all
+    pub fn has_king(self, col: ChessColour) -> bool {
+        !(self == Variant::Horde && col == ChessColour::White)
+    }
+
+    // what a pawn may promote to
+    pub fn promotions(self) -> &'static [PieceKind] {
+        match self {
+            Variant::Antichess => &[Queen, Rook, Bishop, Knight, King],
+            _ => &[Queen, Rook, Bishop, Knight],
+        }
+    }
+
+    // whether captured pieces go into a pocket to be dropped later
+    pub fn has_pockets(self) -> bool {
+        self == Variant::Crazyhouse
+    }
+
+    // anything the variant rules out on top of the usual rules
+    pub fn restrict_moves(self, moves: &mut Vec<ChessMove>) {
+        if self == Variant::Antichess && moves.iter().any(ChessMove::is_capture) {
+            moves.retain(ChessMove::is_capture);
+        }
+    }
+}
+
+impl fmt::Display for Variant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl State {
+    pub fn new_variant(variant: Variant) -> Self {
+        State::from_fen_with_variant(variant.start_fen(), variant)
+            .expect("Starting position FEN is malformed")
+    }
+
+    // the variant's own way of winning, once somebody has managed it. the game is over then,
+    // so there are no legal moves left
+    pub fn variant_termination(&self) -> Option<Termination> {
+        if self.variant == Variant::RacingKings {
+            return self.race_termination();
+        }
+        for col in [ChessColour::White, ChessColour::Black] {
+            let reason = match self.variant {
+                Variant::Standard => return None,
+                Variant::ThreeCheck if self.checks_given[col.index()] >= 3 => "third check",
+                Variant::KingOfTheHill if self.pieces[col.index()][King.index()] & HILL != 0 => {
+                    "king of the hill"
+                }
+                Variant::Antichess if self.occupancy[col.index()] == 0 => "lost all pieces",
+                Variant::Atomic if self.pieces[col.flip().index()][King.index()] == 0 => {
+                    "king exploded"
+                }
+                Variant::Horde if col == ChessColour::Black && self.occupancy[0] == 0 => {
+                    "captured the horde"
+                }
+                _ => continue,
+            };
+            return Some(Termination::VariantEnd {
+                winner: Some(col),
+                reason,
+            });
+        }
+        None
+    }
+
+    // the first king to the last rank wins, except that when white gets there first, black
+    // still gets to move, and draws by getting there as well
+    fn race_termination(&self) -> Option<Termination> {
+        let arrived = |col: ChessColour| self.pieces[col.index()][King.index()] & rank_mask(7) != 0;
+        let winner = match (arrived(ChessColour::White), arrived(ChessColour::Black)) {
+            (false, false) => return None,
+            (true, true) => {
+                return Some(Termination::VariantEnd {
+                    winner: None,
+                    reason: "both kings reached the last rank",
+                })
+            }
+            (false, true) => ChessColour::Black,
+            (true, false) => {
+                let catching_up = self.turn == ChessColour::Black
+                    && self
+                        .legal_moves(ChessColour::Black)
+                        .iter()
+                        .any(|m| self[m.src].content.unwrap().kind == King && m.dst.1 == 7);
+                if catching_up {
+                    return None;
+                }
+                ChessColour::White
+            }
+        };
+        Some(Termination::VariantEnd {
+            winner: Some(winner),
+            reason: "king reached the last rank",
+        })
+    }
+
+    // how the game has ended if the side to move has no legal moves
+    pub fn no_moves_termination(&self) -> Termination {
+        if let Some(termination) = self.variant_termination() {
+            return termination;
+        }
+        match self.variant {
+            Variant::Antichess => Termination::VariantEnd {
+                winner: Some(self.turn),
+                reason: "no moves left",
+            },
+            _ if self.is_in_check(self.turn) => Termination::Checkmate {
+                winner: self.turn.flip(),
+            },
+            _ => Termination::Stalemate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chess_move::MoveKind, fen::FenError};
+
+    fn play(state: &mut State, moves: &[&str]) {
+        for text in moves {
+            let chess_move = state
+                .parse_uci_move(text)
+                .unwrap_or_else(|| panic!("{text} is not legal"));
+            state.make_move(chess_move);
+        }
+    }
+
+    #[test]
+    fn names() {
+        for variant in Variant::ALL {
+            assert_eq!(Variant::parse(variant.name()), Some(variant));
+            assert_eq!(Variant::parse(variant.uci_name()), Some(variant));
+        }
+        assert_eq!(Variant::parse("three check"), Some(Variant::ThreeCheck));
+        assert_eq!(
+            Variant::parse("KingOfTheHill"),
+            Some(Variant::KingOfTheHill)
+        );
+        assert_eq!(Variant::parse("shogi"), None);
+    }
+
+    #[test]
+    fn three_check() {
+        let mut state = State::new_variant(Variant::ThreeCheck);
+        assert_eq!(
+            state.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 3+3 0 1"
+        );
+        play(
+            &mut state,
+            &["e2e4", "d7d5", "f1b5", "c7c6", "b5c6", "b7c6"],
+        );
+        assert_eq!(state.checks_given, [2, 0]);
+        assert!(state.to_fen().contains(" 1+3 "));
+        let from_fen = State::from_fen_with_variant(&state.to_fen(), Variant::ThreeCheck).unwrap();
+        assert_eq!(from_fen.checks_given, [2, 0]);
+        assert_eq!(from_fen.hash, state.hash);
+
+        play(&mut state, &["d1h5", "g8f6", "h5f7"]);
+        assert_eq!(
+            state.forced_termination(),
+            Some(Termination::VariantEnd {
+                winner: Some(ChessColour::White),
+                reason: "third check"
+            })
+        );
+        assert!(state.get_all_moves_for_colour(state.turn).is_empty());
+
+        state.unmake_move();
+        assert_eq!(state.checks_given, [2, 0]);
+        assert_eq!(state.forced_termination(), None);
+    }
+
+    #[test]
+    fn king_of_the_hill() {
+        let mut state =
+            State::from_fen_with_variant("4k3/8/8/8/8/4K3/8/8 w - - 0 1", Variant::KingOfTheHill)
+                .unwrap();
+        // not a draw, as either king can still make it to the centre
+        assert_eq!(state.forced_termination(), None);
+        play(&mut state, &["e3d4"]);
+        assert_eq!(
+            state.forced_termination().unwrap().result(),
+            crate::termination::GameResult::WhiteWins
+        );
+    }
+
+    #[test]
+    fn antichess() {
+        // the king can be taken, and has to be
+        let state =
+            State::from_fen_with_variant("8/8/8/8/8/2k5/1K6/8 w - - 0 1", Variant::Antichess)
+                .unwrap();
+        let moves = state.get_all_moves_for_colour(state.turn);
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].to_string(), "b2c3");
+
+        // pawns may promote to kings
+        let state =
+            State::from_fen_with_variant("8/4P3/8/8/8/8/8/r7 w - - 0 1", Variant::Antichess)
+                .unwrap();
+        let moves = state.get_all_moves_for_colour(state.turn);
+        assert!(moves.contains(&ChessMove::new(
+            (4, 6),
+            (4, 7),
+            MoveKind::Promotion {
+                promote_to: King,
+                capture: false
+            }
+        )));
+
+        // losing everything wins, and so does being stuck
+        let mut state =
+            State::from_fen_with_variant("8/8/8/8/8/8/1p6/2R5 b - - 0 1", Variant::Antichess)
+                .unwrap();
+        play(&mut state, &["b2c1q"]);
+        assert_eq!(
+            state.forced_termination(),
+            Some(Termination::VariantEnd {
+                winner: Some(ChessColour::White),
+                reason: "lost all pieces"
+            })
+        );
+        let stuck = State::from_fen_with_variant("8/8/8/8/8/p7/P7/8 w - - 0 1", Variant::Antichess)
+            .unwrap();
+        assert_eq!(
+            stuck.forced_termination().unwrap().result(),
+            crate::termination::GameResult::WhiteWins
+        );
+    }
+
+    #[test]
+    fn crazyhouse() {
+        let mut state = State::new_variant(Variant::Crazyhouse);
+        play(
+            &mut state,
+            &["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5e5"],
+        );
+        assert_eq!(state.pockets, [[1, 0, 0, 0, 0], [1, 0, 0, 0, 0]]);
+        assert_eq!(
+            state.to_fen(),
+            "rnb1kbnr/ppp1pppp/8/4q3/8/2N5/PPPP1PPP/R1BQKBNR[Pp] w KQkq - 2 4"
+        );
+
+        // a dropped pawn blocks the check
+        let block = state.parse_uci_move("P@e2").unwrap();
+        assert_eq!(block.to_string(), "P@e2");
+        assert_eq!(state.move_to_san(block), "P@e2");
+        assert!(state.parse_uci_move("P@e8").is_none());
+        state.make_move(block);
+        assert_eq!(state.pockets[0], [0; 5]);
+        state.unmake_move();
+        assert_eq!(state.pockets[0], [1, 0, 0, 0, 0]);
+
+        // a promoted piece goes back to being a pawn once it's captured
+        let mut state =
+            State::from_fen_with_variant("r3k3/1P6/8/8/8/8/8/4K3[] w q - 0 1", Variant::Crazyhouse)
+                .unwrap();
+        play(&mut state, &["b7b8q"]);
+        assert_eq!(state.to_fen(), "rQ~2k3/8/8/8/8/8/8/4K3[] b q - 0 1");
+        let from_fen = State::from_fen_with_variant(&state.to_fen(), Variant::Crazyhouse).unwrap();
+        assert_eq!(from_fen.promoted, state.promoted);
+        play(&mut state, &["a8b8"]);
+        assert_eq!(state.pockets[1], [1, 0, 0, 0, 0]);
+        assert_eq!(state.promoted, 0);
+        assert_eq!(state.hash, state.compute_hash());
+
+        assert!(State::from_fen_with_variant(
+            "4k3/8/8/8/8/8/8/4K3[Kq] w - - 0 1",
+            Variant::Crazyhouse
+        )
+        .is_err());
+        // only a piece can be marked as promoted, and only once
+        for placement in ["r1~2k3", "~r3k3", "rQ~~2k3"] {
+            assert_eq!(
+                State::from_fen_with_variant(
+                    &format!("{placement}/8/8/8/8/8/8/4K3[] b q - 0 1"),
+                    Variant::Crazyhouse
+                )
+                .err(),
+                Some(FenError::InvalidPiece('~')),
+                "{placement}"
+            );
+        }
+    }
+
+    #[test]
+    fn atomic() {
+        // the knight blows up itself, the pawn, and the king next to them
+        let mut state =
+            State::from_fen_with_variant("4k3/5p2/8/6N1/8/8/8/4K3 w - - 0 1", Variant::Atomic)
+                .unwrap();
+        play(&mut state, &["g5f7"]);
+        assert_eq!(state.to_fen(), "8/8/8/8/8/8/8/4K3 b - - 0 1");
+        assert_eq!(
+            state.forced_termination(),
+            Some(Termination::VariantEnd {
+                winner: Some(ChessColour::White),
+                reason: "king exploded"
+            })
+        );
+        state.unmake_move();
+        assert_eq!(state.to_fen(), "4k3/5p2/8/6N1/8/8/8/4K3 w - - 0 1");
+
+        // pawns next to the blast survive it
+        let mut state =
+            State::from_fen_with_variant("4k3/8/8/2pnP3/3Q4/8/8/4K3 w - - 0 1", Variant::Atomic)
+                .unwrap();
+        play(&mut state, &["d4d5"]);
+        assert_eq!(state.to_fen(), "4k3/8/8/2p1P3/8/8/8/4K3 b - - 0 1");
+
+        // kings can't capture, and kings that touch can't be in check
+        let state =
+            State::from_fen_with_variant("4k3/8/8/8/8/8/3p4/4K3 w - - 0 1", Variant::Atomic)
+                .unwrap();
+        assert!(state.is_in_check(ChessColour::White));
+        assert!(state.parse_uci_move("e1d2").is_none());
+        let touching =
+            State::from_fen_with_variant("8/8/8/8/8/8/3k4/r3K3 w - - 0 1", Variant::Atomic)
+                .unwrap();
+        assert!(!touching.is_in_check(ChessColour::White));
+    }
+
+    #[test]
+    fn horde() {
+        // pawns on the first rank may push two squares, and can be taken en passant
+        let mut state =
+            State::from_fen_with_variant("4k3/8/8/8/8/8/1p6/P7 w - - 0 1", Variant::Horde).unwrap();
+        assert!(!state.is_in_check(ChessColour::White));
+        play(&mut state, &["a1a3"]);
+        assert_eq!(state.to_fen(), "4k3/8/8/8/8/P7/1p6/8 b - a2 0 1");
+        assert_eq!(
+            State::from_fen_with_variant(&state.to_fen(), Variant::Horde)
+                .unwrap()
+                .en_passant,
+            Some((0, 1))
+        );
+        assert!(
+            State::from_fen_with_variant("4k3/8/8/8/8/8/8/P3K3 w - - 0 1", Variant::Horde).is_err()
+        );
+
+        // black wins by taking every last piece
+        let mut state =
+            State::from_fen_with_variant("4k3/8/8/8/8/8/8/P6r b - - 0 1", Variant::Horde).unwrap();
+        play(&mut state, &["h1a1"]);
+        assert_eq!(
+            state.forced_termination(),
+            Some(Termination::VariantEnd {
+                winner: Some(ChessColour::Black),
+                reason: "captured the horde"
+            })
+        );
+    }
+
+    #[test]
+    fn racing_kings() {
+        // giving check is as illegal as walking into one
+        let state =
+            State::from_fen_with_variant("8/8/8/8/8/6k1/8/R3K3 w - - 0 1", Variant::RacingKings)
+                .unwrap();
+        assert!(state.parse_uci_move("a1g1").is_none());
+        assert!(state.parse_uci_move("a1a3").is_none());
+        assert!(state.parse_uci_move("a1a2").is_some());
+
+        // black gets one more move after white reaches the last rank, and draws by getting there
+        let mut state =
+            State::from_fen_with_variant("8/1k5K/8/8/8/8/8/8 w - - 0 1", Variant::RacingKings)
+                .unwrap();
+        play(&mut state, &["h7h8"]);
+        assert_eq!(state.forced_termination(), None);
+        play(&mut state, &["b7b8"]);
+        assert_eq!(
+            state.forced_termination().unwrap().result(),
+            crate::termination::GameResult::Draw
+        );
+
+        // but if black can't, white wins straight away
+        let mut state =
+            State::from_fen_with_variant("8/7K/1k6/8/8/8/8/8 w - - 0 1", Variant::RacingKings)
+                .unwrap();
+        play(&mut state, &["h7h8"]);
+        assert_eq!(
+            state.forced_termination(),
+            Some(Termination::VariantEnd {
+                winner: Some(ChessColour::White),
+                reason: "king reached the last rank"
+            })
+        );
+    }
+
+    #[test]
+    fn perft() {
+        let mut state = State::new_variant(Variant::Antichess);
+        assert_eq!(state.perft(3), 8067);
+        let mut state = State::new_variant(Variant::ThreeCheck);
+        assert_eq!(state.perft(3), 8902);
+
+        // from Fairy-Stockfish's test suite
+        for (fen, depth, nodes) in [
+            ("2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1", 2, 75353),
+            (
+                "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1",
+                3,
+                58057,
+            ),
+        ] {
+            let mut state = State::from_fen_with_variant(fen, Variant::Crazyhouse).unwrap();
+            assert_eq!(state.perft(depth), nodes, "{fen}");
+        }
+
+        // from python-chess's test suite
+        for (fen, depth, nodes) in [
+            (crate::fen::STARTING_FEN, 4, 197326),
+            (
+                "rn1qkb1r/p5pp/2p5/3p4/N3P3/5P2/PPP4P/R1BQK3 w Qkq - 0 1",
+                3,
+                23353,
+            ),
+        ] {
+            let mut state = State::from_fen_with_variant(fen, Variant::Atomic).unwrap();
+            assert_eq!(state.perft(depth), nodes, "{fen}");
+        }
+        let mut state = State::new_variant(Variant::Horde);
+        assert_eq!(state.perft(4), 23310);
+        let mut state = State::new_variant(Variant::RacingKings);
+        assert_eq!(state.perft(3), 11264);
+    }
+}
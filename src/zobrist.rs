@@ -0,0 +1,241 @@
+use crate::{
+    bitboard::*,
+    piece::{PieceKind::*, *},
+    state::{CastlingRights, State},
+};
+
+// random numbers xor-ed together to form a position's hash. generated at compile time from
+// a fixed seed, so hashes stay the same between runs and can be stored
+pub struct ZobristKeys {
+    // indexed by ChessColour::index, PieceKind::index and square index
+    pub pieces: [[[u64; 64]; 6]; 2],
+    pub black_to_move: u64,
+    // white short, white long, black short, black long
+    pub castling: [u64; 4],
+    pub en_passant_file: [u64; 8],
+    // for three-check, indexed by ChessColour::index and how many checks that side has given.
+    // no checks is 0, so that other variants hash the same as before
+    pub checks_given: [[u64; 4]; 2],
+    // for crazyhouse, indexed by ChessColour::index, PieceKind::index and how many of that
+    // piece are in the pocket, which again hashes to 0 when there are none
+    pub pockets: [[[u64; POCKET_KEYS]; 5]; 2],
+}
+
+// a pocket can't hold more than 16 of anything in a real game
+const POCKET_KEYS: usize = 17;
+
+// splitmix64, see https://prng.di.unimi.it/splitmix64.c
+const fn next_random(seed: &mut u64) -> u64 {
+    *seed = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *seed;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+pub const ZOBRIST: ZobristKeys = {
+    let mut seed = 0x5C4A_A4C5_u64;
+    let mut pieces = [[[0; 64]; 6]; 2];
+    let mut colour = 0;
+    while colour < 2 {
+        let mut kind = 0;
+        while kind < 6 {
+            let mut square = 0;
+            while square < 64 {
+                pieces[colour][kind][square] = next_random(&mut seed);
+                square += 1;
+            }
+            kind += 1;
+        }
+        colour += 1;
+    }
+
+    let black_to_move = next_random(&mut seed);
+
+    let mut castling = [0; 4];
+    let mut i = 0;
+    while i < 4 {
+        castling[i] = next_random(&mut seed);
+        i += 1;
+    }
+
+    let mut en_passant_file = [0; 8];
+    let mut i = 0;
+    while i < 8 {
+        en_passant_file[i] = next_random(&mut seed);
+        i += 1;
+    }
+
+    let mut checks_given = [[0; 4]; 2];
+    let mut colour = 0;
+    while colour < 2 {
+        let mut i = 1;
+        while i < 4 {
+            checks_given[colour][i] = next_random(&mut seed);
+            i += 1;
+        }
+        colour += 1;
+    }
+
+    let mut pockets = [[[0; POCKET_KEYS]; 5]; 2];
+    let mut colour = 0;
+    while colour < 2 {
+        let mut kind = 0;
+        while kind < 5 {
+            let mut i = 1;
+            while i < POCKET_KEYS {
+                pockets[colour][kind][i] = next_random(&mut seed);
+                i += 1;
+            }
+            kind += 1;
+        }
+        colour += 1;
+    }
+
+    ZobristKeys {
+        pieces,
+        black_to_move,
+        castling,
+        en_passant_file,
+        checks_given,
+        pockets,
+    }
+};
+
+impl ZobristKeys {
+    pub fn piece(&self, piece: Piece, coord: (u8, u8)) -> u64 {
+        self.pieces[piece.colour.index()][piece.kind.index()][square_index(coord)]
+    }
+
+    pub fn pocket(&self, col: ChessColour, kind: PieceKind, count: u8) -> u64 {
+        self.pockets[col.index()][kind.index()][(count as usize).min(POCKET_KEYS - 1)]
+    }
+
+    pub fn castling_rights(&self, rights: CastlingRights) -> u64 {
+        [
+            rights.white_short,
+            rights.white_long,
+            rights.black_short,
+            rights.black_long,
+        ]
+        .iter()
+        .zip(self.castling)
+        .filter(|(present, _)| **present)
+        .fold(0, |acc, (_, key)| acc ^ key)
+    }
+}
+
+impl State {
+    // the en passant file only counts towards the hash when a pawn can actually capture there,
+    // so that positions which only differ in an unusable en passant square are the same
+    pub fn en_passant_key(&self) -> u64 {
+        match self.en_passant {
+            Some(target)
+                if PAWN_ATTACKS[self.turn.flip().index()][square_index(target)]
+                    & self.pieces[self.turn.index()][Pawn.index()]
+                    != 0 =>
+            {
+                ZOBRIST.en_passant_file[target.0 as usize]
+            }
+            _ => 0,
+        }
+    }
+
+    // the hash of the position from scratch; make_move keeps State::hash equal to this incrementally
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = self
+            .squares
+            .iter()
+            .filter_map(|s| s.content.map(|p| ZOBRIST.piece(p, s.coords)))
+            .fold(0, |acc, key| acc ^ key);
+
+        if self.turn == ChessColour::Black {
+            hash ^= ZOBRIST.black_to_move;
+        }
+
+        for (keys, checks) in ZOBRIST.checks_given.iter().zip(self.checks_given) {
+            hash ^= keys[checks as usize];
+        }
+        for col in [ChessColour::White, ChessColour::Black] {
+            for kind in [Pawn, Rook, Knight, Bishop, Queen] {
+                hash ^= ZOBRIST.pocket(col, kind, self.pockets[col.index()][kind.index()]);
+            }
+        }
+
+        hash ^ ZOBRIST.castling_rights(self.castling_rights) ^ self.en_passant_key()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fen::STARTING_FEN;
+    use crate::state::State;
+    use crate::variant::Variant;
+
+    // plays every line up to the given depth, checking the incremental hash along the way
+    fn walk(state: &mut State, depth: u32) {
+        assert_eq!(state.hash, state.compute_hash(), "{}", state.to_fen());
+        if depth == 0 {
+            return;
+        }
+        for m in state.get_all_moves_for_colour(state.turn) {
+            let before = state.hash;
+            state.make_move(m);
+            walk(state, depth - 1);
+            state.unmake_move();
+            assert_eq!(state.hash, before);
+        }
+    }
+
+    #[test]
+    fn incremental_matches_full() {
+        for fen in [
+            STARTING_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ] {
+            walk(&mut State::from_fen(fen).unwrap(), 3);
+        }
+
+        // pockets, and a promoted piece that goes back into one as a pawn
+        let crazyhouse = "r3k3/1P6/8/8/8/8/8/4K3[Nq] w q - 0 1";
+        walk(
+            &mut State::from_fen_with_variant(crazyhouse, Variant::Crazyhouse).unwrap(),
+            3,
+        );
+        // explosions, some of which take castling rooks with them
+        let atomic = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        walk(
+            &mut State::from_fen_with_variant(atomic, Variant::Atomic).unwrap(),
+            3,
+        );
+    }
+
+    #[test]
+    fn transpositions_share_a_hash() {
+        let play = |moves: &[&str]| {
+            let mut state = State::new();
+            for text in moves {
+                let m = state
+                    .get_all_moves_for_colour(state.turn)
+                    .into_iter()
+                    .find(|m| m.to_string() == *text)
+                    .unwrap();
+                state.make_move(m);
+            }
+            state
+        };
+
+        let a = play(&["g1f3", "g8f6", "b1c3"]);
+        let b = play(&["b1c3", "g8f6", "g1f3"]);
+        assert_eq!(a.hash, b.hash);
+        assert_ne!(a.hash, play(&["g1f3", "g8f6"]).hash);
+
+        // the en passant square after e2e4 can't be used by black, so it doesn't count
+        let double_push = play(&["e2e4"]);
+        let fen =
+            State::from_fen("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1").unwrap();
+        assert_eq!(double_push.hash, fen.hash);
+    }
+}